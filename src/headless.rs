@@ -0,0 +1,62 @@
+use egui_snarl::Snarl;
+use crate::nodes::executor::{self, NodeStatus};
+use crate::panes::pipeline_editor::Node;
+
+/// Exit codes returned by [`run_pipeline`].
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_NODE_FAILED: i32 = 1;
+pub const EXIT_INVALID: i32 = 2;
+
+/// Pulls the `snarl` of the first `PipelinePane` out of a saved pane layout.
+pub fn load_pipeline(path: &str) -> Result<Snarl<Box<dyn Node>>, String> {
+    let json = std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let panes: Vec<serde_json::Value> = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+
+    let snarl = panes.into_iter()
+        .filter_map(|mut pane| pane.get_mut("pane").map(serde_json::Value::take))
+        .find(|pane| pane.get("type").and_then(serde_json::Value::as_str) == Some("PipelinePane"))
+        .and_then(|mut pane| pane.get_mut("snarl").map(serde_json::Value::take))
+        .filter(|snarl| !snarl.is_null())
+        .ok_or_else(|| format!("No pipeline found in {}", path))?;
+
+    serde_json::from_value(snarl).map_err(|e| format!("Failed to load pipeline: {}", e))
+}
+
+/// Runs a saved pipeline without a window, printing progress to stdout.
+/// Returns the process exit code.
+pub fn run_pipeline(path: &str) -> i32 {
+    let mut snarl = match load_pipeline(path) {
+        Ok(snarl) => snarl,
+        Err(e) => {
+            eprintln!("{}", e);
+            return EXIT_INVALID;
+        }
+    };
+
+    let result = executor::execute_with_progress(&mut snarl, |index, total, name, status| {
+        match status {
+            NodeStatus::Done(values) => {
+                let values: Vec<String> = values.iter().map(ToString::to_string).collect();
+                println!("[{}/{}] {}: ok [{}]", index + 1, total, name, values.join(", "));
+            }
+            NodeStatus::Failed(e) => println!("[{}/{}] {}: failed: {}", index + 1, total, name, e),
+            NodeStatus::Skipped(e) => println!("[{}/{}] {}: skipped: {}", index + 1, total, name, e),
+        }
+    });
+
+    match result {
+        Ok(report) if report.failed() == 0 => {
+            println!("Pipeline finished: {} nodes", report.order.len());
+            EXIT_OK
+        }
+        Ok(report) => {
+            eprintln!("Pipeline finished with {} of {} nodes failed", report.failed(), report.order.len());
+            EXIT_NODE_FAILED
+        }
+        Err(e) => {
+            eprintln!("Pipeline is invalid:\n{}", e);
+            EXIT_INVALID
+        }
+    }
+}
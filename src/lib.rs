@@ -1,8 +1,11 @@
 #![warn(clippy::all, rust_2018_idioms)]
 mod app;
+mod headless;
 mod pane_manager;
 mod panes;
 mod nodes;
+mod point_cloud;
 
 pub use app::App;
+pub use headless::run_pipeline;
 pub use pane_manager::PaneManager;
\ No newline at end of file
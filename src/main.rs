@@ -9,6 +9,15 @@ use rushroom::App;
 fn main() -> eframe::Result {
     // env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
+    // `rushroom run [layout.json]` runs the saved pipeline without opening a window
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("run") {
+        #[cfg(windows)]
+        attach_parent_console();
+        let path = args.get(2).map(String::as_str).unwrap_or("pane_layout.json");
+        std::process::exit(rushroom::run_pipeline(path));
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([400.0, 300.0])
@@ -30,6 +39,23 @@ fn main() -> eframe::Result {
     )
 }
 
+/// Release builds on Windows use the GUI subsystem and start without a console, so
+/// `rushroom run` borrows the one it was started from for its output. Redirected
+/// output keeps working, since the standard handles are only filled in where missing.
+/// Callers that need the exit code should wait for the process, e.g. `start /wait`.
+#[cfg(windows)]
+fn attach_parent_console() {
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    // Fails harmlessly when there is no parent console or one is already attached
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
 // // When compiling to web using trunk:
 // #[cfg(target_arch = "wasm32")]
 // fn main() {
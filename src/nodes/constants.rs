@@ -1,8 +1,7 @@
-use eframe::epaint::Color32;
 use egui::{Id, Ui};
 use egui_snarl::{InPin, OutPin};
 use egui_snarl::ui::{PinInfo, WireStyle};
-use crate::pane_manager::{Pane, PaneMode, PaneState, PsudoCreationContext};
+use crate::nodes::value::{PinType, Value};
 use crate::panes::pipeline_editor::Node;
 
 
@@ -10,15 +9,20 @@ use crate::panes::pipeline_editor::Node;
 pub struct Constants {
     vars: Vec<(String, String)>,
     popup_open: bool,
+    #[serde(skip, default = "random_id")]
     uid: Id,
 }
+
+fn random_id() -> Id {
+    Id::new(rand::random::<u64>())
+}
 #[typetag::serde]
 impl Node for Constants {
     fn new() -> Self {
         let mut s = Self {
             vars: Vec::new(),
             popup_open: false,
-            uid: Id::new(rand::random::<u64>()),
+            uid: random_id(),
         };
         s.vars.push(("VAR".to_string(), "Change Me".to_string()));
         s
@@ -43,14 +47,17 @@ impl Node for Constants {
         PinInfo::square()
     }
     fn show_output(&mut self, pin: &OutPin, ui: &mut Ui, _scale: f32) -> PinInfo {
-        ui.label(self.vars.iter().nth(pin.id.output).unwrap().0.clone());
-        PinInfo::square().with_fill(Color32::RED).with_wire_style(WireStyle::Bezier3)
+        ui.label(self.vars[pin.id.output].0.clone());
+        PinInfo::square().with_wire_style(WireStyle::Bezier3)
     }
-    fn can_rx(&self, _other: &Box<dyn Node>) -> bool {
-        true
+    fn input_type(&self, _input: usize) -> PinType {
+        PinType::Any
     }
-    fn can_tx(&self, _other: &Box<dyn Node>) -> bool {
-        true
+    fn output_type(&self, _output: usize) -> PinType {
+        PinType::Text
+    }
+    fn input_required(&self, _input: usize) -> bool {
+        false
     }
     fn context_menu(&mut self, ui: &mut Ui) {
         // egui::Window::new("TEST").show(ui.ctx(), |ui| {
@@ -67,21 +74,22 @@ impl Node for Constants {
                 egui::Grid::new("my_grid").striped(true)
                     .max_col_width(9999.)
                     .show(ui, |ui| {
-                    for (i, (var1, var2)) in &mut self.vars.iter().enumerate() {
-                        if var1.is_empty() {
-                            self.vars.remove()
-                        }
+                    for (var1, var2) in self.vars.iter_mut() {
                         ui.add(egui::TextEdit::singleline(var1));
                         ui.add(egui::TextEdit::singleline(var2));
                         ui.end_row();
                     }
                 });
+                self.vars.retain(|(var1, _)| !var1.is_empty());
                 if ui.button("ADD").clicked() {
                     self.vars.push(("VAR".to_string(), "Change Me".to_string()));
                 }
             });
         }
     }
+    fn evaluate(&mut self, _inputs: &[Option<Value>]) -> Result<Vec<Value>, String> {
+        Ok(self.vars.iter().map(|(_, value)| Value::Text(value.clone())).collect())
+    }
 }
 
 // #[derive(serde::Serialize, serde::Deserialize, Clone)]
@@ -0,0 +1,239 @@
+use std::collections::{HashMap, VecDeque};
+use egui_snarl::{NodeId, Snarl};
+use crate::nodes::validation;
+use crate::nodes::value::Value;
+use crate::panes::pipeline_editor::Node;
+
+/// Outcome of a single node after the pipeline has been run.
+#[derive(Clone, Debug)]
+pub enum NodeStatus {
+    Done(Vec<Value>),
+    Failed(String),
+    /// The node was not evaluated because something upstream failed.
+    Skipped(String),
+}
+
+impl NodeStatus {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, NodeStatus::Done(_))
+    }
+}
+
+#[derive(Default)]
+pub struct ExecutionReport {
+    pub order: Vec<NodeId>,
+    pub results: HashMap<NodeId, NodeStatus>,
+}
+
+impl ExecutionReport {
+    pub fn get(&self, node: NodeId) -> Option<&NodeStatus> {
+        self.results.get(&node)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.values().filter(|s| !s.is_ok()).count()
+    }
+}
+
+/// Orders the nodes so every node comes after all nodes feeding its inputs (Kahn's algorithm).
+/// Returns the nodes that could not be ordered if the graph contains a cycle.
+pub fn schedule(snarl: &Snarl<Box<dyn Node>>) -> Result<Vec<NodeId>, Vec<NodeId>> {
+    let mut in_degree: HashMap<NodeId, usize> = snarl.node_ids().map(|(id, _)| (id, 0)).collect();
+    let mut downstream: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+
+    for (out_pin, in_pin) in snarl.wires() {
+        *in_degree.entry(in_pin.node).or_insert(0) += 1;
+        downstream.entry(out_pin.node).or_default().push(in_pin.node);
+    }
+
+    // Start from the lowest ids so runs are deterministic
+    let mut ready: Vec<NodeId> = in_degree.iter().filter(|(_, &d)| d == 0).map(|(&id, _)| id).collect();
+    ready.sort();
+    let mut queue: VecDeque<NodeId> = ready.into();
+
+    let mut order = Vec::with_capacity(in_degree.len());
+    while let Some(node) = queue.pop_front() {
+        order.push(node);
+        if let Some(targets) = downstream.get(&node) {
+            for target in targets {
+                let degree = in_degree.get_mut(target).expect("Wire to unknown node");
+                *degree -= 1;
+                if *degree == 0 {
+                    queue.push_back(*target);
+                }
+            }
+        }
+    }
+
+    if order.len() == in_degree.len() {
+        Ok(order)
+    } else {
+        let mut stuck: Vec<NodeId> = in_degree.into_iter().filter(|(_, d)| *d > 0).map(|(id, _)| id).collect();
+        stuck.sort();
+        Err(stuck)
+    }
+}
+
+/// Evaluates every node in dependency order, passing output values along the wires.
+/// Refuses to start if validation finds any errors.
+pub fn execute(snarl: &mut Snarl<Box<dyn Node>>) -> Result<ExecutionReport, String> {
+    execute_with_progress(snarl, |_, _, _, _| {})
+}
+
+/// Like [`execute`], calling `progress(index, total, name, status)` after each node finishes.
+pub fn execute_with_progress(
+    snarl: &mut Snarl<Box<dyn Node>>,
+    mut progress: impl FnMut(usize, usize, &str, &NodeStatus),
+) -> Result<ExecutionReport, String> {
+    let validation = validation::validate(snarl);
+    if !validation.is_ok() {
+        return Err(validation.summary(snarl));
+    }
+
+    let order = schedule(snarl).map_err(|stuck| {
+        let names: Vec<&str> = stuck.iter()
+            .filter_map(|id| snarl.get_node(*id))
+            .map(|node| node.get_name())
+            .collect();
+        format!("Pipeline contains a cycle through: {}", names.join(", "))
+    })?;
+
+    let wires: Vec<_> = snarl.wires().collect();
+    let mut report = ExecutionReport {
+        order: order.clone(),
+        results: HashMap::new(),
+    };
+
+    let total = order.len();
+    for (index, id) in order.into_iter().enumerate() {
+        let node = snarl.get_node_mut(id).expect("Scheduled node missing");
+        let mut inputs: Vec<Option<Value>> = vec![None; node.inputs()];
+        let mut blocked: Option<String> = None;
+
+        for (out_pin, in_pin) in wires.iter().filter(|(_, in_pin)| in_pin.node == id) {
+            match report.results.get(&out_pin.node) {
+                Some(NodeStatus::Done(values)) => {
+                    let value = values.get(out_pin.output).cloned();
+                    match &value {
+                        Some(value) => {
+                            let expected = node.input_type(in_pin.input);
+                            if !expected.accepts(value.pin_type()) {
+                                blocked = Some(format!(
+                                    "Input {} expects {} but received {}",
+                                    in_pin.input, expected.name(), value.pin_type().name()
+                                ));
+                            }
+                        }
+                        None => blocked = Some(format!(
+                            "Input {} is wired to output {} which no longer exists", in_pin.input, out_pin.output
+                        )),
+                    }
+                    if let Some(slot) = inputs.get_mut(in_pin.input) {
+                        *slot = value;
+                    }
+                }
+                _ => blocked = Some(format!("Input {} has no value because an upstream node failed", in_pin.input)),
+            }
+        }
+
+        let status = if let Some(reason) = blocked {
+            NodeStatus::Skipped(reason)
+        } else {
+            match node.evaluate(&inputs) {
+                Ok(values) if values.len() == node.outputs() => NodeStatus::Done(values),
+                Ok(values) => NodeStatus::Failed(format!(
+                    "Produced {} values for {} outputs", values.len(), node.outputs()
+                )),
+                Err(e) => NodeStatus::Failed(e),
+            }
+        };
+        progress(index, total, node.get_name(), &status);
+        report.results.insert(id, status);
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use egui_snarl::Snarl;
+    use crate::nodes::testing::{add, wire, TestNode};
+    use crate::nodes::value::{PinType, Value};
+    use super::*;
+
+    #[test]
+    fn schedule_puts_inputs_first() {
+        let mut snarl = Snarl::new();
+        let sink = add(&mut snarl, TestNode::sink("sink", &[PinType::Scalar, PinType::Scalar]));
+        let middle = add(&mut snarl, TestNode::sink("middle", &[PinType::Scalar]));
+        let source = add(&mut snarl, TestNode::source("source", PinType::Scalar));
+        wire(&mut snarl, source, middle, 0);
+        wire(&mut snarl, middle, sink, 0);
+        wire(&mut snarl, source, sink, 1);
+
+        assert_eq!(schedule(&snarl), Ok(vec![source, middle, sink]));
+    }
+
+    #[test]
+    fn schedule_reports_nodes_stuck_in_a_cycle() {
+        let mut snarl = Snarl::new();
+        // Schedulable, so not reported
+        add(&mut snarl, TestNode::source("source", PinType::Scalar));
+        let a = add(&mut snarl, TestNode::sink("a", &[PinType::Scalar]));
+        let b = add(&mut snarl, TestNode::sink("b", &[PinType::Scalar]));
+        let after = add(&mut snarl, TestNode::sink("after", &[PinType::Scalar]));
+        wire(&mut snarl, a, b, 0);
+        wire(&mut snarl, b, a, 0);
+        wire(&mut snarl, b, after, 0);
+
+        assert_eq!(schedule(&snarl), Err(vec![a, b, after]));
+    }
+
+    #[test]
+    fn execute_passes_values_along_wires() {
+        let mut snarl = Snarl::new();
+        let source = add(&mut snarl, TestNode::source("source", PinType::Text));
+        let sink = add(&mut snarl, TestNode::sink("sink", &[PinType::Text]));
+        wire(&mut snarl, source, sink, 0);
+
+        let report = execute(&mut snarl).unwrap();
+        assert_eq!(report.order, vec![source, sink]);
+        assert!(matches!(report.get(source), Some(NodeStatus::Done(v)) if v == &[Value::Text("source".to_string())]));
+        assert!(report.get(sink).is_some_and(NodeStatus::is_ok));
+        assert_eq!(report.failed(), 0);
+    }
+
+    #[test]
+    fn execute_skips_a_node_given_the_wrong_type() {
+        let mut snarl = Snarl::new();
+        // Declared as any, so the wire is allowed, but it carries text
+        let mut source = TestNode::source("source", PinType::Text);
+        source.output_type = PinType::Any;
+        let source = add(&mut snarl, source);
+        let sink = add(&mut snarl, TestNode::sink("sink", &[PinType::Scalar]));
+        let after = add(&mut snarl, TestNode::sink("after", &[PinType::Scalar]));
+        wire(&mut snarl, source, sink, 0);
+        wire(&mut snarl, sink, after, 0);
+
+        let report = execute(&mut snarl).unwrap();
+        assert!(report.get(source).is_some_and(NodeStatus::is_ok));
+        match report.get(sink) {
+            Some(NodeStatus::Skipped(reason)) => assert_eq!(reason, "Input 0 expects scalar but received string"),
+            other => panic!("expected the sink to be skipped, got {:?}", other),
+        }
+        // Nothing downstream of a skipped node runs either
+        assert!(matches!(report.get(after), Some(NodeStatus::Skipped(_))));
+        assert_eq!(report.failed(), 2);
+    }
+
+    #[test]
+    fn execute_refuses_an_invalid_graph() {
+        let mut snarl = Snarl::new();
+        let a = add(&mut snarl, TestNode::sink("a", &[PinType::Scalar]));
+        let b = add(&mut snarl, TestNode::sink("b", &[PinType::Scalar]));
+        wire(&mut snarl, a, b, 0);
+        wire(&mut snarl, b, a, 0);
+
+        assert_eq!(execute(&mut snarl).err().as_deref(), Some("Cycle through a -> b"));
+    }
+}
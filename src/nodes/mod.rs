@@ -1 +1,7 @@
-pub mod constants;
\ No newline at end of file
+pub mod constants;
+pub mod executor;
+pub mod point_cloud;
+pub mod value;
+pub mod validation;
+#[cfg(test)]
+pub mod testing;
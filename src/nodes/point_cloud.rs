@@ -0,0 +1,210 @@
+use std::sync::Arc;
+use egui::Ui;
+use egui_snarl::{InPin, OutPin};
+use egui_snarl::ui::{PinInfo, WireStyle};
+use crate::nodes::value::{PinType, Value};
+use crate::panes::pipeline_editor::Node;
+use crate::point_cloud::{self, Format, LoadOptions};
+use crate::point_cloud::ply::{self, PlyFormat};
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct LoadPointCloud {
+    path: String,
+    #[serde(default)]
+    options: LoadOptions,
+}
+#[typetag::serde]
+impl Node for LoadPointCloud {
+    fn new() -> Self {
+        Self {
+            path: "./".to_string(),
+            options: LoadOptions::default(),
+        }
+    }
+
+    fn get_name(&self) -> &str {
+        "Load Point Cloud"
+    }
+    fn get_description(&self) -> &str {"Reads a PLY, PCD, OBJ, LAS or XYZ/CSV file, keeping every point attribute"}
+
+    fn duplicate(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn inputs(&self) -> usize {
+        0
+    }
+    fn outputs(&self) -> usize {
+        1
+    }
+    fn show_input(&mut self, _pin: &InPin, _ui: &mut Ui, _scale: f32) -> PinInfo {
+        PinInfo::square()
+    }
+    fn show_output(&mut self, _pin: &OutPin, ui: &mut Ui, _scale: f32) -> PinInfo {
+        ui.label("Cloud");
+        PinInfo::circle().with_wire_style(WireStyle::Bezier3)
+    }
+    fn input_type(&self, _input: usize) -> PinType {
+        PinType::Any
+    }
+    fn output_type(&self, _output: usize) -> PinType {
+        PinType::PointCloud
+    }
+    fn input_required(&self, _input: usize) -> bool {
+        false
+    }
+    fn context_menu(&mut self, _ui: &mut Ui) {}
+    fn update(&mut self, ui: &mut Ui) {
+        ui.add(egui::TextEdit::singleline(&mut self.path).hint_text("path"));
+        if let Ok(Format::Xyz) = Format::detect(&self.path) {
+            self.options.xyz.show(ui);
+        }
+    }
+    fn evaluate(&mut self, _inputs: &[Option<Value>]) -> Result<Vec<Value>, String> {
+        let cloud = point_cloud::load(&self.path, &self.options)?;
+        Ok(vec![Value::PointCloud(Arc::new(cloud))])
+    }
+}
+
+/// Min, max and mean of one per-point attribute.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct AttributeStats {
+    attribute: String,
+}
+#[typetag::serde]
+impl Node for AttributeStats {
+    fn new() -> Self {
+        Self {
+            attribute: "intensity".to_string(),
+        }
+    }
+
+    fn get_name(&self) -> &str {
+        "Attribute Stats"
+    }
+    fn get_description(&self) -> &str {"Minimum, maximum and mean of a point attribute"}
+
+    fn duplicate(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn inputs(&self) -> usize {
+        1
+    }
+    fn outputs(&self) -> usize {
+        3
+    }
+    fn show_input(&mut self, _pin: &InPin, ui: &mut Ui, _scale: f32) -> PinInfo {
+        ui.label("Cloud");
+        PinInfo::circle()
+    }
+    fn show_output(&mut self, pin: &OutPin, ui: &mut Ui, _scale: f32) -> PinInfo {
+        ui.label(["Min", "Max", "Mean"][pin.id.output]);
+        PinInfo::circle().with_wire_style(WireStyle::Bezier3)
+    }
+    fn input_type(&self, _input: usize) -> PinType {
+        PinType::PointCloud
+    }
+    fn output_type(&self, _output: usize) -> PinType {
+        PinType::Scalar
+    }
+    fn input_required(&self, _input: usize) -> bool {
+        true
+    }
+    fn context_menu(&mut self, _ui: &mut Ui) {}
+    fn update(&mut self, ui: &mut Ui) {
+        ui.add(egui::TextEdit::singleline(&mut self.attribute).hint_text("attribute"));
+    }
+    fn evaluate(&mut self, inputs: &[Option<Value>]) -> Result<Vec<Value>, String> {
+        let Some(Value::PointCloud(cloud)) = &inputs[0] else {
+            return Err("No point cloud".to_string());
+        };
+        let attribute = cloud.attribute(&self.attribute).ok_or_else(|| {
+            let names: Vec<&str> = cloud.attribute_names().collect();
+            format!("No attribute '{}' (have: {})", self.attribute, names.join(", "))
+        })?;
+        if attribute.values.is_empty() {
+            return Err("Point cloud is empty".to_string());
+        }
+
+        let (mut min, mut max, mut sum) = (f64::MAX, f64::MIN, 0.0);
+        for &v in &attribute.values {
+            let v = v as f64 + attribute.offset;
+            min = min.min(v);
+            max = max.max(v);
+            sum += v;
+        }
+        Ok(vec![
+            Value::Scalar(min),
+            Value::Scalar(max),
+            Value::Scalar(sum / attribute.values.len() as f64),
+        ])
+    }
+}
+
+/// Pipeline output: writes the incoming cloud to a PLY file.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct SavePointCloud {
+    path: String,
+    format: PlyFormat,
+}
+#[typetag::serde]
+impl Node for SavePointCloud {
+    fn new() -> Self {
+        Self {
+            path: "./out.ply".to_string(),
+            format: PlyFormat::BinaryLittleEndian,
+        }
+    }
+
+    fn get_name(&self) -> &str {
+        "Save Point Cloud"
+    }
+    fn get_description(&self) -> &str {"Writes a PLY file with colours and attributes"}
+
+    fn duplicate(&self) -> Box<dyn Node> {
+        Box::new(self.clone())
+    }
+
+    fn inputs(&self) -> usize {
+        1
+    }
+    fn outputs(&self) -> usize {
+        1
+    }
+    fn show_input(&mut self, _pin: &InPin, ui: &mut Ui, _scale: f32) -> PinInfo {
+        ui.label("Cloud");
+        PinInfo::circle()
+    }
+    fn show_output(&mut self, _pin: &OutPin, ui: &mut Ui, _scale: f32) -> PinInfo {
+        ui.label("Path");
+        PinInfo::circle().with_wire_style(WireStyle::Bezier3)
+    }
+    fn input_type(&self, _input: usize) -> PinType {
+        PinType::PointCloud
+    }
+    fn output_type(&self, _output: usize) -> PinType {
+        PinType::Path
+    }
+    fn input_required(&self, _input: usize) -> bool {
+        true
+    }
+    fn context_menu(&mut self, _ui: &mut Ui) {}
+    fn update(&mut self, ui: &mut Ui) {
+        ui.add(egui::TextEdit::singleline(&mut self.path).hint_text("path"));
+        egui::ComboBox::from_id_salt(ui.id().with("format"))
+            .selected_text(self.format.label())
+            .show_ui(ui, |ui| {
+                for format in PlyFormat::ALL {
+                    ui.selectable_value(&mut self.format, format, format.label());
+                }
+            });
+    }
+    fn evaluate(&mut self, inputs: &[Option<Value>]) -> Result<Vec<Value>, String> {
+        let Some(Value::PointCloud(cloud)) = &inputs[0] else {
+            return Err("No point cloud".to_string());
+        };
+        ply::write_ply(&self.path, cloud, self.format)?;
+        Ok(vec![Value::Path(self.path.clone().into())])
+    }
+}
@@ -0,0 +1,72 @@
+use egui::{Pos2, Ui};
+use egui_snarl::{InPin, InPinId, NodeId, OutPin, OutPinId, Snarl};
+use egui_snarl::ui::PinInfo;
+use crate::nodes::value::{PinType, Value};
+use crate::panes::pipeline_editor::Node;
+
+/// A node with declared pin types that outputs a fixed value on each pin, for building
+/// small graphs by hand in tests. Pin types are not saved; it is never serialised.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct TestNode {
+    pub name: String,
+    #[serde(skip)]
+    pub inputs: Vec<PinType>,
+    pub required: bool,
+    /// Declared type of the single output
+    #[serde(skip, default = "scalar")]
+    pub output_type: PinType,
+    /// What the output actually carries, which may differ from the declared type
+    #[serde(skip, default = "scalar")]
+    pub output: PinType,
+}
+
+fn scalar() -> PinType {
+    PinType::Scalar
+}
+
+impl TestNode {
+    pub fn source(name: &str, output: PinType) -> Self {
+        Self { name: name.to_string(), inputs: Vec::new(), required: true, output_type: output, output }
+    }
+
+    pub fn sink(name: &str, inputs: &[PinType]) -> Self {
+        Self { name: name.to_string(), inputs: inputs.to_vec(), required: true, output_type: PinType::Scalar, output: PinType::Scalar }
+    }
+}
+
+#[typetag::serde]
+impl Node for TestNode {
+    fn new() -> Self {
+        Self::source("Test", PinType::Scalar)
+    }
+    fn get_name(&self) -> &str { &self.name }
+    fn get_description(&self) -> &str { "Test node" }
+    fn duplicate(&self) -> Box<dyn Node> { Box::new(self.clone()) }
+    fn inputs(&self) -> usize { self.inputs.len() }
+    fn outputs(&self) -> usize { 1 }
+    fn show_input(&mut self, _pin: &InPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square() }
+    fn show_output(&mut self, _pin: &OutPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square() }
+    fn input_type(&self, input: usize) -> PinType { self.inputs[input] }
+    fn output_type(&self, _output: usize) -> PinType { self.output_type }
+    fn input_required(&self, _input: usize) -> bool { self.required }
+    fn context_menu(&mut self, _ui: &mut Ui) {}
+    fn update(&mut self, _ui: &mut Ui) {}
+    fn evaluate(&mut self, inputs: &[Option<Value>]) -> Result<Vec<Value>, String> {
+        if inputs.iter().any(Option::is_none) && self.required {
+            return Err("Missing input".to_string());
+        }
+        Ok(vec![match self.output {
+            PinType::Text => Value::Text(self.name.clone()),
+            _ => Value::Scalar(1.0),
+        }])
+    }
+}
+
+pub fn add(snarl: &mut Snarl<Box<dyn Node>>, node: TestNode) -> NodeId {
+    snarl.insert_node(Pos2::ZERO, Box::new(node))
+}
+
+/// Wires the output of `from` into input `input` of `to`.
+pub fn wire(snarl: &mut Snarl<Box<dyn Node>>, from: NodeId, to: NodeId, input: usize) {
+    snarl.connect(OutPinId { node: from, output: 0 }, InPinId { node: to, input });
+}
@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+use egui_snarl::{InPinId, NodeId, OutPinId, Snarl};
+use crate::panes::pipeline_editor::Node;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Issue {
+    /// Nodes that feed back into each other, in id order.
+    Cycle(Vec<NodeId>),
+    /// A required input with nothing wired into it.
+    MissingInput(InPinId),
+    /// A node with no wires at all.
+    Dangling(NodeId),
+    /// A wire from an output or into an input the node no longer has, e.g. after
+    /// outputs were removed from a Constants node.
+    MissingPin(OutPinId, InPinId),
+}
+
+impl Issue {
+    /// Errors stop the pipeline from running, warnings do not.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Issue::Dangling(_))
+    }
+}
+
+#[derive(Default)]
+pub struct ValidationReport {
+    pub issues: Vec<Issue>,
+    cycle_nodes: HashSet<NodeId>,
+    cycle_wires: HashSet<(OutPinId, InPinId)>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        !self.issues.iter().any(Issue::is_error)
+    }
+
+    pub fn errors(&self) -> usize {
+        self.issues.iter().filter(|i| i.is_error()).count()
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.issues.len() - self.errors()
+    }
+
+    pub fn in_cycle(&self, node: NodeId) -> bool {
+        self.cycle_nodes.contains(&node)
+    }
+
+    pub fn wire_in_cycle(&self, from: OutPinId, to: InPinId) -> bool {
+        self.cycle_wires.contains(&(from, to))
+    }
+
+    pub fn output_in_cycle(&self, pin: OutPinId) -> bool {
+        self.cycle_wires.iter().any(|(from, _)| *from == pin)
+    }
+
+    pub fn input_missing(&self, pin: InPinId) -> bool {
+        self.issues.contains(&Issue::MissingInput(pin))
+    }
+
+    pub fn has_error(&self, node: NodeId) -> bool {
+        self.in_cycle(node) || self.issues.iter().any(|i| match i {
+            Issue::MissingInput(pin) => pin.node == node,
+            Issue::MissingPin(from, to) => from.node == node || to.node == node,
+            _ => false,
+        })
+    }
+
+    pub fn is_dangling(&self, node: NodeId) -> bool {
+        self.issues.contains(&Issue::Dangling(node))
+    }
+
+    pub fn describe(&self, issue: &Issue, snarl: &Snarl<Box<dyn Node>>) -> String {
+        let name = |id: NodeId| snarl.get_node(id).map_or("?", |n| n.get_name()).to_string();
+        match issue {
+            Issue::Cycle(nodes) => {
+                let names: Vec<String> = nodes.iter().map(|id| name(*id)).collect();
+                format!("Cycle through {}", names.join(" -> "))
+            }
+            Issue::MissingInput(pin) => format!("\"{}\" input {} is not connected", name(pin.node), pin.input),
+            Issue::Dangling(node) => format!("\"{}\" is not connected to anything", name(*node)),
+            Issue::MissingPin(from, to) => {
+                let has_output = snarl.get_node(from.node).is_some_and(|n| from.output < n.outputs());
+                let missing = if has_output { format!("input {}", to.input) } else { format!("output {}", from.output) };
+                format!(
+                    "Wire from \"{}\" output {} to \"{}\" input {}: {} no longer exists",
+                    name(from.node), from.output, name(to.node), to.input, missing,
+                )
+            }
+        }
+    }
+
+    /// One line per error, for refusing to run.
+    pub fn summary(&self, snarl: &Snarl<Box<dyn Node>>) -> String {
+        let lines: Vec<String> = self.issues.iter()
+            .filter(|i| i.is_error())
+            .map(|i| self.describe(i, snarl))
+            .collect();
+        lines.join("\n")
+    }
+}
+
+/// Checks the graph for cycles, wires to missing pins, unconnected required inputs and dangling nodes.
+pub fn validate(snarl: &Snarl<Box<dyn Node>>) -> ValidationReport {
+    let wires: Vec<(OutPinId, InPinId)> = snarl.wires().collect();
+    let mut report = ValidationReport::default();
+
+    for component in strongly_connected(snarl, &wires) {
+        let self_loop = component.len() == 1
+            && wires.iter().any(|(from, to)| from.node == component[0] && to.node == component[0]);
+        if component.len() > 1 || self_loop {
+            report.cycle_nodes.extend(component.iter().copied());
+            report.issues.push(Issue::Cycle(component));
+        }
+    }
+    for &(from, to) in &wires {
+        if report.cycle_nodes.contains(&from.node) && report.cycle_nodes.contains(&to.node) {
+            report.cycle_wires.insert((from, to));
+        }
+    }
+
+    let mut missing_pins: Vec<(OutPinId, InPinId)> = wires.iter().copied()
+        .filter(|(from, to)| {
+            let has_output = snarl.get_node(from.node).is_some_and(|n| from.output < n.outputs());
+            let has_input = snarl.get_node(to.node).is_some_and(|n| to.input < n.inputs());
+            !has_output || !has_input
+        })
+        .collect();
+    // Wires come out of the snarl in no particular order
+    missing_pins.sort_by_key(|(from, to)| (to.node, to.input, from.node, from.output));
+    report.issues.extend(missing_pins.into_iter().map(|(from, to)| Issue::MissingPin(from, to)));
+
+    let single_node = snarl.node_ids().count() == 1;
+    for (id, node) in snarl.node_ids() {
+        let mut connected = false;
+        for input in 0..node.inputs() {
+            let pin = InPinId { node: id, input };
+            let wired = wires.iter().any(|(_, to)| *to == pin);
+            connected |= wired;
+            if !wired && node.input_required(input) {
+                report.issues.push(Issue::MissingInput(pin));
+            }
+        }
+        connected |= wires.iter().any(|(from, _)| from.node == id);
+        if !connected && !single_node && node.inputs() + node.outputs() > 0 {
+            report.issues.push(Issue::Dangling(id));
+        }
+    }
+
+    report
+}
+
+/// Tarjan's algorithm; each component is returned sorted by id.
+fn strongly_connected(snarl: &Snarl<Box<dyn Node>>, wires: &[(OutPinId, InPinId)]) -> Vec<Vec<NodeId>> {
+    struct State {
+        edges: HashMap<NodeId, Vec<NodeId>>,
+        index: HashMap<NodeId, usize>,
+        low: HashMap<NodeId, usize>,
+        stack: Vec<NodeId>,
+        on_stack: HashSet<NodeId>,
+        components: Vec<Vec<NodeId>>,
+    }
+
+    fn visit(s: &mut State, node: NodeId) {
+        let index = s.index.len();
+        s.index.insert(node, index);
+        s.low.insert(node, index);
+        s.stack.push(node);
+        s.on_stack.insert(node);
+
+        for next in s.edges.get(&node).cloned().unwrap_or_default() {
+            if !s.index.contains_key(&next) {
+                visit(s, next);
+                let low = s.low[&node].min(s.low[&next]);
+                s.low.insert(node, low);
+            } else if s.on_stack.contains(&next) {
+                let low = s.low[&node].min(s.index[&next]);
+                s.low.insert(node, low);
+            }
+        }
+
+        if s.low[&node] == s.index[&node] {
+            let mut component = Vec::new();
+            while let Some(top) = s.stack.pop() {
+                s.on_stack.remove(&top);
+                component.push(top);
+                if top == node {
+                    break;
+                }
+            }
+            component.sort();
+            s.components.push(component);
+        }
+    }
+
+    let mut state = State {
+        edges: HashMap::new(),
+        index: HashMap::new(),
+        low: HashMap::new(),
+        stack: Vec::new(),
+        on_stack: HashSet::new(),
+        components: Vec::new(),
+    };
+    for (from, to) in wires {
+        state.edges.entry(from.node).or_default().push(to.node);
+    }
+
+    let mut ids: Vec<NodeId> = snarl.node_ids().map(|(id, _)| id).collect();
+    ids.sort();
+    for id in ids {
+        if !state.index.contains_key(&id) {
+            visit(&mut state, id);
+        }
+    }
+    state.components
+}
+
+#[cfg(test)]
+mod tests {
+    use egui_snarl::Snarl;
+    use crate::nodes::testing::{add, wire, TestNode};
+    use crate::nodes::value::PinType;
+    use super::*;
+
+    #[test]
+    fn finds_each_cycle_once() {
+        let mut snarl = Snarl::new();
+        let a = add(&mut snarl, TestNode::sink("a", &[PinType::Scalar]));
+        let b = add(&mut snarl, TestNode::sink("b", &[PinType::Scalar]));
+        let c = add(&mut snarl, TestNode::sink("c", &[PinType::Scalar, PinType::Scalar]));
+        let own = add(&mut snarl, TestNode::sink("own", &[PinType::Scalar]));
+        wire(&mut snarl, a, b, 0);
+        wire(&mut snarl, b, c, 0);
+        wire(&mut snarl, c, a, 0);
+        wire(&mut snarl, a, c, 1);
+        wire(&mut snarl, own, own, 0);
+
+        let report = validate(&snarl);
+        assert_eq!(report.issues, vec![Issue::Cycle(vec![a, b, c]), Issue::Cycle(vec![own])]);
+        assert!(report.in_cycle(b) && report.in_cycle(own));
+        assert!(report.wire_in_cycle(OutPinId { node: a, output: 0 }, InPinId { node: c, input: 1 }));
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn a_chain_is_not_a_cycle() {
+        let mut snarl = Snarl::new();
+        let source = add(&mut snarl, TestNode::source("source", PinType::Scalar));
+        let sink = add(&mut snarl, TestNode::sink("sink", &[PinType::Scalar]));
+        wire(&mut snarl, source, sink, 0);
+
+        let report = validate(&snarl);
+        assert!(report.issues.is_empty());
+        assert!(report.is_ok());
+    }
+
+    #[test]
+    fn reports_missing_required_inputs() {
+        let mut snarl = Snarl::new();
+        let source = add(&mut snarl, TestNode::source("source", PinType::Scalar));
+        let sink = add(&mut snarl, TestNode::sink("sink", &[PinType::Scalar, PinType::Scalar]));
+        let mut optional = TestNode::sink("optional", &[PinType::Scalar, PinType::Scalar]);
+        optional.required = false;
+        let optional = add(&mut snarl, optional);
+        wire(&mut snarl, source, sink, 0);
+        wire(&mut snarl, source, optional, 0);
+
+        let report = validate(&snarl);
+        let missing = InPinId { node: sink, input: 1 };
+        assert_eq!(report.issues, vec![Issue::MissingInput(missing)]);
+        assert!(report.input_missing(missing) && report.has_error(sink));
+        assert!(!report.has_error(optional));
+        assert_eq!(report.summary(&snarl), "\"sink\" input 1 is not connected");
+    }
+
+    #[test]
+    fn dangling_nodes_are_only_warnings() {
+        let mut snarl = Snarl::new();
+        let source = add(&mut snarl, TestNode::source("source", PinType::Scalar));
+        let sink = add(&mut snarl, TestNode::sink("sink", &[PinType::Scalar]));
+        let alone = add(&mut snarl, TestNode::source("alone", PinType::Scalar));
+        wire(&mut snarl, source, sink, 0);
+
+        let report = validate(&snarl);
+        assert_eq!(report.issues, vec![Issue::Dangling(alone)]);
+        assert!(report.is_ok());
+        assert_eq!((report.errors(), report.warnings()), (0, 1));
+    }
+
+    #[test]
+    fn reports_wires_to_pins_that_no_longer_exist() {
+        let mut snarl = Snarl::new();
+        let source = add(&mut snarl, TestNode::source("source", PinType::Scalar));
+        let sink = add(&mut snarl, TestNode::sink("sink", &[PinType::Scalar]));
+        wire(&mut snarl, source, sink, 0);
+        // Wires left behind when pins were removed
+        let gone_output = OutPinId { node: source, output: 1 };
+        let gone_input = InPinId { node: sink, input: 1 };
+        snarl.connect(gone_output, InPinId { node: sink, input: 0 });
+        snarl.connect(OutPinId { node: source, output: 0 }, gone_input);
+
+        let report = validate(&snarl);
+        assert_eq!(report.issues, vec![
+            Issue::MissingPin(gone_output, InPinId { node: sink, input: 0 }),
+            Issue::MissingPin(OutPinId { node: source, output: 0 }, gone_input),
+        ]);
+        assert!(!report.is_ok());
+        assert!(report.has_error(source) && report.has_error(sink));
+        assert_eq!(
+            report.summary(&snarl),
+            "Wire from \"source\" output 1 to \"sink\" input 0: output 1 no longer exists\n\
+             Wire from \"source\" output 0 to \"sink\" input 1: input 1 no longer exists",
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use egui::Color32;
+use glam::Mat4;
+use crate::point_cloud::PointCloud;
+
+/// Data carried along a wire from an output pin to an input pin.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    PointCloud(Arc<PointCloud>),
+    Scalar(f64),
+    Text(String),
+    Transform(Mat4),
+    Path(PathBuf),
+}
+
+impl Value {
+    pub fn pin_type(&self) -> PinType {
+        match self {
+            Value::PointCloud(_) => PinType::PointCloud,
+            Value::Scalar(_) => PinType::Scalar,
+            Value::Text(_) => PinType::Text,
+            Value::Transform(_) => PinType::Transform,
+            Value::Path(_) => PinType::Path,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::PointCloud(cloud) if cloud.attributes.is_empty() => write!(f, "{} points", cloud.len()),
+            Value::PointCloud(cloud) => {
+                let names: Vec<&str> = cloud.attribute_names().collect();
+                write!(f, "{} points ({})", cloud.len(), names.join(", "))
+            }
+            Value::Scalar(v) => write!(f, "{}", v),
+            Value::Text(s) => write!(f, "\"{}\"", s),
+            Value::Transform(m) => write!(f, "transform (translation {})", m.w_axis.truncate()),
+            Value::Path(p) => write!(f, "{}", p.display()),
+        }
+    }
+}
+
+/// Data type declared by an input or output pin.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PinType {
+    /// Accepts or produces any type, for generic nodes.
+    Any,
+    PointCloud,
+    Scalar,
+    Text,
+    Transform,
+    Path,
+}
+
+impl PinType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PinType::Any => "any",
+            PinType::PointCloud => "point cloud",
+            PinType::Scalar => "scalar",
+            PinType::Text => "string",
+            PinType::Transform => "transform",
+            PinType::Path => "path",
+        }
+    }
+
+    pub fn color(&self) -> Color32 {
+        match self {
+            PinType::Any => Color32::GRAY,
+            PinType::PointCloud => Color32::from_rgb(0x4c, 0xaf, 0x50),
+            PinType::Scalar => Color32::from_rgb(0x21, 0x96, 0xf3),
+            PinType::Text => Color32::from_rgb(0xff, 0xc1, 0x07),
+            PinType::Transform => Color32::from_rgb(0x9c, 0x27, 0xb0),
+            PinType::Path => Color32::from_rgb(0xff, 0x57, 0x22),
+        }
+    }
+
+    /// Whether an output of type `from` may be wired into an input of this type.
+    pub fn accepts(&self, from: PinType) -> bool {
+        *self == PinType::Any || from == PinType::Any || *self == from
+    }
+}
@@ -1,4 +1,4 @@
-use egui::{Ui, Color32, Stroke};
+use egui::Ui;
 use eframe::egui_glow::glow;
 use std::sync::Arc;
 use crate::panes::*;
@@ -17,6 +17,7 @@ pub enum PaneMode {
 
 #[typetag::serde(tag = "type")]
 pub trait Pane {
+    #[allow(clippy::new_ret_no_self)]
     fn new() -> PaneState where Self: Sized;
     fn init(&mut self, pcc: &PsudoCreationContext);
     fn name(&mut self) -> &str;
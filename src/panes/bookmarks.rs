@@ -0,0 +1,92 @@
+use egui::Ui;
+use glam::{Quat, Vec3};
+use crate::panes::point_cloud_renderer::{CameraView, Projection};
+
+/// A camera view kept with the pane. The pivot is in absolute coordinates, so the view
+/// still fits when the scene is loaded again with another origin.
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SavedView {
+    pub pivot: [f64; 3],
+    pub orientation: [f32; 4],
+    pub distance: f32,
+    pub projection: Projection,
+}
+
+impl SavedView {
+    pub fn new(view: &CameraView, scene_origin: [f64; 3]) -> Self {
+        Self {
+            pivot: std::array::from_fn(|axis| scene_origin[axis] + view.position[axis] as f64),
+            orientation: view.orientation.to_array(),
+            distance: view.distance,
+            projection: view.projection,
+        }
+    }
+
+    pub fn view(&self, scene_origin: [f64; 3]) -> CameraView {
+        CameraView {
+            position: Vec3::from_array(std::array::from_fn(|axis| (self.pivot[axis] - scene_origin[axis]) as f32)),
+            orientation: Quat::from_array(self.orientation).normalize(),
+            distance: self.distance,
+            projection: self.projection,
+        }
+    }
+}
+
+/// A named camera view.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    #[serde(flatten)]
+    pub view: SavedView,
+}
+
+/// The bookmarks panel: saved views and the name for the next one.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Bookmarks {
+    pub list: Vec<Bookmark>,
+    #[serde(skip)]
+    name: String,
+}
+
+impl Bookmarks {
+    /// Lists the bookmarks and adds `current` on request. Returns the view of a
+    /// bookmark to go to.
+    pub fn show(&mut self, ui: &mut Ui, current: &CameraView, scene_origin: [f64; 3]) -> Option<CameraView> {
+        ui.horizontal(|ui| {
+            let hint = format!("View {}", self.list.len() + 1);
+            ui.add(egui::TextEdit::singleline(&mut self.name).hint_text(&hint).desired_width(120.0));
+            if ui.button("Add Current View").clicked() {
+                let name = match self.name.trim() {
+                    "" => hint,
+                    name => name.to_string(),
+                };
+                self.list.push(Bookmark { name, view: SavedView::new(current, scene_origin) });
+                self.name.clear();
+            }
+        });
+        ui.separator();
+
+        if self.list.is_empty() {
+            ui.label("No bookmarks yet");
+        }
+        let mut target = None;
+        let mut removed = None;
+        egui::Grid::new("bookmarks").num_columns(3).striped(true).show(ui, |ui| {
+            for (i, bookmark) in self.list.iter_mut().enumerate() {
+                ui.add(egui::TextEdit::singleline(&mut bookmark.name).desired_width(120.0));
+                if ui.small_button("Go").clicked() {
+                    target = Some(bookmark.view.view(scene_origin));
+                }
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = removed {
+            self.list.remove(i);
+        }
+        target
+    }
+}
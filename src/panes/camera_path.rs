@@ -0,0 +1,338 @@
+use egui::Ui;
+use glam::{DVec3, Quat};
+use std::f64::consts::{PI, TAU};
+use std::ops::{Add, Mul, Sub};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use crate::panes::bookmarks::SavedView;
+use crate::panes::point_cloud_renderer::{turntable, yaw_tilt, CameraView, PointRenderer};
+
+/// A view the path passes through `time` seconds in.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Keyframe {
+    pub time: f32,
+    #[serde(flatten)]
+    pub view: SavedView,
+}
+
+/// What the camera path panel asks of the pane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathEvent {
+    /// The scrubber moved; the camera should show the path at `time`
+    Seek,
+    Export,
+    CancelExport,
+}
+
+/// Keyframes for flythroughs, the timeline scrubber and the export settings.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CameraPath {
+    /// Sorted by time
+    pub keyframes: Vec<Keyframe>,
+    /// Seconds between the last keyframe and a new one
+    pub spacing: f32,
+    pub export_dir: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: f32,
+    /// Position of the scrubber, in seconds
+    #[serde(skip)]
+    pub time: f32,
+    #[serde(skip)]
+    pub playing: bool,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        Self {
+            keyframes: Vec::new(),
+            spacing: 2.0,
+            export_dir: "./frames".to_string(),
+            width: 1920,
+            height: 1080,
+            fps: 30.0,
+            time: 0.0,
+            playing: false,
+        }
+    }
+}
+
+/// Uniform Catmull-Rom spline through `p[1]` and `p[2]`, at `t` from 0 to 1 between them.
+fn catmull_rom<T>(p: [T; 4], t: f64) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    let [p0, p1, p2, p3] = p;
+    let (t2, t3) = (t * t, t * t * t);
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+impl CameraPath {
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// The view `time` seconds in, on a spline through the keyframes. Pivot, distance,
+    /// yaw and tilt are interpolated separately, so the path never rolls.
+    pub fn view_at(&self, time: f32, scene_origin: [f64; 3]) -> Option<CameraView> {
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        if keys.len() == 1 || time <= first.time {
+            return Some(first.view.view(scene_origin));
+        }
+        let i = keys.windows(2).position(|pair| time < pair[1].time).unwrap_or(keys.len() - 2);
+        let (a, b) = (&keys[i], &keys[i + 1]);
+        let t = ((time - a.time) / (b.time - a.time).max(1e-6)).clamp(0.0, 1.0) as f64;
+        // The ends repeat, so the path starts and stops on its first and last keyframes
+        let at = |offset: isize| &keys[(i as isize + offset).clamp(0, keys.len() as isize - 1) as usize].view;
+        let views = [at(-1), at(0), at(1), at(2)];
+
+        let pivot = catmull_rom(views.map(|view| DVec3::from(view.pivot)), t);
+        let distance = catmull_rom(views.map(|view| view.distance as f64), t).max(1e-3);
+        let mut angles = views.map(|view| {
+            let (yaw, tilt) = yaw_tilt(Quat::from_array(view.orientation).normalize());
+            (yaw as f64, tilt as f64)
+        });
+        // Unwrapped, so the path turns the short way round between keyframes
+        for j in 1..4 {
+            let turn = angles[j].0 - angles[j - 1].0;
+            angles[j].0 -= (turn / TAU).round() * TAU;
+        }
+        let yaw = catmull_rom(angles.map(|(yaw, _)| yaw), t);
+        let tilt = catmull_rom(angles.map(|(_, tilt)| tilt), t).clamp(0.0, PI);
+
+        let view = SavedView {
+            pivot: pivot.to_array(),
+            orientation: turntable(yaw as f32, tilt as f32).to_array(),
+            distance: distance as f32,
+            projection: a.view.projection,
+        };
+        Some(view.view(scene_origin))
+    }
+
+    /// Views of every frame to export, at `fps` from the first keyframe to the last.
+    pub fn frames(&self, scene_origin: [f64; 3]) -> Vec<CameraView> {
+        let fps = self.fps.max(1.0);
+        let count = (self.duration() * fps).ceil() as usize + 1;
+        (0..count).filter_map(|frame| self.view_at(frame as f32 / fps, scene_origin)).collect()
+    }
+
+    /// The panel. `current` is the camera's view, for new keyframes; `export` the
+    /// progress of a running export as frames written and total.
+    pub fn show(&mut self, ui: &mut Ui, current: &CameraView, scene_origin: [f64; 3], export: Option<(usize, usize)>) -> Option<PathEvent> {
+        let mut event = None;
+        let duration = self.duration();
+        ui.horizontal(|ui| {
+            let play = if self.playing { "Pause" } else { "Play" };
+            if ui.add_enabled(self.keyframes.len() > 1, egui::Button::new(play)).clicked() {
+                if !self.playing && self.time >= duration {
+                    self.time = 0.0;
+                }
+                self.playing = !self.playing;
+            }
+            let slider = egui::Slider::new(&mut self.time, 0.0..=duration.max(0.0)).suffix(" s").fixed_decimals(2);
+            if ui.add_enabled(!self.keyframes.is_empty(), slider).changed() {
+                self.playing = false;
+                event = Some(PathEvent::Seek);
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Add Keyframe").clicked() {
+                let time = if self.keyframes.is_empty() { 0.0 } else { duration + self.spacing };
+                self.keyframes.push(Keyframe { time, view: SavedView::new(current, scene_origin) });
+                self.time = time;
+            }
+            ui.label("after");
+            ui.add(egui::DragValue::new(&mut self.spacing).range(0.1..=60.0).speed(0.1).suffix(" s"));
+        });
+        ui.separator();
+
+        if self.keyframes.is_empty() {
+            ui.label("Add keyframes to move the camera along a path");
+        }
+        let mut removed = None;
+        let mut reorder = false;
+        egui::Grid::new("keyframes").num_columns(4).striped(true).show(ui, |ui| {
+            for (i, keyframe) in self.keyframes.iter_mut().enumerate() {
+                let time = egui::DragValue::new(&mut keyframe.time).range(0.0..=3600.0).speed(0.05).suffix(" s");
+                reorder |= ui.add(time).changed();
+                if ui.small_button("Go").clicked() {
+                    self.time = keyframe.time;
+                    self.playing = false;
+                    event = Some(PathEvent::Seek);
+                }
+                if ui.small_button("Update").on_hover_text("Replace with the current view").clicked() {
+                    keyframe.view = SavedView::new(current, scene_origin);
+                }
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = removed {
+            self.keyframes.remove(i);
+        }
+        if reorder {
+            self.keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.label("Frames to");
+            ui.text_edit_singleline(&mut self.export_dir);
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::DragValue::new(&mut self.width).range(16..=8192).suffix(" px"));
+            ui.label("x");
+            ui.add(egui::DragValue::new(&mut self.height).range(16..=8192).suffix(" px"));
+            ui.label("at");
+            ui.add(egui::DragValue::new(&mut self.fps).range(1.0..=240.0).suffix(" fps"));
+        });
+        match export {
+            Some((written, total)) => {
+                ui.horizontal(|ui| {
+                    ui.add(egui::ProgressBar::new(written as f32 / total.max(1) as f32)
+                        .text(format!("{} / {} frames", written, total)));
+                    if ui.button("Cancel").clicked() {
+                        event = Some(PathEvent::CancelExport);
+                    }
+                });
+            }
+            None => {
+                let frames = (self.duration() * self.fps.max(1.0)).ceil() as usize + 1;
+                let button = egui::Button::new(format!("Export {} Frames", frames));
+                if ui.add_enabled(self.keyframes.len() > 1, button).clicked() {
+                    event = Some(PathEvent::Export);
+                }
+            }
+        }
+        event
+    }
+}
+
+/// Passes over a frame within one paint callback, so points in memory are all
+/// uploaded before it is written.
+const PASSES_PER_FRAME: usize = 8;
+/// Paint callbacks to wait for nodes still paging in from a cache before a frame is
+/// written as it is.
+const MAX_ATTEMPTS: usize = 300;
+
+/// Renders a path's frames, one per paint callback, and writes them as numbered PNGs
+/// on a thread of its own.
+pub struct PathExport {
+    views: Vec<CameraView>,
+    width: u32,
+    height: u32,
+    dir: PathBuf,
+    /// Next frame to render
+    next: usize,
+    /// Times the next frame came out with nodes missing
+    attempts: usize,
+    frames: mpsc::Sender<(PathBuf, Vec<u8>)>,
+    results: mpsc::Receiver<Result<(), String>>,
+    written: usize,
+    error: Option<String>,
+}
+
+impl PathExport {
+    pub fn start(dir: &str, width: u32, height: u32, views: Vec<CameraView>) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir, e))?;
+        let (frames, receiver) = mpsc::channel::<(PathBuf, Vec<u8>)>();
+        let (sender, results) = mpsc::channel();
+        std::thread::spawn(move || {
+            for (path, pixels) in receiver {
+                let result = write_png(&path, width, height, &pixels)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e));
+                if sender.send(result).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            views,
+            width,
+            height,
+            dir: PathBuf::from(dir),
+            next: 0,
+            attempts: 0,
+            frames,
+            results,
+            written: 0,
+            error: None,
+        })
+    }
+
+    /// Renders the next frame and hands it to the writer. Runs in a paint callback, as
+    /// `PointRenderer::render_image` needs; the camera is left as it was.
+    pub fn step(&mut self, renderer: &mut PointRenderer) {
+        let Some(&view) = self.views.get(self.next) else {
+            return;
+        };
+        if self.error.is_some() {
+            return;
+        }
+        let camera = renderer.camera.as_mut().expect("Not Initialised");
+        let current = camera.view();
+        camera.set_view(view);
+        let mut image = renderer.render_image(self.width, self.height);
+        for _ in 1..PASSES_PER_FRAME {
+            if image.is_err() || renderer.stats.pending_nodes == 0 {
+                break;
+            }
+            image = renderer.render_image(self.width, self.height);
+        }
+        renderer.camera.as_mut().expect("Not Initialised").set_view(current);
+
+        let pixels = match image {
+            Ok(pixels) => pixels,
+            Err(e) => {
+                self.error = Some(e);
+                return;
+            }
+        };
+        if renderer.stats.pending_nodes > 0 && self.attempts < MAX_ATTEMPTS {
+            self.attempts += 1;
+            return;
+        }
+        self.attempts = 0;
+        let path = self.dir.join(format!("frame_{:05}.png", self.next));
+        self.next += 1;
+        if self.frames.send((path, pixels)).is_err() {
+            self.error = Some("The frame writer stopped".to_string());
+        }
+    }
+
+    /// Frames written so far and in total, or the first error.
+    pub fn poll(&mut self) -> Result<(usize, usize), String> {
+        while let Ok(result) = self.results.try_recv() {
+            if let Err(e) = result {
+                self.error.get_or_insert(e);
+            }
+            self.written += 1;
+        }
+        match &self.error {
+            Some(e) => Err(e.clone()),
+            None => Ok((self.written, self.views.len())),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.written == self.views.len()
+    }
+}
+
+/// Writes tightly packed RGB rows as an 8 bit PNG.
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(pixels).map_err(|e| e.to_string())
+}
@@ -0,0 +1,369 @@
+use egui::{Color32, Painter, Pos2, Rect, Stroke, Ui};
+use glam::{EulerRot, Mat4, Quat, Vec3, Vec4};
+use crate::panes::measurements::to_screen;
+
+/// Clip planes the renderer has room for; the vertex shader's array is this long.
+pub const MAX_CLIP_PLANES: usize = 4;
+
+/// How close to a gizmo handle, in points, a press has to land to grab it.
+const HANDLE_RADIUS: f32 = 7.0;
+
+/// Hides the points on one side of a plane.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ClipPlane {
+    pub enabled: bool,
+    /// A point on the plane, in absolute coordinates
+    pub point: [f64; 3],
+    /// Points to the side that stays visible
+    pub normal: [f32; 3],
+}
+
+/// Hides the points outside, or inside, an oriented box.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ClipBox {
+    pub enabled: bool,
+    /// Centre in absolute coordinates
+    pub center: [f64; 3],
+    /// Half the box's size along its own axes
+    pub half_extent: [f32; 3],
+    /// Degrees about x, y and z, as layers are rotated
+    pub rotation: [f32; 3],
+    /// Hides the inside instead, to cut a hole
+    pub keep_outside: bool,
+}
+
+impl Default for ClipBox {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            center: [0.0; 3],
+            half_extent: [1.0; 3],
+            rotation: [0.0; 3],
+            keep_outside: false,
+        }
+    }
+}
+
+/// The clip volume in scene coordinates, as the vertex shader applies it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClipVolume {
+    /// Points are kept where `plane.xyz · p + plane.w >= 0`
+    pub planes: Vec<Vec4>,
+    /// From scene coordinates into the box's, where it spans -1 to 1, and whether
+    /// the inside is kept
+    pub clip_box: Option<(Mat4, bool)>,
+}
+
+impl ClipVolume {
+    pub fn is_empty(&self) -> bool {
+        self.planes.is_empty() && self.clip_box.is_none()
+    }
+
+    /// Whether a point at `p`, in scene coordinates, is drawn.
+    pub fn keeps(&self, p: Vec3) -> bool {
+        self.planes.iter().all(|plane| plane.truncate().dot(p) + plane.w >= 0.0)
+            && self.clip_box.is_none_or(|(to_box, keep_inside)| {
+                to_box.transform_point3(p).abs().cmple(Vec3::ONE).all() == keep_inside
+            })
+    }
+
+    /// The planes in a layer's own coordinates, for skipping octree nodes clipped away
+    /// whole. A box keeping its inside adds its six faces.
+    pub fn local_planes(&self, model: Mat4) -> Vec<Vec4> {
+        let mut planes = self.planes.clone();
+        if let Some((to_box, true)) = self.clip_box {
+            for axis in 0..3 {
+                for sign in [1.0, -1.0] {
+                    // 1 - sign * coordinate >= 0 in the box's coordinates
+                    let mut plane = Vec4::W;
+                    plane[axis] = -sign;
+                    planes.push(to_box.transpose() * plane);
+                }
+            }
+        }
+        planes.into_iter().map(|plane| model.transpose() * plane).collect()
+    }
+}
+
+/// A gizmo handle, each moving along one axis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Handle {
+    Plane(usize),
+    Face { axis: usize, sign: f32 },
+}
+
+/// Clip planes and box, edited in a panel and through gizmos in the viewport.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Clipping {
+    pub planes: Vec<ClipPlane>,
+    pub clip_box: ClipBox,
+    pub show_gizmos: bool,
+    /// Handle being dragged
+    #[serde(skip)]
+    drag: Option<Handle>,
+}
+
+impl Default for Clipping {
+    fn default() -> Self {
+        Self {
+            planes: Vec::new(),
+            clip_box: ClipBox::default(),
+            show_gizmos: true,
+            drag: None,
+        }
+    }
+}
+
+/// `point` relative to the scene origin.
+fn relative(point: [f64; 3], scene_origin: [f64; 3]) -> Vec3 {
+    Vec3::from_array(std::array::from_fn(|axis| (point[axis] - scene_origin[axis]) as f32))
+}
+
+impl Clipping {
+    pub fn volume(&self, scene_origin: [f64; 3]) -> ClipVolume {
+        let planes = self.planes.iter()
+            .filter(|plane| plane.enabled)
+            .take(MAX_CLIP_PLANES)
+            .map(|plane| {
+                let normal = Vec3::from(plane.normal).normalize_or_zero();
+                normal.extend(-normal.dot(relative(plane.point, scene_origin)))
+            })
+            .collect();
+        let clip_box = self.clip_box.enabled
+            .then(|| (self.box_matrix(scene_origin).inverse(), !self.clip_box.keep_outside));
+        ClipVolume { planes, clip_box }
+    }
+
+    fn box_rotation(&self) -> Quat {
+        let [rx, ry, rz] = self.clip_box.rotation.map(f32::to_radians);
+        Quat::from_euler(EulerRot::XYZ, rx, ry, rz)
+    }
+
+    /// From the cube spanning -1 to 1 to the box, in scene coordinates.
+    fn box_matrix(&self, scene_origin: [f64; 3]) -> Mat4 {
+        Mat4::from_scale_rotation_translation(
+            Vec3::from(self.clip_box.half_extent).max(Vec3::splat(1e-4)),
+            self.box_rotation(),
+            relative(self.clip_box.center, scene_origin),
+        )
+    }
+
+    /// The handles of the enabled planes and box faces: where each is in the scene, and
+    /// the unit axis it moves along.
+    fn handles(&self, scene_origin: [f64; 3]) -> Vec<(Handle, Vec3, Vec3)> {
+        let mut handles: Vec<(Handle, Vec3, Vec3)> = self.planes.iter().enumerate()
+            .filter(|(_, plane)| plane.enabled)
+            .map(|(i, plane)| (Handle::Plane(i), relative(plane.point, scene_origin), Vec3::from(plane.normal).normalize_or_zero()))
+            .collect();
+        if self.clip_box.enabled {
+            let center = relative(self.clip_box.center, scene_origin);
+            for axis in 0..3 {
+                for sign in [1.0, -1.0] {
+                    let direction = self.box_rotation() * (Vec3::AXES[axis] * sign);
+                    let position = center + direction * self.clip_box.half_extent[axis];
+                    handles.push((Handle::Face { axis, sign }, position, direction));
+                }
+            }
+        }
+        handles
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Starts dragging the handle nearest `pointer`, if one is within reach. Returns
+    /// whether one was.
+    pub fn grab(&mut self, view_projection: Mat4, viewport: Rect, scene_origin: [f64; 3], pointer: Pos2) -> bool {
+        self.drag = None;
+        if !self.show_gizmos {
+            return false;
+        }
+        self.drag = self.handles(scene_origin).into_iter()
+            .filter_map(|(handle, position, _)| Some((handle, to_screen(view_projection, viewport, position)?.distance(pointer))))
+            .filter(|(_, distance)| *distance <= HANDLE_RADIUS)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(handle, _)| handle);
+        self.drag.is_some()
+    }
+
+    /// Moves the dragged handle along its axis as far as the pointer moved along the
+    /// axis on screen.
+    pub fn drag(&mut self, view_projection: Mat4, viewport: Rect, scene_origin: [f64; 3], delta: egui::Vec2) {
+        let Some(handle) = self.drag else {
+            return;
+        };
+        let Some((_, position, axis)) = self.handles(scene_origin).into_iter().find(|(h, ..)| *h == handle) else {
+            self.drag = None;
+            return;
+        };
+        let (Some(start), Some(end)) = (to_screen(view_projection, viewport, position), to_screen(view_projection, viewport, position + axis)) else {
+            return;
+        };
+        // Screen points per scene unit along the axis
+        let along = end - start;
+        if along.length_sq() < 1e-6 {
+            return;
+        }
+        let distance = delta.dot(along) / along.length_sq();
+        match handle {
+            Handle::Plane(i) => {
+                let plane = &mut self.planes[i];
+                for (value, offset) in plane.point.iter_mut().zip(axis.to_array()) {
+                    *value += (offset * distance) as f64;
+                }
+            }
+            Handle::Face { axis: i, .. } => {
+                // The opposite face stays where it is
+                let half = &mut self.clip_box.half_extent[i];
+                let grown = (*half + distance * 0.5).max(1e-3) - *half;
+                *half += grown;
+                for (value, offset) in self.clip_box.center.iter_mut().zip(axis.to_array()) {
+                    *value += (offset * grown) as f64;
+                }
+            }
+        }
+    }
+
+    pub fn release(&mut self) {
+        self.drag = None;
+    }
+
+    /// Draws the box's edges, a square for each plane with an arrow to the side kept,
+    /// and the handles.
+    pub fn paint(&self, painter: &Painter, view_projection: Mat4, viewport: Rect, scene_origin: [f64; 3]) {
+        if !self.show_gizmos {
+            return;
+        }
+        let color = Color32::from_rgb(80, 200, 255);
+        let stroke = Stroke { width: 1.5, color };
+        let screen = |p: Vec3| to_screen(view_projection, viewport, p);
+        let line = |a: Vec3, b: Vec3| {
+            if let (Some(a), Some(b)) = (screen(a), screen(b)) {
+                painter.line_segment([a, b], stroke);
+            }
+        };
+
+        if self.clip_box.enabled {
+            let matrix = self.box_matrix(scene_origin);
+            let corner = |i: usize| matrix.transform_point3(Vec3::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+            ));
+            for i in 0..8 {
+                for bit in [1, 2, 4] {
+                    if i & bit == 0 {
+                        line(corner(i), corner(i | bit));
+                    }
+                }
+            }
+        }
+
+        for (handle, position, axis) in self.handles(scene_origin) {
+            let Some(center) = screen(position) else {
+                continue;
+            };
+            if let Handle::Plane(_) = handle {
+                // About 50 points across, whatever the distance
+                let (u, v) = axis.any_orthonormal_pair();
+                let Some(step) = screen(position + u).map(|p| p.distance(center)).filter(|step| *step > 1e-3) else {
+                    continue;
+                };
+                let size = 25.0 / step;
+                let corners = [u + v, u - v, -u - v, -u + v].map(|c| position + c * size);
+                for i in 0..4 {
+                    line(corners[i], corners[(i + 1) % 4]);
+                }
+                line(position, position + axis * size);
+            }
+            let fill = if self.drag == Some(handle) { Color32::WHITE } else { color };
+            painter.circle(center, HANDLE_RADIUS * 0.7, fill, Stroke { width: 1.0, color: Color32::BLACK });
+        }
+    }
+
+    /// The panel. `bounds` is the scene's bounding box, to place new planes and fit the
+    /// box. Returns whether cropping the selected layer to the volume was asked for.
+    pub fn show(&mut self, ui: &mut Ui, bounds: Option<(Vec3, Vec3)>, scene_origin: [f64; 3], can_crop: bool) -> bool {
+        let absolute = |p: Vec3| -> [f64; 3] { std::array::from_fn(|axis| scene_origin[axis] + p[axis] as f64) };
+        let center = bounds.map_or(scene_origin, |(min, max)| absolute((min + max) * 0.5));
+        // Drags move about a thousandth of the scene per point
+        let speed = bounds.map_or(0.01, |(min, max)| ((max - min).length() as f64 * 1e-3).max(1e-4));
+
+        ui.checkbox(&mut self.show_gizmos, "Show gizmos");
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Planes");
+            if ui.add_enabled(self.planes.len() < MAX_CLIP_PLANES, egui::Button::new("Add Plane")).clicked() {
+                self.planes.push(ClipPlane { enabled: true, point: center, normal: [0.0, 0.0, -1.0] });
+            }
+        });
+        let mut removed = None;
+        for (i, plane) in self.planes.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut plane.enabled, format!("{}", i + 1));
+                for v in &mut plane.point {
+                    ui.add(egui::DragValue::new(v).speed(speed));
+                }
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(i);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Normal");
+                for v in &mut plane.normal {
+                    ui.add(egui::DragValue::new(v).range(-1.0..=1.0).speed(0.01));
+                }
+                if ui.small_button("Flip").clicked() {
+                    plane.normal = plane.normal.map(|v| -v);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            self.planes.remove(i);
+            self.drag = None;
+        }
+        ui.separator();
+
+        ui.checkbox(&mut self.clip_box.enabled, "Clip box");
+        ui.add_enabled_ui(self.clip_box.enabled, |ui| {
+            let clip_box = &mut self.clip_box;
+            ui.horizontal(|ui| {
+                ui.label("Centre");
+                for v in &mut clip_box.center {
+                    ui.add(egui::DragValue::new(v).speed(speed));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Half size");
+                for v in &mut clip_box.half_extent {
+                    ui.add(egui::DragValue::new(v).range(1e-3..=f32::MAX).speed(speed));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Rotation");
+                for v in &mut clip_box.rotation {
+                    ui.add(egui::DragValue::new(v).range(-180.0..=180.0).suffix("°"));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut clip_box.keep_outside, "Hide inside");
+                if ui.add_enabled(bounds.is_some(), egui::Button::new("Fit to Scene")).clicked() {
+                    if let Some((min, max)) = bounds {
+                        clip_box.center = absolute((min + max) * 0.5);
+                        clip_box.half_extent = ((max - min) * 0.5).max(Vec3::splat(1e-3)).to_array();
+                        clip_box.rotation = [0.0; 3];
+                    }
+                }
+            });
+        });
+        ui.separator();
+
+        let active = !self.volume(scene_origin).is_empty();
+        ui.add_enabled(can_crop && active, egui::Button::new("Crop Layer to Clip Volume"))
+            .on_hover_text("Removes the hidden points from the selected layer")
+            .clicked()
+    }
+}
@@ -0,0 +1,481 @@
+use egui::{Align2, Color32, FontId, Rect, Ui, Vec2};
+use glam::{EulerRot, Mat4, Quat, Vec3};
+use std::sync::Arc;
+use crate::panes::point_cloud_renderer::{LayerStyle, Pick, ScalarSource, ScalarStyle};
+use crate::point_cloud::{las, PointCloud};
+use crate::point_cloud::cache::{CacheAttribute, CacheColoring};
+use crate::point_cloud::octree::Octree;
+use crate::point_cloud::ramp::{self, ColorRamp};
+
+/// Points looked at when fitting the ramp range to a field.
+const RANGE_SAMPLES: usize = 20_000;
+
+/// One cloud in the scene, with how it is placed and drawn.
+pub struct Layer {
+    /// Stable id the renderer keys the layer's GPU data by, given out by the pane
+    pub id: u64,
+    pub name: String,
+    pub visible: bool,
+    /// Moves the layer relative to where its coordinates put it
+    pub translation: [f32; 3],
+    /// Degrees about x, y and z, applied in that order around the layer origin
+    pub rotation: [f32; 3],
+    pub scale: f32,
+    /// Multiplies the camera's point size
+    pub point_size: f32,
+    pub color_mode: ColorMode,
+    pub ramp: ColorRamp,
+    /// Field values at the two ends of the ramp
+    pub scalar_range: [f64; 2],
+    /// Fit `scalar_range` to the field whenever it or the view of it changes
+    pub auto_range: bool,
+    /// What `scalar_range` was last fitted to: field, model matrix and, for distances, the eye
+    range_key: Option<(ScalarField, Mat4, Vec3)>,
+    /// Multiplies every point colour
+    pub tint: Color32,
+    pub opacity: f32,
+    /// Empty when streaming from a cache, but its `origin` is still set
+    pub cloud: PointCloud,
+    pub octree: Arc<Octree>,
+    /// Point count of the open on-disk cache
+    pub cached_points: Option<usize>,
+    /// Attributes stored in the open on-disk cache, which `cloud` does not hold
+    pub cached_attributes: Vec<CacheAttribute>,
+    /// One flag per cloud point, or empty when nothing is selected
+    pub selection: Vec<bool>,
+    /// Set when the cloud or its colouring changed and the renderer needs the points again.
+    pub dirty: bool,
+}
+
+impl Layer {
+    pub fn new(name: impl Into<String>, cloud: PointCloud, octree: Octree) -> Self {
+        Self {
+            id: 0,
+            name: name.into(),
+            visible: true,
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            scale: 1.0,
+            point_size: 1.0,
+            color_mode: ColorMode::Rgb,
+            ramp: ColorRamp::default(),
+            scalar_range: [0.0, 1.0],
+            auto_range: true,
+            range_key: None,
+            tint: Color32::WHITE,
+            opacity: 1.0,
+            cloud,
+            octree: Arc::new(octree),
+            cached_points: None,
+            cached_attributes: Vec::new(),
+            selection: Vec::new(),
+            dirty: true,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cached_points.unwrap_or(self.cloud.len())
+    }
+
+    pub fn is_selected(&self, i: usize) -> bool {
+        self.selection.get(i).copied().unwrap_or(false)
+    }
+
+    pub fn selected_count(&self) -> usize {
+        self.selection.iter().filter(|&&s| s).count()
+    }
+
+    fn cached_attribute(&self, name: &str) -> Option<usize> {
+        self.cached_attributes.iter().position(|a| a.name == name)
+    }
+
+    /// Names of the attributes colour modes can use, in memory or in the cache.
+    pub fn attribute_names(&self) -> impl Iterator<Item = &str> {
+        self.cloud.attribute_names().chain(self.cached_attributes.iter().map(|a| a.name.as_str()))
+    }
+
+    /// What the cache reader should put in the colour word for the current colour mode.
+    pub fn cache_coloring(&self) -> CacheColoring {
+        match &self.color_mode {
+            ColorMode::Classification => self.cached_attribute("classification")
+                .map_or(CacheColoring::Rgb, CacheColoring::Classification),
+            ColorMode::Scalar(ScalarField::Attribute(name)) => self.cached_attribute(name)
+                .map_or(CacheColoring::Rgb, CacheColoring::Scalar),
+            _ => CacheColoring::Rgb,
+        }
+    }
+
+    /// From the layer's origin-relative coordinates to the scene. `scene_origin` is subtracted
+    /// from the layer origin in f64, so layers far apart in world coordinates still line up.
+    pub fn model(&self, scene_origin: [f64; 3]) -> Mat4 {
+        let offset: [f64; 3] = std::array::from_fn(|axis| self.cloud.origin[axis] - scene_origin[axis]);
+        let [rx, ry, rz] = self.rotation.map(f32::to_radians);
+        Mat4::from_translation(Vec3::from(offset.map(|v| v as f32)))
+            * Mat4::from_scale_rotation_translation(
+                Vec3::splat(self.scale),
+                Quat::from_euler(EulerRot::XYZ, rx, ry, rz),
+                Vec3::from(self.translation),
+            )
+    }
+
+    /// The renderer's view of this layer.
+    pub fn style(&self, scene_origin: [f64; 3]) -> LayerStyle {
+        let [r, g, b, _] = self.tint.to_array().map(|c| c as f32 / 255.0);
+        let scalar = match &self.color_mode {
+            ColorMode::Scalar(field) => {
+                // The shader sees elevations in scene coordinates and attributes without their offset
+                let (source, shift) = match field {
+                    ScalarField::Elevation => (ScalarSource::Elevation, scene_origin[2]),
+                    ScalarField::DistanceToCamera => (ScalarSource::DistanceToCamera, 0.0),
+                    ScalarField::Attribute(name) => {
+                        let offset = match self.cloud.attribute(name) {
+                            Some(attribute) => attribute.offset,
+                            None => self.cached_attribute(name).map_or(0.0, |a| self.cached_attributes[a].offset),
+                        };
+                        (ScalarSource::Point, offset)
+                    }
+                };
+                Some(ScalarStyle {
+                    source,
+                    range: self.scalar_range.map(|v| (v - shift) as f32),
+                    ramp: self.ramp,
+                })
+            }
+            _ => None,
+        };
+        LayerStyle {
+            visible: self.visible,
+            model: self.model(scene_origin),
+            point_size: self.point_size,
+            tint: [r, g, b, self.opacity],
+            scalar,
+        }
+    }
+
+    /// Refits `scalar_range` when the field changed or, with auto range on, when the
+    /// layer or the eye moved in a way that changes the field. `eye` is in scene coordinates.
+    pub fn update_range(&mut self, scene_origin: [f64; 3], eye: Vec3) {
+        let ColorMode::Scalar(field) = &self.color_mode else {
+            return;
+        };
+        let model = self.model(scene_origin);
+        let eye = if *field == ScalarField::DistanceToCamera { eye } else { Vec3::ZERO };
+        let key = (field.clone(), model, eye);
+        let stale = match &self.range_key {
+            None => true,
+            Some(last) => last.0 != key.0 || (self.auto_range && *last != key),
+        };
+        if !stale {
+            return;
+        }
+        let samples = self.scalar_samples(field, model, scene_origin, eye);
+        if let Some(range) = ramp::histogram_range(&samples, 0.01, 0.99) {
+            self.scalar_range = range;
+        }
+        self.range_key = Some(key);
+    }
+
+    /// Up to `RANGE_SAMPLES` values of `field`, spread over the cloud. A layer streamed
+    /// from a cache only has its octree and attribute samples at hand, so node centres
+    /// stand in for the positions.
+    fn scalar_samples(&self, field: &ScalarField, model: Mat4, scene_origin: [f64; 3], eye: Vec3) -> Vec<f64> {
+        let value = |p: Vec3| {
+            let p = model.transform_point3(p);
+            match field {
+                ScalarField::DistanceToCamera => p.distance(eye) as f64,
+                _ => p.z as f64 + scene_origin[2],
+            }
+        };
+        let step = (self.cloud.len() / RANGE_SAMPLES).max(1);
+        match field {
+            ScalarField::Attribute(name) => match (self.cloud.attribute(name), self.cached_attribute(name)) {
+                (Some(attribute), _) => (0..attribute.values.len()).step_by(step).map(|i| attribute.get(i)).collect(),
+                (None, Some(a)) => {
+                    let attribute = &self.cached_attributes[a];
+                    attribute.samples.iter().map(|&v| v as f64 + attribute.offset).collect()
+                }
+                (None, None) => Vec::new(),
+            },
+            _ if self.cloud.is_empty() => self.octree.nodes.iter().map(|node| value(node.center())).collect(),
+            _ => self.cloud.positions.iter().step_by(step).map(|&p| value(Vec3::from(p))).collect(),
+        }
+    }
+
+    /// Everything known about a picked point of this layer.
+    pub fn inspect(&self, pick: &Pick) -> PickedPoint {
+        let record = self.octree.nodes[pick.node as usize].first + pick.offset;
+        match self.octree.order.get(record) {
+            Some(&index) if self.cached_points.is_none() => {
+                let index = index as usize;
+                PickedPoint {
+                    layer: self.id,
+                    local: pick.local,
+                    position: self.cloud.position(index),
+                    color: self.cloud.colors[index],
+                    index,
+                    attributes: self.cloud.attributes.iter().map(|a| (a.name.clone(), a.get(index))).collect(),
+                }
+            }
+            // Streamed points only carry what their vertices hold: position and the colour word
+            _ => {
+                let (color, attributes) = match self.cache_coloring() {
+                    CacheColoring::Scalar(a) => {
+                        let attribute = &self.cached_attributes[a];
+                        let value = f32::from_bits(pick.word) as f64 + attribute.offset;
+                        let [min, max] = self.scalar_range;
+                        let t = ((value - min) / (max - min).max(1e-12)).clamp(0.0, 1.0);
+                        (self.ramp.sample(t as f32), vec![(attribute.name.clone(), value)])
+                    }
+                    CacheColoring::Rgb | CacheColoring::Classification(_) => {
+                        let [r, g, b, a] = pick.word.to_ne_bytes();
+                        (Color32::from_rgba_premultiplied(r, g, b, a), Vec::new())
+                    }
+                };
+                PickedPoint {
+                    layer: self.id,
+                    local: pick.local,
+                    position: std::array::from_fn(|axis| self.cloud.origin[axis] + pick.local[axis] as f64),
+                    color,
+                    index: record,
+                    attributes,
+                }
+            }
+        }
+    }
+
+    /// Colour ramp with the field name and its range, in the bottom right corner of `rect`.
+    pub fn paint_legend(&self, painter: &egui::Painter, rect: Rect) {
+        let ColorMode::Scalar(field) = &self.color_mode else {
+            return;
+        };
+        let text_size = 12.;
+        let bar = Rect::from_min_size(rect.right_bottom() + Vec2::new(-140., -180.), Vec2::new(14., 150.));
+        let background = Rect::from_min_max(bar.min - Vec2::new(8., 28.), egui::pos2(rect.right() - 8., bar.bottom() + 8.));
+        painter.rect_filled(background, 4., Color32::from_black_alpha(140));
+
+        const SEGMENTS: usize = 64;
+        let height = bar.height() / SEGMENTS as f32;
+        for i in 0..SEGMENTS {
+            // Top of the bar is the top of the range
+            let t = 1.0 - (i as f32 + 0.5) / SEGMENTS as f32;
+            let segment = Rect::from_min_size(bar.min + Vec2::new(0., i as f32 * height), Vec2::new(bar.width(), height + 0.5));
+            painter.rect_filled(segment, 0., self.ramp.sample(t));
+        }
+
+        let [min, max] = self.scalar_range;
+        for (t, value) in [(0.0, max), (0.5, (min + max) / 2.0), (1.0, min)] {
+            painter.text(bar.right_top() + Vec2::new(4., t * bar.height()), Align2::LEFT_CENTER,
+                format_value(value), FontId::monospace(text_size), Color32::WHITE);
+        }
+        painter.text(egui::pos2(rect.right() - 12., bar.top() - 8.), Align2::RIGHT_BOTTOM,
+            field.label(), FontId::monospace(text_size), Color32::WHITE);
+    }
+
+    /// Editors for everything about the layer except its points.
+    pub fn show(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            ui.text_edit_singleline(&mut self.name);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Translation");
+            for v in &mut self.translation {
+                ui.add(egui::DragValue::new(v).speed(0.1));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Rotation");
+            for v in &mut self.rotation {
+                ui.add(egui::DragValue::new(v).range(-180.0..=180.0).suffix("°"));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Scale");
+            ui.add(egui::DragValue::new(&mut self.scale).range(0.001..=1000.0).speed(0.01));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Point size");
+            ui.add(egui::Slider::new(&mut self.point_size, 0.1..=10.0).logarithmic(true));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Opacity");
+            ui.add(egui::Slider::new(&mut self.opacity, 0.0..=1.0));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Tint");
+            ui.color_edit_button_srgba(&mut self.tint);
+        });
+
+        ui.label("Colour by");
+        let mut modes = vec![
+            ColorMode::Rgb,
+            ColorMode::Classification,
+            ColorMode::Scalar(ScalarField::Elevation),
+            ColorMode::Scalar(ScalarField::DistanceToCamera),
+        ];
+        modes.extend(self.attribute_names().map(|name| ColorMode::Scalar(ScalarField::Attribute(name.to_string()))));
+        for mode in modes {
+            let available = mode != ColorMode::Classification || self.attribute_names().any(|name| name == "classification");
+            let label = mode.label().to_string();
+            ui.add_enabled_ui(available, |ui| {
+                if ui.radio_value(&mut self.color_mode, mode, label).changed() {
+                    // Attribute values are uploaded in place of the colours
+                    self.dirty = true;
+                }
+            });
+        }
+
+        if let ColorMode::Scalar(_) = self.color_mode {
+            egui::ComboBox::from_label("Ramp")
+                .selected_text(self.ramp.label())
+                .show_ui(ui, |ui| {
+                    for ramp in ColorRamp::ALL {
+                        ui.selectable_value(&mut self.ramp, ramp, ramp.label());
+                    }
+                });
+            if ui.checkbox(&mut self.auto_range, "Auto range (1st to 99th percentile)").changed() {
+                self.range_key = None;
+            }
+            ui.add_enabled_ui(!self.auto_range, |ui| {
+                let [min, max] = &mut self.scalar_range;
+                let speed = ((*max - *min).abs() / 200.0).max(1e-6);
+                ui.horizontal(|ui| {
+                    ui.label("Min");
+                    ui.add(egui::DragValue::new(min).speed(speed));
+                    ui.label("Max");
+                    ui.add(egui::DragValue::new(max).speed(speed));
+                });
+            });
+        }
+    }
+}
+
+/// A point picked in the viewport, copied out so it stays valid while the layer changes.
+#[derive(Clone, Debug)]
+pub struct PickedPoint {
+    pub layer: u64,
+    /// Where to draw the marker, in the layer's origin-relative coordinates
+    pub local: Vec3,
+    /// The point's own coordinates, before the layer transform
+    pub position: [f64; 3],
+    pub color: Color32,
+    /// Index in the cloud, or in the cache's points file for a streamed layer
+    pub index: usize,
+    pub attributes: Vec<(String, f64)>,
+}
+
+impl PickedPoint {
+    pub fn show(&self, ui: &mut Ui) {
+        egui::Grid::new("picked_point").num_columns(2).striped(true).show(ui, |ui| {
+            ui.label("Point");
+            ui.label(self.index.to_string());
+            ui.end_row();
+            for (axis, value) in ["x", "y", "z"].iter().zip(self.position) {
+                ui.label(*axis);
+                ui.monospace(format!("{:.4}", value));
+                ui.end_row();
+            }
+            ui.label("Colour");
+            ui.horizontal(|ui| {
+                let (rect, _) = ui.allocate_exact_size(Vec2::splat(12.), egui::Sense::hover());
+                ui.painter().rect_filled(rect, 2., self.color);
+                let [r, g, b, a] = self.color.to_array();
+                ui.monospace(format!("{} {} {} {}", r, g, b, a));
+            });
+            ui.end_row();
+            for (name, value) in &self.attributes {
+                ui.label(name);
+                ui.monospace(format!("{}", value));
+                ui.end_row();
+            }
+        });
+    }
+}
+
+/// Short label for a legend value, with fewer decimals the larger it gets.
+fn format_value(value: f64) -> String {
+    match value.abs() {
+        v if v >= 1000.0 => format!("{:.0}", value),
+        v if v >= 10.0 => format!("{:.1}", value),
+        _ => format!("{:.3}", value),
+    }
+}
+
+/// The layer list: visibility, selection and removal, then the selected layer's editors.
+pub fn show_layers(layers: &mut Vec<Layer>, selected: &mut Option<u64>, ui: &mut Ui) {
+    if layers.is_empty() {
+        ui.label("No layers");
+    }
+    let mut removed = None;
+    for layer in layers.iter_mut() {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut layer.visible, "");
+            let label = format!("{} ({} points)", layer.name, layer.len());
+            if ui.selectable_label(*selected == Some(layer.id), label).clicked() {
+                *selected = Some(layer.id);
+            }
+            if ui.small_button("Remove").clicked() {
+                removed = Some(layer.id);
+            }
+        });
+    }
+    if let Some(id) = removed {
+        layers.retain(|layer| layer.id != id);
+        if *selected == Some(id) {
+            *selected = layers.first().map(|layer| layer.id);
+        }
+    }
+
+    if let Some(layer) = layers.iter_mut().find(|layer| Some(layer.id) == *selected) {
+        ui.separator();
+        layer.show(ui);
+    }
+}
+
+/// Where point colours come from.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ColorMode {
+    #[default]
+    Rgb,
+    Classification,
+    Scalar(ScalarField),
+}
+
+/// A per-point value to colour by through the layer's ramp.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScalarField {
+    Elevation,
+    DistanceToCamera,
+    Attribute(String),
+}
+
+impl ColorMode {
+    pub fn label(&self) -> &str {
+        match self {
+            ColorMode::Rgb => "RGB",
+            ColorMode::Classification => "Classification",
+            ColorMode::Scalar(field) => field.label(),
+        }
+    }
+
+    /// Per-point colours baked on the CPU, or `None` when the shader colours the points.
+    pub fn colors(&self, cloud: &PointCloud) -> Option<Vec<Color32>> {
+        match self {
+            ColorMode::Classification => {
+                let attribute = cloud.attribute("classification")?;
+                Some(attribute.values.iter().map(|&c| las::classification_color(c as u8)).collect())
+            }
+            ColorMode::Rgb | ColorMode::Scalar(_) => None,
+        }
+    }
+}
+
+impl ScalarField {
+    pub fn label(&self) -> &str {
+        match self {
+            ScalarField::Elevation => "Elevation",
+            ScalarField::DistanceToCamera => "Distance to camera",
+            ScalarField::Attribute(name) => name,
+        }
+    }
+}
@@ -0,0 +1,444 @@
+use egui::{Color32, FontId, Pos2, Rect, Stroke, Ui};
+use glam::{DVec3, Mat4, Vec3};
+use std::fmt::Write as _;
+
+/// What a measurement measures, and so how many points it takes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MeasureKind {
+    Distance,
+    Polyline,
+    Area,
+    Height,
+    Angle,
+}
+
+impl MeasureKind {
+    pub const ALL: [MeasureKind; 5] = [
+        MeasureKind::Distance,
+        MeasureKind::Polyline,
+        MeasureKind::Area,
+        MeasureKind::Height,
+        MeasureKind::Angle,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MeasureKind::Distance => "Distance",
+            MeasureKind::Polyline => "Polyline",
+            MeasureKind::Area => "Area",
+            MeasureKind::Height => "Height",
+            MeasureKind::Angle => "Angle",
+        }
+    }
+
+    /// Points after which the measurement is complete; `None` when the user finishes it.
+    fn points(&self) -> Option<usize> {
+        match self {
+            MeasureKind::Distance | MeasureKind::Height => Some(2),
+            MeasureKind::Angle => Some(3),
+            MeasureKind::Polyline | MeasureKind::Area => None,
+        }
+    }
+
+    fn min_points(&self) -> usize {
+        match self {
+            MeasureKind::Area | MeasureKind::Angle => 3,
+            _ => 2,
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            MeasureKind::Area => "area",
+            MeasureKind::Angle => "degrees",
+            _ => "length",
+        }
+    }
+}
+
+/// Points picked in the scene and what they measure. Points are absolute scene
+/// coordinates, so a measurement stays put when layers come and go.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Measurement {
+    pub name: String,
+    pub kind: MeasureKind,
+    pub points: Vec<[f64; 3]>,
+}
+
+impl Measurement {
+    pub fn new(kind: MeasureKind, name: String) -> Self {
+        Self { name, kind, points: Vec::new() }
+    }
+
+    /// Adds a point; returns true once the measurement has all the points it takes.
+    pub fn push(&mut self, point: [f64; 3]) -> bool {
+        // The second click of a double click lands on the same point
+        if self.points.last() != Some(&point) {
+            self.points.push(point);
+        }
+        self.kind.points().is_some_and(|n| self.points.len() >= n)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.points.len() >= self.kind.min_points()
+    }
+
+    /// Length, area, height difference or angle in degrees, once there are enough points.
+    pub fn value(&self) -> Option<f64> {
+        if !self.is_complete() {
+            return None;
+        }
+        let p: Vec<DVec3> = self.points.iter().map(|&p| DVec3::from(p)).collect();
+        Some(match self.kind {
+            MeasureKind::Distance | MeasureKind::Polyline => p.windows(2).map(|w| w[0].distance(w[1])).sum(),
+            MeasureKind::Height => p[1].z - p[0].z,
+            MeasureKind::Angle => (p[0] - p[1]).angle_between(p[2] - p[1]).to_degrees(),
+            // Newell's method: half the length of the summed cross products, for any planar polygon
+            MeasureKind::Area => {
+                let normal: DVec3 = (0..p.len()).map(|i| (p[i] - p[0]).cross(p[(i + 1) % p.len()] - p[0])).sum();
+                normal.length() / 2.0
+            }
+        })
+    }
+
+    /// The value as shown next to the measurement.
+    pub fn label(&self) -> String {
+        match (self.value(), self.kind) {
+            (None, _) => format!("{}: ...", self.name),
+            (Some(v), MeasureKind::Angle) => format!("{}: {:.2}°", self.name, v),
+            (Some(v), MeasureKind::Area) => format!("{}: {:.3}²", self.name, v),
+            (Some(v), _) => format!("{}: {:.3}", self.name, v),
+        }
+    }
+
+    /// Lines through the points, closed for areas, with the value at their centre.
+    /// `scene_origin` is where the renderer's coordinates are zero.
+    pub fn paint(&self, painter: &egui::Painter, rect: Rect, view_projection: Mat4, scene_origin: [f64; 3], color: Color32) {
+        let screen: Vec<Option<Pos2>> = self.points.iter()
+            .map(|&p| to_screen(view_projection, rect, relative(p, scene_origin)))
+            .collect();
+        let stroke = Stroke { width: 2.0, color };
+
+        let mut segments: Vec<(usize, usize)> = (1..screen.len()).map(|i| (i - 1, i)).collect();
+        if self.kind == MeasureKind::Area && screen.len() > 2 {
+            segments.push((screen.len() - 1, 0));
+        }
+        if self.kind == MeasureKind::Height && screen.len() == 2 {
+            // From the second point straight down or up to the first one's height, then across to it
+            let [a, b] = [self.points[0], self.points[1]];
+            let corner = [b[0], b[1], a[2]];
+            let corner = to_screen(view_projection, rect, relative(corner, scene_origin));
+            for pair in [(screen[1], corner), (corner, screen[0])] {
+                if let (Some(a), Some(b)) = pair {
+                    painter.add(egui::Shape::dashed_line(&[a, b], Stroke { width: 1.0, color }, 6.0, 4.0));
+                }
+            }
+        }
+        for (a, b) in segments {
+            if let (Some(a), Some(b)) = (screen[a], screen[b]) {
+                painter.line_segment([a, b], stroke);
+            }
+        }
+        for point in screen.iter().flatten() {
+            painter.circle_filled(*point, 3.0, color);
+        }
+
+        let visible: Vec<Pos2> = screen.iter().flatten().copied().collect();
+        if !visible.is_empty() {
+            // At the vertex for angles, otherwise at the centre of the points
+            let anchor = match self.kind {
+                MeasureKind::Angle => screen.get(1).copied().flatten(),
+                _ => None,
+            }.unwrap_or_else(|| {
+                let sum = visible.iter().fold(egui::Vec2::ZERO, |sum, p| sum + p.to_vec2());
+                (sum / visible.len() as f32).to_pos2()
+            });
+            let galley_pos = anchor + egui::Vec2::new(8.0, -8.0);
+            let text = self.label();
+            let font = FontId::monospace(12.);
+            let galley = painter.layout_no_wrap(text, font, Color32::WHITE);
+            let background = Rect::from_min_size(galley_pos - egui::Vec2::new(0., galley.size().y), galley.size()).expand(2.0);
+            painter.rect_filled(background, 2.0, Color32::from_black_alpha(160));
+            painter.galley(background.min + egui::Vec2::splat(2.0), galley, Color32::WHITE);
+        }
+    }
+}
+
+/// A scene point relative to the origin the renderer draws around.
+fn relative(point: [f64; 3], scene_origin: [f64; 3]) -> Vec3 {
+    Vec3::from(std::array::from_fn(|axis| (point[axis] - scene_origin[axis]) as f32))
+}
+
+/// Where a point in renderer coordinates lands in `rect`, or `None` behind the eye.
+pub fn to_screen(view_projection: Mat4, rect: Rect, point: Vec3) -> Option<Pos2> {
+    let clip = view_projection * point.extend(1.0);
+    if clip.w <= 0.0 {
+        return None;
+    }
+    let ndc = clip.truncate() / clip.w;
+    Some(rect.left_top() + egui::Vec2::new((ndc.x + 1.0) / 2.0 * rect.width(), (1.0 - ndc.y) / 2.0 * rect.height()))
+}
+
+/// One line per measurement: name, kind, value, unit and the points as `x y z` separated by `;`.
+pub fn to_csv(measurements: &[Measurement]) -> String {
+    let mut csv = String::from("name,kind,value,unit,points\n");
+    for m in measurements {
+        let points: Vec<String> = m.points.iter().map(|p| format!("{} {} {}", p[0], p[1], p[2])).collect();
+        let _ = writeln!(
+            csv,
+            "\"{}\",{},{},{},{}",
+            m.name.replace('"', "\"\""),
+            m.kind.label(),
+            m.value().map_or(String::new(), |v| v.to_string()),
+            m.kind.unit(),
+            points.join(";"),
+        );
+    }
+    csv
+}
+
+#[derive(serde::Serialize)]
+struct MeasurementRecord<'a> {
+    #[serde(flatten)]
+    measurement: &'a Measurement,
+    value: Option<f64>,
+    unit: &'static str,
+}
+
+pub fn to_json(measurements: &[Measurement]) -> Result<String, String> {
+    let records: Vec<MeasurementRecord<'_>> = measurements.iter()
+        .map(|measurement| MeasurementRecord { measurement, value: measurement.value(), unit: measurement.kind.unit() })
+        .collect();
+    serde_json::to_string_pretty(&records).map_err(|e| format!("Failed to encode measurements: {}", e))
+}
+
+/// Writes the measurements as JSON if `path` ends in `.json`, otherwise as CSV.
+pub fn export(path: &str, measurements: &[Measurement]) -> Result<(), String> {
+    let text = if path.to_ascii_lowercase().ends_with(".json") {
+        to_json(measurements)?
+    } else {
+        to_csv(measurements)
+    };
+    std::fs::write(path, text).map_err(|e| format!("Failed to write file: {}", e))
+}
+
+/// The measurements panel: the one in progress, the finished ones, and export.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Measurements {
+    pub list: Vec<Measurement>,
+    /// Being picked; not in `list` yet
+    #[serde(skip)]
+    pub current: Option<Measurement>,
+    #[serde(skip)]
+    pub export_path: String,
+    #[serde(skip)]
+    pub error: Option<String>,
+    next_number: usize,
+}
+
+impl Default for Measurements {
+    fn default() -> Self {
+        Self {
+            list: Vec::new(),
+            current: None,
+            export_path: "./measurements.csv".to_string(),
+            error: None,
+            next_number: 0,
+        }
+    }
+}
+
+impl Measurements {
+    /// Adds a picked point to the measurement in progress, starting one of `kind` if needed.
+    pub fn add_point(&mut self, kind: MeasureKind, point: [f64; 3]) {
+        if self.current.as_ref().is_some_and(|m| m.kind != kind) {
+            self.current = None;
+        }
+        let current = self.current.get_or_insert_with(|| {
+            self.next_number += 1;
+            Measurement::new(kind, format!("{} {}", kind.label(), self.next_number))
+        });
+        if current.push(point) {
+            self.finish();
+        }
+    }
+
+    /// Keeps the measurement in progress if it has enough points, drops it otherwise.
+    pub fn finish(&mut self) {
+        if let Some(current) = self.current.take() {
+            if current.is_complete() {
+                self.list.push(current);
+            }
+        }
+    }
+
+    pub fn paint(&self, painter: &egui::Painter, rect: Rect, view_projection: Mat4, scene_origin: [f64; 3]) {
+        for m in &self.list {
+            m.paint(painter, rect, view_projection, scene_origin, Color32::from_rgb(255, 200, 0));
+        }
+        if let Some(current) = &self.current {
+            current.paint(painter, rect, view_projection, scene_origin, Color32::from_rgb(0, 220, 255));
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut Ui) {
+        if let Some(current) = &self.current {
+            let text = format!("{} ({} points)", current.label(), current.points.len());
+            let can_finish = current.is_complete();
+            ui.horizontal(|ui| {
+                ui.label(text);
+                if ui.add_enabled(can_finish, egui::Button::new("Finish")).clicked() {
+                    self.finish();
+                }
+                if ui.button("Cancel").clicked() {
+                    self.current = None;
+                }
+            });
+            ui.separator();
+        }
+
+        if self.list.is_empty() {
+            ui.label("Pick points with a measuring tool to add measurements");
+        }
+        let mut removed = None;
+        egui::Grid::new("measurements").num_columns(3).striped(true).show(ui, |ui| {
+            for (i, m) in self.list.iter_mut().enumerate() {
+                ui.add(egui::TextEdit::singleline(&mut m.name).desired_width(120.0));
+                ui.monospace(match (m.value(), m.kind) {
+                    (Some(v), MeasureKind::Angle) => format!("{:.2}°", v),
+                    (Some(v), _) => format!("{:.3}", v),
+                    (None, _) => String::new(),
+                });
+                if ui.small_button("Remove").clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = removed {
+            self.list.remove(i);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.export_path).hint_text("measurements.csv or .json"));
+            if ui.add_enabled(!self.list.is_empty(), egui::Button::new("Export")).clicked() {
+                self.error = export(&self.export_path, &self.list)
+                    .err()
+                    .map(|e| format!("Failed to export {}: {}", self.export_path, e));
+            }
+            if ui.add_enabled(!self.list.is_empty(), egui::Button::new("Clear")).clicked() {
+                self.list.clear();
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(kind: MeasureKind, points: &[[f64; 3]]) -> Measurement {
+        let mut m = Measurement::new(kind, kind.label().to_string());
+        for &p in points {
+            m.push(p);
+        }
+        m
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn distance_polyline_and_height() {
+        let path = [[0.0, 0.0, 0.0], [3.0, 4.0, 0.0], [3.0, 4.0, -12.0]];
+        assert_eq!(measurement(MeasureKind::Distance, &path[..2]).value(), Some(5.0));
+        assert_eq!(measurement(MeasureKind::Polyline, &path).value(), Some(17.0));
+        assert_eq!(measurement(MeasureKind::Height, &path[1..]).value(), Some(-12.0));
+    }
+
+    #[test]
+    fn area_of_a_planar_concave_polygon() {
+        // A 10 x 10 square with a 5 x 5 notch, far from the origin
+        let [x, y, z] = [512_000.0, 6_123_000.0, 88.0];
+        let outline = [(0.0, 0.0), (10.0, 0.0), (10.0, 5.0), (5.0, 5.0), (5.0, 10.0), (0.0, 10.0)]
+            .map(|(dx, dy)| [x + dx, y + dy, z]);
+        assert!(close(measurement(MeasureKind::Area, &outline).value().unwrap(), 75.0));
+        // Winding the other way does not change it
+        let reversed: Vec<_> = outline.iter().rev().copied().collect();
+        assert!(close(measurement(MeasureKind::Area, &reversed).value().unwrap(), 75.0));
+    }
+
+    #[test]
+    fn area_of_a_tilted_polygon() {
+        // A 4 x 3√2 rectangle on a plane at 45° to the ground
+        let outline = [[0.0, 0.0, 0.0], [4.0, 0.0, 0.0], [4.0, 3.0, 3.0], [0.0, 3.0, 3.0]];
+        assert!(close(measurement(MeasureKind::Area, &outline).value().unwrap(), 12.0 * 2f64.sqrt()));
+    }
+
+    #[test]
+    fn angle_at_the_middle_point() {
+        let right = measurement(MeasureKind::Angle, &[[5.0, 0.0, 1.0], [0.0, 0.0, 1.0], [0.0, 0.0, 3.0]]);
+        assert!(close(right.value().unwrap(), 90.0));
+        let straight = measurement(MeasureKind::Angle, &[[-1.0, 0.0, 0.0], [0.0, 0.0, 0.0], [2.0, 0.0, 0.0]]);
+        assert!(close(straight.value().unwrap(), 180.0));
+    }
+
+    #[test]
+    fn incomplete_measurements_have_no_value() {
+        let mut area = measurement(MeasureKind::Area, &[[0.0; 3], [1.0, 0.0, 0.0]]);
+        // A double click adds its point once
+        assert!(!area.push([1.0, 0.0, 0.0]));
+        assert_eq!(area.points.len(), 2);
+        assert_eq!(area.value(), None);
+        assert_eq!(area.label(), "Area: ...");
+
+        let mut distance = Measurement::new(MeasureKind::Distance, "d".into());
+        assert!(!distance.push([0.0; 3]));
+        assert!(distance.push([1.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn csv_export() {
+        let mut distance = measurement(MeasureKind::Distance, &[[0.0, 0.0, 0.0], [3.0, 4.0, 0.5]]);
+        distance.name = "Kerb \"north\"".into();
+        let open = measurement(MeasureKind::Angle, &[[1.0, 2.0, 3.0]]);
+
+        assert_eq!(
+            to_csv(&[distance, open]),
+            "name,kind,value,unit,points\n\
+             \"Kerb \"\"north\"\"\",Distance,5.024937810560445,length,0 0 0;3 4 0.5\n\
+             \"Angle\",Angle,,degrees,1 2 3\n",
+        );
+    }
+
+    #[test]
+    fn json_export() {
+        let area = measurement(MeasureKind::Area, &[[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]]);
+        let open = measurement(MeasureKind::Height, &[[0.0, 0.0, 1.0]]);
+        let json: serde_json::Value = serde_json::from_str(&to_json(&[area, open]).unwrap()).unwrap();
+
+        assert_eq!(json, serde_json::json!([
+            {
+                "name": "Area",
+                "kind": "Area",
+                "points": [[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [0.0, 2.0, 0.0]],
+                "value": 2.0,
+                "unit": "area",
+            },
+            {
+                "name": "Height",
+                "kind": "Height",
+                "points": [[0.0, 0.0, 1.0]],
+                "value": null,
+                "unit": "length",
+            },
+        ]));
+    }
+}
@@ -1,2 +1,9 @@
+pub mod bookmarks;
+pub mod camera_path;
+pub mod clipping;
+pub mod layers;
+pub mod measurements;
 pub mod pipeline_editor;
-pub mod point_cloud_renderer;
\ No newline at end of file
+pub mod point_cloud_renderer;
+pub mod selection;
+pub mod shading;
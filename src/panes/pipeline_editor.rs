@@ -1,8 +1,10 @@
-use eframe::emath::Rect;
 use crate::pane_manager::{Pane, PaneMode, PaneState, PsudoCreationContext};
+use crate::nodes::executor::{self, ExecutionReport, NodeStatus};
+use crate::nodes::validation::{self, ValidationReport};
+use crate::nodes::value::{PinType, Value};
 
 
-use egui::{Color32, Id, Pos2, Ui};
+use egui::{Color32, Id, Pos2, Rect, Stroke, Ui};
 use egui_snarl::{ui::{PinInfo, SnarlStyle, SnarlViewer}, InPin, InPinId, NodeId, OutPin, OutPinId, Snarl};
 use egui_snarl::ui::{WireStyle};
 
@@ -11,6 +13,12 @@ pub struct PipelinePane {
     snarl: Option<Snarl<Box<dyn Node>>>,
     style: Option<SnarlStyle>,
     snarl_ui_id: Option<Id>,
+    #[serde(skip)]
+    last_run: Option<ExecutionReport>,
+    #[serde(skip)]
+    last_error: Option<String>,
+    #[serde(skip)]
+    connect_error: Option<String>,
 }
 #[typetag::serde]
 impl Pane for PipelinePane {
@@ -22,6 +30,9 @@ impl Pane for PipelinePane {
             snarl: Some(Snarl::new()),
             style: Some(SnarlStyle::new()),
             snarl_ui_id: None,
+            last_run: None,
+            last_error: None,
+            connect_error: None,
         };
         PaneState {
             id: s.name().to_string(),
@@ -36,9 +47,34 @@ impl Pane for PipelinePane {
     fn render(&mut self, ui: &mut Ui) {
         self.snarl_ui_id = Some(ui.id());
 
+        if let Some(error) = &self.last_error {
+            ui.colored_label(Color32::RED, error);
+        } else if let Some(report) = &self.last_run {
+            ui.label(format!("Last run: {} nodes, {} failed", report.order.len(), report.failed()));
+        }
+        if let Some(error) = &self.connect_error {
+            ui.colored_label(Color32::YELLOW, error);
+        }
+
         if let Some(snarl) = &mut self.snarl {
+            let validation = validation::validate(snarl);
+            if !validation.issues.is_empty() {
+                let title = format!("{} errors, {} warnings", validation.errors(), validation.warnings());
+                ui.collapsing(title, |ui| {
+                    for issue in &validation.issues {
+                        let color = if issue.is_error() { Color32::RED } else { Color32::YELLOW };
+                        ui.colored_label(color, validation.describe(issue, snarl));
+                    }
+                });
+            }
+
             if let Some(style) = &self.style {
-                snarl.show(&mut NodeViewer, style, "snarl", ui);
+                let mut viewer = NodeViewer {
+                    report: self.last_run.as_ref(),
+                    validation: &validation,
+                    connect_error: &mut self.connect_error,
+                };
+                snarl.show(&mut viewer, style, "snarl", ui);
             }
         }
 
@@ -64,72 +100,16 @@ impl Pane for PipelinePane {
 impl PipelinePane {
     fn run(&mut self) {
         if let Some(snarl) = &mut self.snarl {
-            fn remove_duplicates(nodes: Vec<NodeId>) -> Vec<NodeId> {
-                let mut new_vec : Vec<NodeId> = Vec::new();
-                for node in nodes {
-                    if !new_vec.contains(&node){
-                        new_vec.push(node);
-                    }
+            match executor::execute(snarl) {
+                Ok(report) => {
+                    self.last_run = Some(report);
+                    self.last_error = None;
                 }
-                new_vec
-            }
-            fn has_input_wire(snarl: &Snarl<Box<dyn Node>>, nodeid: NodeId) -> bool {
-                for wire in snarl.wires() {
-                    if wire.1.node == nodeid {
-                        return true;
-                    }
+                Err(e) => {
+                    self.last_run = None;
+                    self.last_error = Some(e);
                 }
-                false
             }
-            fn get_output_wires(snarl: &Snarl<Box<dyn Node>>, nodeid: &NodeId) -> Vec<OutPinId> {
-                let mut arr: Vec<OutPinId> = Vec::new();
-                for wire in snarl.wires() {
-                    if &wire.0.node == nodeid {
-                        arr.push(wire.0)
-                    }
-                }
-                arr
-            }
-
-            // let wires = snarl.wires().map(|| {})
-
-            let mut nodes: Vec<Vec<NodeId>> = Vec::new();
-            let mut starting_nodes: Vec<NodeId> = Vec::new();
-            for node in snarl.nodes_ids_data() {
-                if !has_input_wire(snarl, node.0) {
-                    starting_nodes.push(node.0.clone())
-                }
-            }
-            starting_nodes = remove_duplicates(starting_nodes);
-            nodes.push(starting_nodes);
-
-
-            for i in 1..50 {
-                if nodes.get(i-1).is_none() {break}
-                let mut prevarr = nodes.get(i-1).unwrap();
-                if prevarr.len() == 0 {break}
-
-                let mut newarr: Vec<NodeId> = Vec::new();
-
-                for node in prevarr {
-                    for wire in get_output_wires(snarl, node) {
-                        newarr.push(wire.node);
-                    }
-                }
-
-                newarr = remove_duplicates(newarr);
-
-                nodes.push(newarr);
-            }
-
-            for nodearr in nodes {
-                println!("Nodes: ");
-                for node in nodearr {
-                    println!("{}", snarl.get_node(node).unwrap().get_name());
-                }
-            }
-
-
         }
     }
 }
@@ -139,6 +119,13 @@ fn format_float(v: f64) -> String {
     format!("{}", v)
 }
 
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Scalar(v) => format_float(*v),
+        other => other.to_string(),
+    }
+}
+
 #[typetag::serde(tag = "type")]
 pub trait Node {
     fn new() -> Self
@@ -151,10 +138,15 @@ pub trait Node {
     fn outputs(&self) -> usize;
     fn show_input(&mut self, pin: &InPin, ui: &mut Ui, scale: f32) -> PinInfo;
     fn show_output(&mut self, pin: &OutPin, ui: &mut Ui, scale: f32) -> PinInfo;
-    fn can_rx(&self, other: &Box<dyn Node>) -> bool;
-    fn can_tx(&self, other: &Box<dyn Node>) -> bool;
+    fn input_type(&self, input: usize) -> PinType;
+    fn output_type(&self, output: usize) -> PinType;
+    /// Whether the pipeline may run with nothing wired into this input.
+    fn input_required(&self, input: usize) -> bool;
     fn context_menu(&mut self, ui: &mut Ui);
     fn update(&mut self, ui: &mut Ui);
+    /// Computes one value per output pin. `inputs` has one slot per input pin,
+    /// `None` where nothing is wired in.
+    fn evaluate(&mut self, inputs: &[Option<Value>]) -> Result<Vec<Value>, String>;
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
@@ -174,15 +166,21 @@ impl Node for Node1 {
         1
     }
     fn show_input(&mut self, _pin: &InPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square() }
-    fn show_output(&mut self, _pin: &OutPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square().with_fill(Color32::RED).with_wire_style(WireStyle::Bezier3) }
-    fn can_rx(&self, _other: &Box<dyn Node>) -> bool {
-        true
+    fn show_output(&mut self, _pin: &OutPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square().with_wire_style(WireStyle::Bezier3) }
+    fn input_type(&self, _input: usize) -> PinType {
+        PinType::Any
+    }
+    fn output_type(&self, _output: usize) -> PinType {
+        PinType::Any
     }
-    fn can_tx(&self, _other: &Box<dyn Node>) -> bool {
+    fn input_required(&self, _input: usize) -> bool {
         true
     }
     fn context_menu(&mut self, ui: &mut Ui) { ui.label("Test!"); }
-    fn update(&mut self, ui: &mut Ui) {}
+    fn update(&mut self, _ui: &mut Ui) {}
+    fn evaluate(&mut self, inputs: &[Option<Value>]) -> Result<Vec<Value>, String> {
+        Ok(vec![inputs[0].clone().ok_or("Input not connected")?])
+    }
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
@@ -202,15 +200,25 @@ impl Node for Node2 {
         1
     }
     fn show_input(&mut self, _pin: &InPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square() }
-    fn show_output(&mut self, _pin: &OutPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square().with_fill(Color32::RED).with_wire_style(WireStyle::Bezier3) }
-    fn can_rx(&self, _other: &Box<dyn Node>) -> bool {
-        true
+    fn show_output(&mut self, _pin: &OutPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square().with_wire_style(WireStyle::Bezier3) }
+    fn input_type(&self, _input: usize) -> PinType {
+        PinType::Any
     }
-    fn can_tx(&self, _other: &Box<dyn Node>) -> bool {
+    fn output_type(&self, _output: usize) -> PinType {
+        PinType::Any
+    }
+    fn input_required(&self, _input: usize) -> bool {
         true
     }
     fn context_menu(&mut self, ui: &mut Ui) { ui.label("Test!"); }
-    fn update(&mut self, ui: &mut Ui) {}
+    fn update(&mut self, _ui: &mut Ui) {}
+    fn evaluate(&mut self, inputs: &[Option<Value>]) -> Result<Vec<Value>, String> {
+        match (&inputs[0], &inputs[1]) {
+            (Some(Value::Scalar(a)), Some(Value::Scalar(b))) => Ok(vec![Value::Scalar(a + b)]),
+            (Some(a), Some(b)) => Ok(vec![Value::Text(a.to_string() + &b.to_string())]),
+            _ => Err("Both inputs must be connected".to_string()),
+        }
+    }
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
@@ -230,38 +238,58 @@ impl Node for crate::panes::pipeline_editor::Node3 {
         2
     }
     fn show_input(&mut self, _pin: &InPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square() }
-    fn show_output(&mut self, _pin: &OutPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square().with_fill(Color32::RED).with_wire_style(WireStyle::Bezier3) }
-    fn can_rx(&self, _other: &Box<dyn Node>) -> bool {
-        true
+    fn show_output(&mut self, _pin: &OutPin, _ui: &mut Ui, _scale: f32) -> PinInfo { PinInfo::square().with_wire_style(WireStyle::Bezier3) }
+    fn input_type(&self, _input: usize) -> PinType {
+        PinType::Any
     }
-    fn can_tx(&self, _other: &Box<dyn Node>) -> bool {
+    fn output_type(&self, _output: usize) -> PinType {
+        PinType::Any
+    }
+    fn input_required(&self, _input: usize) -> bool {
         true
     }
     fn context_menu(&mut self, ui: &mut Ui) { ui.label("Test!"); }
-    fn update(&mut self, ui: &mut Ui) {}
+    fn update(&mut self, _ui: &mut Ui) {}
+    fn evaluate(&mut self, inputs: &[Option<Value>]) -> Result<Vec<Value>, String> {
+        let value = inputs[0].clone().ok_or("Input not connected")?;
+        Ok(vec![value.clone(), value])
+    }
 }
 
 
-#[derive(Clone, serde::Serialize, serde::Deserialize)]
-struct NodeViewer;
+struct NodeViewer<'a> {
+    report: Option<&'a ExecutionReport>,
+    validation: &'a ValidationReport,
+    /// Why the last attempted connection was refused, shown above the graph.
+    connect_error: &'a mut Option<String>,
+}
 
-impl SnarlViewer<Box<dyn Node>> for NodeViewer {
+impl SnarlViewer<Box<dyn Node>> for NodeViewer<'_> {
     fn connect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Box<dyn Node>>) {
         // Validate connection
 
         let rx = snarl.get_node(to.id.node).unwrap();
         let tx = snarl.get_node(from.id.node).unwrap();
 
-        if rx.can_rx(tx) && tx.can_tx(rx) {
+        let rx_type = rx.input_type(to.id.input);
+        let tx_type = tx.output_type(from.id.output);
+
+        if rx_type.accepts(tx_type) {
             for &remote in &to.remotes {
                 snarl.disconnect(remote, to.id);
             }
 
             snarl.connect(from.id, to.id);
+            *self.connect_error = None;
+        } else {
+            *self.connect_error = Some(format!(
+                "Cannot connect {} output of \"{}\" to {} input of \"{}\"",
+                tx_type.name(), tx.get_name(), rx_type.name(), rx.get_name()
+            ));
         }
     }
 
-    fn disconnect(&mut self, from: &OutPin, to: &InPin, snarl: &mut Snarl<Box<dyn Node>>) {
+    fn disconnect(&mut self, _from: &OutPin, to: &InPin, snarl: &mut Snarl<Box<dyn Node>>) {
         for &remote in &to.remotes {
             snarl.disconnect(remote, to.id);
         }
@@ -286,10 +314,16 @@ impl SnarlViewer<Box<dyn Node>> for NodeViewer {
         scale: f32,
         snarl: &mut Snarl<Box<dyn Node>>,
     ) -> PinInfo {
-        snarl
-            .get_node_mut(pin.id.node)
-            .unwrap()
-            .show_input(pin, ui, scale)
+        let node = snarl.get_node_mut(pin.id.node).unwrap();
+        let pin_type = node.input_type(pin.id.input);
+        let mut info = node.show_input(pin, ui, scale).with_fill(pin_type.color());
+        if self.validation.input_missing(pin.id) {
+            info = info.with_stroke(Stroke::new(2.0 * scale, Color32::RED));
+        } else if pin.remotes.iter().any(|&remote| self.validation.wire_in_cycle(remote, pin.id)) {
+            info = info.with_fill(Color32::RED);
+        }
+        ui.label(egui::RichText::new(pin_type.name()).weak().small());
+        info
     }
 
     fn show_output(
@@ -299,10 +333,14 @@ impl SnarlViewer<Box<dyn Node>> for NodeViewer {
         scale: f32,
         snarl: &mut Snarl<Box<dyn Node>>,
     ) -> PinInfo {
-        snarl
-            .get_node_mut(pin.id.node)
-            .unwrap()
-            .show_output(pin, ui, scale)
+        let node = snarl.get_node_mut(pin.id.node).unwrap();
+        let pin_type = node.output_type(pin.id.output);
+        let mut info = node.show_output(pin, ui, scale).with_fill(pin_type.color());
+        if self.validation.output_in_cycle(pin.id) {
+            info = info.with_fill(Color32::RED);
+        }
+        ui.label(egui::RichText::new(pin_type.name()).weak().small());
+        info
     }
 
 
@@ -364,17 +402,53 @@ impl SnarlViewer<Box<dyn Node>> for NodeViewer {
         }
     }
 
-    fn has_body(&mut self, node: &Box<dyn Node>) -> bool {
+    fn has_body(&mut self, _node: &Box<dyn Node>) -> bool {
         true
     }
 
-    fn show_body(&mut self, node: NodeId, inputs: &[InPin], outputs: &[OutPin], ui: &mut Ui, scale: f32, snarl: &mut Snarl<Box<dyn Node>>) {
+    fn show_body(&mut self, node: NodeId, _inputs: &[InPin], _outputs: &[OutPin], ui: &mut Ui, _scale: f32, snarl: &mut Snarl<Box<dyn Node>>) {
         snarl.get_node_mut(node).unwrap().update(ui);
     }
+
+    fn final_node_rect(&mut self, node: NodeId, ui_rect: Rect, _graph_rect: Rect, ui: &mut Ui, scale: f32, _snarl: &mut Snarl<Box<dyn Node>>) {
+        let color = if self.validation.has_error(node) {
+            Color32::RED
+        } else if self.validation.is_dangling(node) {
+            Color32::YELLOW
+        } else {
+            return;
+        };
+        ui.painter().rect_stroke(ui_rect.expand(4.0 * scale), 6.0 * scale, Stroke::new(2.0 * scale, color));
+    }
+
+    fn has_wire_widget(&mut self, from: &OutPinId, to: &InPinId, _snarl: &Snarl<Box<dyn Node>>) -> bool {
+        self.validation.wire_in_cycle(*from, *to)
+    }
+
+    fn show_wire_widget(&mut self, _from: &OutPin, _to: &InPin, ui: &mut Ui, _scale: f32, _snarl: &mut Snarl<Box<dyn Node>>) {
+        ui.colored_label(Color32::RED, "cycle");
+    }
+
+    fn has_footer(&mut self, _node: &Box<dyn Node>) -> bool {
+        self.report.is_some()
+    }
+
+    fn show_footer(&mut self, node: NodeId, _inputs: &[InPin], _outputs: &[OutPin], ui: &mut Ui, _scale: f32, _snarl: &mut Snarl<Box<dyn Node>>) {
+        match self.report.and_then(|report| report.get(node)) {
+            Some(NodeStatus::Done(values)) => {
+                for (i, value) in values.iter().enumerate() {
+                    ui.label(format!("{}: {}", i, format_value(value)));
+                }
+            }
+            Some(NodeStatus::Failed(e)) => { ui.colored_label(Color32::RED, e); }
+            Some(NodeStatus::Skipped(e)) => { ui.colored_label(Color32::YELLOW, e); }
+            None => {}
+        }
+    }
 }
 
 
-impl NodeViewer {
+impl NodeViewer<'_> {
     pub fn add_node_menu(pos: Pos2, ui: &mut Ui, snarl: &mut Snarl<Box<dyn Node>>) {
         ui.label("Add node");
 
@@ -385,6 +459,15 @@ impl NodeViewer {
         } else if ui.button("Constants").clicked() {
             snarl.insert_node(pos, Box::new(crate::nodes::constants::Constants::new()));
             ui.close_menu();
+        } else if ui.button("Load Point Cloud").clicked() {
+            snarl.insert_node(pos, Box::new(crate::nodes::point_cloud::LoadPointCloud::new()));
+            ui.close_menu();
+        } else if ui.button("Save Point Cloud").clicked() {
+            snarl.insert_node(pos, Box::new(crate::nodes::point_cloud::SavePointCloud::new()));
+            ui.close_menu();
+        } else if ui.button("Attribute Stats").clicked() {
+            snarl.insert_node(pos, Box::new(crate::nodes::point_cloud::AttributeStats::new()));
+            ui.close_menu();
         } else if ui.button("Test 2-1").clicked() {
             snarl.insert_node(pos, Box::new(Node2::new()));
             ui.close_menu();
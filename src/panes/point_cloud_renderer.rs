@@ -3,11 +3,21 @@ use eframe::egui_glow;
 use egui_glow::glow;
 use std::sync::Arc;
 use glam::{Vec3, Mat4, Quat};
-use std::fs::File;
 // use std::path::Path;
-use std::io::{BufReader, BufRead};
+use crate::point_cloud::{self, pack_normal, ply, Format, LoadOptions, LoadProgress, PointCloud};
+use crate::panes::bookmarks::Bookmarks;
+use crate::panes::clipping::{ClipVolume, Clipping};
+use crate::panes::camera_path::{CameraPath, PathEvent, PathExport};
+use crate::panes::layers::{self, ColorMode, Layer, PickedPoint, ScalarField};
+use crate::panes::measurements::{self, MeasureKind, Measurements};
+use crate::panes::selection::{Combine, SelectShape, Selection};
+use crate::panes::shading::{LightingModel, LightingSettings, ScreenPass, ShadingSettings};
+use crate::point_cloud::cache::{self, CacheColoring, CacheReader};
+use crate::point_cloud::octree::{box_in_frustum, frustum_planes, LodView, Octree};
+use crate::point_cloud::ramp::{self, ColorRamp, RAMP_SAMPLES};
+use std::collections::HashMap;
 use crate::pane_manager::{Pane, PaneMode, PaneState, PsudoCreationContext};
-use std::sync::Mutex;
+use std::sync::{mpsc, Mutex};
 use egui::FontId;
 use egui::Align2;
 // use egui::Pos2;
@@ -15,53 +25,325 @@ use std::time::Instant;
 use egui::Stroke;
 use egui::Ui;
 
-// Shader sources updated for 3D rendering with fixed-point positions
-const VERTEX_SHADER: &str = r#"
+/// Clip volume uniforms and the test against them, shared by the point and glyph shaders.
+macro_rules! clip_glsl {
+    () => {
+        r#"
+    uniform int u_clip_plane_count;
+    uniform vec4 u_clip_planes[4];    // MAX_CLIP_PLANES; kept where dot(xyz, world) + w >= 0
+    uniform int u_clip_box;           // 0 none, 1 keeps the inside, 2 keeps the outside
+    uniform mat4 u_clip_box_inverse;  // Scene to box coordinates, where the box spans -1 to 1
+
+    bool is_clipped(vec3 world) {
+        bool clipped = false;
+        for (int i = 0; i < u_clip_plane_count; i++) {
+            clipped = clipped || dot(u_clip_planes[i].xyz, world) + u_clip_planes[i].w < 0.0;
+        }
+        if (u_clip_box != 0) {
+            vec3 b = abs((u_clip_box_inverse * vec4(world, 1.0)).xyz);
+            bool inside = max(b.x, max(b.y, b.z)) <= 1.0;
+            clipped = clipped || inside != (u_clip_box == 1);
+        }
+        return clipped;
+    }
+"#
+    };
+}
+
+// Shader sources for 3D rendering of positions relative to the cloud origin
+const VERTEX_SHADER: &str = concat!(r#"
     #version 330 core
-    layout (location = 0) in ivec3 position;  // Using unsigned ints for position
-    layout (location = 1) in ivec4 color;     // Using unsigned ints for color
+    layout (location = 0) in vec3 position;  // Relative to the cloud origin
+    layout (location = 1) in vec4 color;     // Normalised from unsigned bytes
+    layout (location = 2) in float scalar;   // The same bytes as colour, for layers uploaded with a scalar field
+    layout (location = 3) in uint flags;     // Bit 0: selected
+    layout (location = 4) in vec4 normal;    // Unit length in layer coordinates, or zero for none
     
     uniform mat4 u_view_projection;
-    uniform float u_position_scale;  // Scale factor to convert from uint to world space
+    uniform mat4 u_model;
+    uniform mat4 u_view;
+    uniform vec3 u_eye;
     uniform float u_point_size_scale;  // Added point size scaling
+    uniform float u_fixed_size;        // Depth factor of every point in orthographic views, 0 in perspective
 
+    uniform int u_scalar_source;  // 0 stored colour, 1 elevation, 2 distance to camera, 3 per-point scalar
+    uniform vec2 u_scalar_range;
+    uniform sampler2D u_ramps;
+    uniform float u_ramp_row;
+"#, clip_glsl!(), r#"
     out vec4 v_color;
+    out vec3 v_normal;       // In scene coordinates, zero for none
+    out vec3 v_view_normal;  // In view coordinates, zero for none
+    out vec3 v_to_eye;
     
     void main() {
-        // Convert uint positions to world space
-        vec3 worldPos = vec3(position) * u_position_scale;
-        gl_Position = u_view_projection * vec4(worldPos, 1.0);
-        gl_PointSize = max(u_point_size_scale * 10.0 * (1.0 - gl_Position.z / gl_Position.w), 1.0);
-        v_color = vec4(color) / 255.0;  // Convert uint colors to float
+        vec3 world = (u_model * vec4(position, 1.0)).xyz;
+        if (is_clipped(world)) {
+            // Outside the clip volume, so never drawn
+            gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+            v_color = vec4(0.0);
+            v_normal = vec3(0.0);
+            v_view_normal = vec3(0.0);
+            v_to_eye = vec3(0.0);
+            return;
+        }
+
+        bool has_normal = dot(normal.xyz, normal.xyz) > 0.25;
+        v_normal = has_normal ? normalize(mat3(u_model) * normal.xyz) : vec3(0.0);
+        v_view_normal = mat3(u_view) * v_normal;
+        v_to_eye = u_eye - world;
+
+        gl_Position = u_view_projection * vec4(position, 1.0);
+        // w is the depth in perspective; 0.1 is POINT_SIZE_DEPTH
+        float depth_factor = u_fixed_size > 0.0 ? u_fixed_size : 0.1 / gl_Position.w;
+        gl_PointSize = max(u_point_size_scale * 10.0 * depth_factor, 1.0);
+
+        if (u_scalar_source == 0) {
+            v_color = color;
+        } else {
+            float value = u_scalar_source == 1 ? world.z
+                        : u_scalar_source == 2 ? distance(world, u_eye)
+                        : scalar;
+            float t = (value - u_scalar_range.x) / max(u_scalar_range.y - u_scalar_range.x, 1e-6);
+            v_color = textureLod(u_ramps, vec2(clamp(t, 0.0, 1.0), u_ramp_row), 0.0);
+        }
+
+        if ((flags & 1u) != 0u) {
+            v_color = vec4(mix(v_color.rgb, vec3(1.0, 0.8, 0.1), 0.75), v_color.a);
+        }
     }
-"#;
+"#);
 
 const FRAGMENT_SHADER: &str = r#"
     #version 330 core
     in vec4 v_color;
+    in vec3 v_normal;
+    in vec3 v_view_normal;
+    in vec3 v_to_eye;
     out vec4 FragColor;
+
+    uniform vec4 u_tint;  // Layer tint, alpha is the layer opacity
+    uniform int u_lighting;  // 0 off, 1 Lambert, 2 Phong
+    uniform float u_ambient;
+    uniform float u_specular;
+    uniform float u_shininess;
+    uniform bool u_splats;
     
     void main() {
-        // Create circular points
-        vec2 coord = gl_PointCoord * 2.0 - 1.0;
+        // Create circular points, with y up as in view coordinates
+        vec2 coord = vec2(gl_PointCoord.x, 1.0 - gl_PointCoord.y) * 2.0 - 1.0;
+        bool has_normal = dot(v_normal, v_normal) > 0.25;
         float r = dot(coord, coord);
+        if (u_splats && has_normal) {
+            // The disc in the tangent plane seen from the front is an ellipse; keep it from
+            // thinning out to nothing when seen edge on
+            vec3 n = normalize(v_view_normal);
+            float nz = max(abs(n.z), 0.3);
+            float dz = dot(n.xy, coord) / nz;
+            r += dz * dz;
+        }
         if (r > 1.0) discard;
-        // if (coord.x > 1.0) discard;
-        // if (coord.y > 1.0) discard;
-        
-        // Apply simple lighting based on depth
-        // float depth = gl_FragCoord.z;
-        FragColor = v_color;
+
+        vec4 color = v_color;
+        if (u_lighting != 0 && has_normal) {
+            // Light from the eye, on whichever side of the point faces it
+            vec3 to_eye = normalize(v_to_eye);
+            vec3 n = normalize(v_normal);
+            if (dot(n, to_eye) < 0.0) n = -n;
+            float diffuse = max(dot(n, to_eye), 0.0);
+            color.rgb *= u_ambient + (1.0 - u_ambient) * diffuse;
+            if (u_lighting == 2) {
+                float highlight = max(dot(reflect(-to_eye, n), to_eye), 0.0);
+                color.rgb += u_specular * pow(highlight, u_shininess);
+            }
+        }
+        FragColor = color * u_tint;
+    }
+"#;
+
+// Draws each point's normal as a line from the point, coloured by direction
+const GLYPH_VERTEX_SHADER: &str = concat!(r#"
+    #version 330 core
+    layout (location = 0) in vec3 position;
+    layout (location = 4) in vec4 normal;
+
+    uniform mat4 u_model;
+"#, clip_glsl!(), r#"
+    out vec3 v_world;
+    out vec3 v_normal;  // Zero where no glyph is drawn
+
+    void main() {
+        v_world = (u_model * vec4(position, 1.0)).xyz;
+        bool has_normal = dot(normal.xyz, normal.xyz) > 0.25;
+        v_normal = has_normal && !is_clipped(v_world) ? normalize(mat3(u_model) * normal.xyz) : vec3(0.0);
+    }
+"#);
+
+const GLYPH_GEOMETRY_SHADER: &str = r#"
+    #version 330 core
+    layout (points) in;
+    layout (line_strip, max_vertices = 2) out;
+
+    in vec3 v_world[];
+    in vec3 v_normal[];
+    out vec3 g_color;
+
+    uniform mat4 u_scene_view_projection;
+    uniform float u_glyph_length;  // In scene units
+
+    void main() {
+        if (dot(v_normal[0], v_normal[0]) < 0.25) return;
+        g_color = v_normal[0] * 0.5 + 0.5;
+        gl_Position = u_scene_view_projection * vec4(v_world[0], 1.0);
+        EmitVertex();
+        gl_Position = u_scene_view_projection * vec4(v_world[0] + v_normal[0] * u_glyph_length, 1.0);
+        EmitVertex();
+        EndPrimitive();
+    }
+"#;
+
+const GLYPH_FRAGMENT_SHADER: &str = r#"
+    #version 330 core
+    in vec3 g_color;
+    out vec4 FragColor;
+
+    uniform vec4 u_tint;
+
+    void main() {
+        FragColor = vec4(g_color, 1.0) * u_tint;
     }
 "#;
 
+/// How the camera follows the mouse and keyboard.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    /// Turntable around the pivot, with scene z kept up
+    #[default]
+    Orbit,
+    /// Looks around from the eye and moves with WASD, Q and E
+    Fly,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 2] = [CameraMode::Orbit, CameraMode::Fly];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CameraMode::Orbit => "Orbit",
+            CameraMode::Fly => "Fly",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Parallel lines stay parallel, for plans and elevations
+    Orthographic,
+}
+
+/// Fixed directions to look at the scene from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewPreset {
+    /// Straight down the z axis
+    Top,
+    /// Along +y
+    Front,
+    /// Along -x
+    Side,
+}
+
+impl ViewPreset {
+    pub const ALL: [ViewPreset; 3] = [ViewPreset::Top, ViewPreset::Front, ViewPreset::Side];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ViewPreset::Top => "Top",
+            ViewPreset::Front => "Front",
+            ViewPreset::Side => "Side",
+        }
+    }
+}
+
+/// Where the camera looks from, without its mode and settings; what transitions
+/// and bookmarks move between.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraView {
+    /// Pivot in scene coordinates
+    pub position: Vec3,
+    pub orientation: Quat,
+    pub distance: f32,
+    pub projection: Projection,
+}
+
+impl Default for CameraView {
+    /// Looking down on the scene origin.
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            orientation: Quat::IDENTITY,
+            distance: 10.0,
+            projection: Projection::Perspective,
+        }
+    }
+}
+
+impl CameraView {
+    /// The same view, moved and zoomed so the box from `min` to `max` fills it.
+    pub fn fitted(self, min: Vec3, max: Vec3) -> Self {
+        let radius = (max - min).length().max(1e-3) * 0.5;
+        // Fits the bounding sphere with a little margin
+        let distance = radius * 1.1 / (FOV.to_radians() / 2.0).sin();
+        Self { position: (min + max) * 0.5, distance, ..self }
+    }
+}
+
+/// Orientation turned `yaw` about scene z and tilted `tilt` up from looking straight down.
+/// Identity looks down -z with y up, so a tilt of 0 is the top view.
+pub fn turntable(yaw: f32, tilt: f32) -> Quat {
+    (Quat::from_rotation_z(yaw) * Quat::from_rotation_x(tilt)).normalize()
+}
+
+/// Yaw and tilt of `orientation`, as `turntable` takes them; any roll is dropped.
+pub fn yaw_tilt(orientation: Quat) -> (f32, f32) {
+    let right = orientation * Vec3::X;
+    let up = orientation * Vec3::Y;
+    let forward = orientation * -Vec3::Z;
+    (right.y.atan2(right.x), up.z.atan2(-forward.z).clamp(0.0, std::f32::consts::PI))
+}
+
+/// Seconds a move to another view takes.
+const TRANSITION_SECONDS: f32 = 0.6;
+
+/// A move between two views, animated over `TRANSITION_SECONDS`.
+#[derive(Clone)]
+struct Transition {
+    from: CameraView,
+    to: CameraView,
+    started: Instant,
+}
+
 // Camera controller for 3D navigation
 #[derive(Clone)]
 pub struct Camera {
+    /// Point orbited around, `distance` in front of the eye
     position: Vec3,
+    /// Turn about scene z, in radians
+    yaw: f32,
+    /// Tilt up from looking straight down, from 0 to pi
+    tilt: f32,
+    /// Follows `yaw` and `tilt`
     pub orientation: Quat,
     distance: f32,
     pub point_size_scale: f32,
+    pub mode: CameraMode,
+    pub projection: Projection,
+    /// Scene units per second when flying
+    pub fly_speed: f32,
+    transition: Option<Transition>,
+    /// Bounding sphere of what is shown, centre and radius, which the clip range is fitted to
+    scene: Option<(Vec3, f32)>,
     // last_pos: Option<Pos2>,
 }
 
@@ -69,38 +351,89 @@ pub struct Camera {
 impl Camera {
     pub fn new() -> Self {
         Self {
-            position: Vec3::new(0.0, 0.0, 5.0),
+            position: Vec3::ZERO,
+            yaw: 0.0,
+            tilt: 0.0,
             orientation: Quat::IDENTITY,
-            distance: 5.0,
+            distance: 10.0,
             point_size_scale: 0.1,
+            mode: CameraMode::Orbit,
+            projection: Projection::Perspective,
+            fly_speed: 1.0,
+            transition: None,
+            scene: None,
             // last_pos: None,
         }
     }
 
-    // pub fn reset(&mut self) {
-    //     self.position = Vec3::new(0.0, 0.0, 5.0);
-    //     self.orientation = Quat::IDENTITY;
-    //     self.distance = 5.0;
-    //     // self.point_size_scale = 0.1;
-    //     self.update_view();
-    // }
+    pub fn view(&self) -> CameraView {
+        CameraView {
+            position: self.position,
+            orientation: self.orientation,
+            distance: self.distance,
+            projection: self.projection,
+        }
+    }
+
+    /// Starts a smooth move to `view`.
+    pub fn go_to(&mut self, view: CameraView) {
+        self.transition = Some(Transition { from: self.view(), to: view, started: Instant::now() });
+    }
+
+    pub fn is_animating(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// Moves along the current transition; returns whether the view changed.
+    pub fn animate(&mut self) -> bool {
+        let Some(Transition { from, to, started }) = self.transition.clone() else {
+            return false;
+        };
+        let t = (started.elapsed().as_secs_f32() / TRANSITION_SECONDS).min(1.0);
+        // Eases in and out
+        let t = t * t * (3.0 - 2.0 * t);
+        self.position = from.position.lerp(to.position, t);
+        self.distance = from.distance + (to.distance - from.distance) * t;
+        self.set_orientation(from.orientation.slerp(to.orientation, t));
+        self.projection = to.projection;
+        if t >= 1.0 {
+            self.transition = None;
+        }
+        true
+    }
+
+    /// Jumps straight to `view`, ending any transition.
+    pub fn set_view(&mut self, view: CameraView) {
+        self.transition = None;
+        self.position = view.position;
+        self.distance = view.distance;
+        self.projection = view.projection;
+        self.set_orientation(view.orientation);
+    }
 
-    pub fn update(&mut self, i: InputState) {
+    /// Takes the yaw and tilt of `orientation`, dropping any roll.
+    fn set_orientation(&mut self, orientation: Quat) {
+        (self.yaw, self.tilt) = yaw_tilt(orientation);
+        self.update_view();
+    }
+
+    /// Returns whether the view changed.
+    pub fn update(&mut self, i: InputState) -> bool {
             let mut changed = false;
             
-            if i.pointer.secondary_down() && !i.modifiers.shift {
+            if i.pointer.secondary_down() && !i.modifiers.shift && i.pointer.delta() != egui::Vec2::ZERO {
                 let delta = i.pointer.delta();
                 
                 let rotation_speed = 0.01;
-                let pitch = delta.y * rotation_speed;
-                let yaw = delta.x * rotation_speed;
-                
-                let pitch_rotation = Quat::from_axis_angle(Vec3::X, -pitch);
-                let yaw_rotation = Quat::from_axis_angle(Vec3::Y, -yaw);
-                let roll_rotation = Quat::from_axis_angle(Vec3::Z, 0.);
-                
-                self.orientation = self.orientation * pitch_rotation * yaw_rotation * roll_rotation;
-                self.orientation = self.orientation.normalize();
+                let eye = self.eye();
+                // Turning about scene z and tilting about the view's x never rolls the view
+                self.yaw -= delta.x * rotation_speed;
+                self.tilt = (self.tilt - delta.y * rotation_speed).clamp(0.0, std::f32::consts::PI);
+                self.update_view();
+                if self.mode == CameraMode::Fly {
+                    // Look around from where the eye is instead
+                    self.position = eye + self.get_forward() * self.distance;
+                }
                 
                 changed = true;
             } // else if i.pointer.secondary_down() && i.modifiers.shift {
@@ -135,13 +468,15 @@ impl Camera {
                     let scale_delta = zoom_delta * 0.01;
                     self.point_size_scale = (self.point_size_scale + scale_delta).clamp(0.1, 1000.0);
                     // println!("{}", self.point_size_scale);
+                } else if self.mode == CameraMode::Fly {
+                    self.fly_speed = (self.fly_speed * (1.0 + zoom_delta * 0.002).max(0.1)).clamp(0.01, 1000.0);
                 } else {
                     self.distance *= (1.0 - zoom_delta * 0.001).max(0.1);
                 }
                 changed = true;
             }
             
-            if i.pointer.primary_down() {
+            if i.pointer.primary_down() && i.pointer.delta() != egui::Vec2::ZERO {
                 let delta = i.pointer.delta();
                 let pan_speed = self.distance * 0.001;
             
@@ -156,12 +491,61 @@ impl Camera {
                 
                 changed = true;
             }
+
+            let direction = self.fly_direction(&i);
+            if direction != Vec3::ZERO {
+                // Long frames would otherwise jump the camera
+                self.position += direction * self.fly_speed * i.stable_dt.min(0.1);
+                changed = true;
+            }
             
             
    
         if changed {
+            // Taking over from an animated move
+            self.transition = None;
             self.update_view();
         }
+        changed
+    }
+
+    /// Unit direction the held keys fly towards, zero when not flying.
+    pub fn fly_direction(&self, i: &InputState) -> Vec3 {
+        if self.mode != CameraMode::Fly {
+            return Vec3::ZERO;
+        }
+        let forward = self.get_forward();
+        let right = self.get_right();
+        let keys = [
+            (egui::Key::W, forward),
+            (egui::Key::S, -forward),
+            (egui::Key::D, right),
+            (egui::Key::A, -right),
+            (egui::Key::E, Vec3::Z),
+            (egui::Key::Q, -Vec3::Z),
+        ];
+        keys.iter()
+            .filter(|(key, _)| i.key_down(*key))
+            .map(|(_, direction)| *direction)
+            .sum::<Vec3>()
+            .normalize_or_zero()
+    }
+
+    /// Centres the view on `pivot`, in scene coordinates, keeping the direction and distance.
+    pub fn set_pivot(&mut self, pivot: Vec3) {
+        self.go_to(CameraView { position: pivot, ..self.view() });
+    }
+
+    /// Looks at the pivot from `preset`'s direction, in an orthographic projection.
+    pub fn snap(&mut self, preset: ViewPreset) {
+        let half_turn = std::f32::consts::FRAC_PI_2;
+        let (yaw, tilt) = match preset {
+            ViewPreset::Top => (0.0, 0.0),
+            ViewPreset::Front => (0.0, half_turn),
+            ViewPreset::Side => (half_turn, half_turn),
+        };
+        let orientation = turntable(yaw, tilt);
+        self.go_to(CameraView { orientation, projection: Projection::Orthographic, ..self.view() });
     }
 
     fn get_right(&self) -> Vec3 {
@@ -177,14 +561,49 @@ impl Camera {
     }
 
     fn update_view(&mut self) {
-        // Ensure orientation stays normalized
-        self.orientation = self.orientation.normalize();
+        self.orientation = turntable(self.yaw, self.tilt);
+    }
+
+    /// Where the camera looks from, in scene coordinates.
+    pub fn eye(&self) -> Vec3 {
+        self.position - self.get_forward() * self.distance
+    }
+
+    /// Sets the box the clip range is fitted to; call every frame with what is shown.
+    pub fn set_scene_bounds(&mut self, bounds: Option<(Vec3, Vec3)>) {
+        self.scene = bounds.map(|(min, max)| ((min + max) * 0.5, (max - min).length() * 0.5));
+    }
+
+    /// Depth of the scene's centre in front of the eye, and its radius with some slack.
+    fn scene_depth(&self) -> Option<(f32, f32)> {
+        let (centre, radius) = self.scene?;
+        Some(((centre - self.eye()).dot(self.get_forward()), (radius * 1.1).max(NEAR)))
+    }
+
+    /// How far behind the eye an orthographic view is drawn from, so the points
+    /// between the eye and the near plane are not clipped.
+    pub fn depth_offset(&self) -> f32 {
+        match (self.projection, self.scene_depth()) {
+            (Projection::Orthographic, Some((centre, radius))) => (radius - centre).max(0.0),
+            _ => 0.0,
+        }
+    }
+
+    /// Near and far clip distances from where the view is drawn, fitted around the scene
+    /// so none of it is clipped whatever its size or distance.
+    pub fn clip_range(&self) -> (f32, f32) {
+        let Some((centre, radius)) = self.scene_depth() else {
+            return (NEAR, FAR);
+        };
+        let centre = centre + self.depth_offset();
+        let far = (centre + radius).max(NEAR);
+        ((centre - radius).max(far * 1e-5), far)
     }
 
     pub fn get_view_matrix(&self) -> Mat4 {
         // Calculate view position by moving back from target along view direction
         let forward = self.get_forward();
-        let view_pos = self.position - forward * self.distance;
+        let view_pos = self.eye() - forward * self.depth_offset();
         
         Mat4::look_at_rh(
             view_pos,
@@ -193,6 +612,69 @@ impl Camera {
         )
     }
 
+    /// Half the height the view covers at the pivot, in scene units.
+    fn half_height(&self) -> f32 {
+        self.distance * (FOV.to_radians() / 2.0).tan()
+    }
+
+    pub fn get_projection_matrix(&self, aspect: f32) -> Mat4 {
+        let (near, far) = self.clip_range();
+        match self.projection {
+            Projection::Perspective => Mat4::perspective_rh(FOV.to_radians(), aspect, near, far),
+            Projection::Orthographic => {
+                // As wide at every depth as the perspective view is at the pivot
+                let height = self.half_height();
+                Mat4::orthographic_rh(-height * aspect, height * aspect, -height, height, near, far)
+            }
+        }
+    }
+
+    /// Point size depth factor for orthographic views, where depth says nothing about
+    /// distance: what a point at the pivot gets in perspective. 0 for perspective.
+    pub fn fixed_point_size(&self) -> f32 {
+        match self.projection {
+            Projection::Perspective => 0.0,
+            Projection::Orthographic => POINT_SIZE_DEPTH / self.distance,
+        }
+    }
+
+    /// Pixels per scene unit at distance 1 for perspective, or at any distance for orthographic.
+    pub fn pixels_per_unit(&self, viewport_height: f32) -> f32 {
+        match self.projection {
+            Projection::Perspective => viewport_height / (2.0 * (FOV.to_radians() / 2.0).tan()),
+            Projection::Orthographic => viewport_height / (2.0 * self.half_height()),
+        }
+    }
+
+    /// Camera settings, for the pane's context menu.
+    pub fn show(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Mode");
+            for mode in CameraMode::ALL {
+                ui.radio_value(&mut self.mode, mode, mode.label());
+            }
+        });
+        ui.add_enabled_ui(self.mode == CameraMode::Fly, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Fly speed");
+                ui.add(egui::DragValue::new(&mut self.fly_speed).range(0.01..=1000.0).speed(0.05).suffix(" /s"));
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Projection");
+            ui.radio_value(&mut self.projection, Projection::Perspective, "Perspective");
+            ui.radio_value(&mut self.projection, Projection::Orthographic, "Orthographic");
+        });
+        ui.horizontal(|ui| {
+            ui.label("View");
+            for preset in ViewPreset::ALL {
+                if ui.button(preset.label()).clicked() {
+                    self.snap(preset);
+                }
+            }
+        });
+    }
+
     // pub fn set_point_size_scale(&mut self, scale: f32) {
     //     self.point_size_scale = scale.clamp(0.1, 10.0);
     // }
@@ -200,14 +682,6 @@ impl Camera {
 }
 
 
-// PLY parsing structures
-#[derive(Debug)]
-struct PlyHeader {
-    vertex_count: usize,
-    has_colors: bool,
-    is_binary: bool,
-}
-
 // #[derive(Debug)]
 // pub struct PlyPoint {
 //     position: (i32, i32, i32),
@@ -215,17 +689,241 @@ struct PlyHeader {
 // }
 
 
+/// Three f32 position words, one word of packed RGBA bytes, flags and a packed normal.
+const WORDS_PER_POINT: usize = 6;
+/// Flag bits of a point's fifth word
+const SELECTED: u32 = 1;
+
 #[derive(Default)]
 pub struct PointRenderer {
     pub gl:      Option<Arc<glow::Context>>,
     program: Option<glow::Program>,
+    /// Draws normals as lines, from the same buffers as the points
+    glyph_program: Option<glow::Program>,
     vao:     Option<glow::VertexArray>,
-    vbo:     Option<glow::Buffer>,
-    points:  Option<Vec<i32>>,
-    // capacity: usize,
+    /// Every colour ramp, one row each
+    ramps:   Option<glow::Texture>,
+    screen_pass: Option<ScreenPass>,
+    pub shading: ShadingSettings,
+    pub lighting: LightingSettings,
+    /// Hides points outside it, in scene coordinates
+    pub clip: ClipVolume,
+    /// GPU side of each layer, by the pane's layer id
+    layers:  HashMap<u64, RenderLayer>,
+    frame: u64,
+    pub lod: LodSettings,
+    /// What the last frame drew, for the overlay
+    pub stats: RenderStats,
     pub camera: Option<Camera>,
 }
 
+struct GpuNode {
+    buffer: glow::Buffer,
+    count: usize,
+    last_used: u64,
+}
+
+/// How a layer is drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LayerStyle {
+    pub visible: bool,
+    /// From the layer's origin-relative coordinates to the scene
+    pub model: Mat4,
+    /// Multiplies the camera's point size
+    pub point_size: f32,
+    /// Multiplies every point colour; alpha is the layer opacity
+    pub tint: [f32; 4],
+    /// Colours points from a scalar field instead of their stored colour
+    pub scalar: Option<ScalarStyle>,
+}
+
+/// Where a layer's scalar field comes from, evaluated per point in the vertex shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarSource {
+    /// Scene z after the model transform
+    Elevation,
+    /// Scene distance to the eye
+    DistanceToCamera,
+    /// The layer's points were uploaded with `add_scalar_point`
+    Point,
+}
+
+/// Mapping of a scalar field onto a colour ramp.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScalarStyle {
+    pub source: ScalarSource,
+    /// Values mapped to the two ends of the ramp, in the units the shader sees
+    pub range: [f32; 2],
+    pub ramp: ColorRamp,
+}
+
+impl Default for LayerStyle {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            model: Mat4::IDENTITY,
+            point_size: 1.0,
+            tint: [1.0; 4],
+            scalar: None,
+        }
+    }
+}
+
+/// One layer's points, hierarchy and uploaded nodes.
+#[derive(Default)]
+pub struct RenderLayer {
+    /// Interleaved vertex data in octree order, `WORDS_PER_POINT` words per point
+    points: Vec<u32>,
+    octree: Arc<Octree>,
+    /// When set, node points are paged in from disk instead of taken from `points`
+    cache:  Option<CacheReader>,
+    /// Buffers of the octree nodes currently on the GPU
+    nodes:  HashMap<u32, GpuNode>,
+    /// Set when `points` changed, so every uploaded node is stale.
+    dirty: bool,
+    pub style: LayerStyle,
+}
+
+impl RenderLayer {
+    /// `position` is relative to the cloud origin. Points must be added in the order of
+    /// the octree passed to `set_octree`. Selected points are highlighted; the normal,
+    /// in the same coordinates, is used for lighting, splats and glyphs.
+    pub fn add_point(&mut self, [x, y, z]: [f32; 3], color: Color32, normal: Option<[f32; 3]>, selected: bool) {
+        let color = u32::from_ne_bytes(color.to_array());
+        let flags = if selected { SELECTED } else { 0 };
+        self.points.extend_from_slice(&[x.to_bits(), y.to_bits(), z.to_bits(), color, flags, pack_normal(normal)]);
+        self.dirty = true;
+    }
+
+    /// Like `add_point`, but stores a scalar for `ScalarSource::Point` in place of the colour.
+    pub fn add_scalar_point(&mut self, [x, y, z]: [f32; 3], value: f32, normal: Option<[f32; 3]>, selected: bool) {
+        let flags = if selected { SELECTED } else { 0 };
+        self.points.extend_from_slice(&[x.to_bits(), y.to_bits(), z.to_bits(), value.to_bits(), flags, pack_normal(normal)]);
+        self.dirty = true;
+    }
+    
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.cache = None;
+        self.dirty = true;
+    }
+
+    pub fn set_octree(&mut self, octree: Arc<Octree>) {
+        self.octree = octree;
+        self.dirty = true;
+    }
+
+    /// Draws from an on-disk cache, replacing any points added before.
+    pub fn set_cache(&mut self, cache: CacheReader) {
+        self.clear();
+        self.octree = Arc::new(cache.header.octree());
+        self.cache = Some(cache);
+    }
+
+    /// What the colour word of cached points holds; nodes read before a change are dropped.
+    pub fn set_cache_coloring(&mut self, coloring: CacheColoring) {
+        if let Some(cache) = &mut self.cache {
+            if cache.set_coloring(coloring) {
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// The first error the cache ran into reading nodes, if any.
+    pub fn cache_error(&self) -> Option<&str> {
+        self.cache.as_ref()?.error.as_deref()
+    }
+
+    fn delete_nodes(&mut self, gl: &glow::Context) {
+        use glow::HasContext;
+        for (_, node) in self.nodes.drain() {
+            unsafe { gl.delete_buffer(node.buffer) };
+        }
+    }
+
+    /// Frees the least recently drawn nodes once the GPU holds more than `limit` points.
+    fn evict(&mut self, gl: &glow::Context, frame: u64, limit: usize) {
+        use glow::HasContext;
+        let mut resident: usize = self.nodes.values().map(|n| n.count).sum();
+        if resident <= limit {
+            return;
+        }
+        let mut stale: Vec<(u64, u32)> = self.nodes.iter()
+            .filter(|(_, n)| n.last_used < frame)
+            .map(|(&id, n)| (n.last_used, id))
+            .collect();
+        stale.sort_unstable();
+        for (_, id) in stale {
+            if resident <= limit {
+                break;
+            }
+            let node = self.nodes.remove(&id).unwrap();
+            resident -= node.count;
+            unsafe { gl.delete_buffer(node.buffer) };
+        }
+    }
+}
+
+/// How much of the octree to draw.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct LodSettings {
+    /// Most points drawn in one frame, shared between the visible layers
+    pub point_budget: usize,
+    /// Nodes are refined while their point spacing covers more pixels than this
+    pub max_spacing_px: f32,
+    /// Memory for node points paged in from an on-disk cache, per layer
+    pub cache_memory_mb: usize,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            point_budget: 5_000_000,
+            max_spacing_px: 2.0,
+            cache_memory_mb: 1024,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub drawn_points: usize,
+    pub drawn_nodes: usize,
+    /// Selected nodes that were not uploaded yet; more frames will refine the view.
+    pub pending_nodes: usize,
+    /// Node points held in memory by on-disk caches
+    pub cache_bytes: usize,
+}
+
+/// Clip distances of the projection while nothing is shown. With a scene the range is
+/// fitted around it, see `Camera::clip_range`.
+const NEAR: f32 = 0.1;
+const FAR: f32 = 1000.0;
+/// Depth at which a point is drawn at its full size in perspective; nearer points grow and
+/// further ones shrink. The vertex shader divides the same value by the depth.
+const POINT_SIZE_DEPTH: f32 = 0.1;
+/// Vertical field of view, in degrees
+const FOV: f32 = 45.0;
+
+/// A point under the cursor, as found by `PointRenderer::pick`.
+#[derive(Clone, Copy, Debug)]
+pub struct Pick {
+    pub layer: u64,
+    /// The octree node holding the point
+    pub node: u32,
+    /// Index of the point among the node's points
+    pub offset: usize,
+    /// Position in the layer's origin-relative coordinates
+    pub local: Vec3,
+    /// The point's fourth vertex word: packed colour, or its scalar for attribute colouring
+    pub word: u32,
+}
+
+/// Points uploaded per frame, so refining never stalls the UI. Less while the camera moves.
+const UPLOADS_PER_FRAME: usize = 2_000_000;
+const UPLOADS_WHILE_MOVING: usize = 250_000;
+
 // impl Defalt for PointRenderer {
 //     fn default() -> Self {
 //         Self {
@@ -242,7 +940,7 @@ pub struct PointRenderer {
 // }
 
 impl PointRenderer {
-    pub fn init(&mut self, gl: Option<Arc<glow::Context>>, initial_capacity: usize) {
+    pub fn init(&mut self, gl: Option<Arc<glow::Context>>) {
         use glow::HasContext;
 
         let gl = gl.unwrap();
@@ -273,222 +971,493 @@ impl PointRenderer {
         let vao = unsafe {
             let vao = gl.create_vertex_array().expect("Cannot create vertex array");
             gl.bind_vertex_array(Some(vao));
-            vao
-        };
-        
-        let vbo = unsafe {
-            let vbo = gl.create_buffer().expect("Cannot create vertex buffer");
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
-            
-            // Position (3) + Color (4) = 7 u32s per vertex
-            let buffer_size = initial_capacity * 7 * std::mem::size_of::<i32>();
-            gl.buffer_data_size(glow::ARRAY_BUFFER, buffer_size as i32, glow::DYNAMIC_DRAW);
-            
-            // Position attribute (uvec3)
-            gl.vertex_attrib_pointer_i32(0, 3, glow::INT, 28, 0);
             gl.enable_vertex_attrib_array(0);
-            
-            // Color attribute (uvec4)
-            gl.vertex_attrib_pointer_i32(1, 4, glow::INT, 28, 12);
             gl.enable_vertex_attrib_array(1);
-            
-            vbo
+            gl.enable_vertex_attrib_array(2);
+            gl.enable_vertex_attrib_array(3);
+            gl.enable_vertex_attrib_array(4);
+            vao
+        };
+
+        let ramps = unsafe {
+            let texture = gl.create_texture().expect("Cannot create ramp texture");
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::RGBA8 as i32, RAMP_SAMPLES as i32, ColorRamp::ALL.len() as i32, 0,
+                glow::RGBA, glow::UNSIGNED_BYTE, Some(&ramp::ramp_texture()));
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            texture
         };
         
         self.gl = Some(gl);
         self.program = Some(program);
+        self.glyph_program = Some(Self::create_glyph_program(self.gl.as_ref().unwrap()).expect("Cannot create normal glyph program"));
         self.vao = Some(vao);
-        self.vbo = Some(vbo);
-        self.points = Some(Vec::with_capacity(initial_capacity * 7));
-            // capacity: initial_capacity,
+        self.ramps = Some(ramps);
+        self.screen_pass = Some(ScreenPass::new(self.gl.as_ref().unwrap()).expect("Cannot create shading pass"));
         self.camera = Some(Camera::new());
     }
-    
-    pub fn add_point(&mut self, x: i32, y: i32, z: i32, color: Color32) {
-        let [r, g, b, a] = color.to_array();
-        self.points.as_mut().as_mut().expect("Not Initialised").extend_from_slice(&[x, y, z, r as i32, g as i32, b as i32, a as i32]);
-    }
-    
-    pub fn clear(&mut self) {
-        self.points.as_mut().as_mut().expect("Not Initialised").clear();
-    }
-    
-    pub fn render(&mut self, rect: Rect, input_state: Option<InputState>) {
+
+    fn create_glyph_program(gl: &glow::Context) -> Result<glow::Program, String> {
         use glow::HasContext;
-        
-        // Update camera
-        if let Some(i) = input_state{
-            self.camera.as_mut().expect("Not Initialised").update(i);
-        }
-        
+
         unsafe {
-            self.gl.as_mut().expect("Not Initialised").use_program(self.program);
-            
-            // Set up view-projection matrix
-            let aspect = rect.width() / rect.height();
-            let projection = Mat4::perspective_rh(45.0f32.to_radians(), aspect, 0.1, 1000.0);
-            let view = self.camera.as_mut().expect("Not Initialised").get_view_matrix();
-            let view_projection = projection * view;
-            
-            let location = self.gl.as_mut().expect("Not Initialised").get_uniform_location(*self.program.as_mut().expect("Not Initialised"), "u_view_projection")
-                .expect("Cannot get uniform location");
-            self.gl.as_mut().expect("Not Initialised").uniform_matrix_4_f32_slice(Some(&location), false, &view_projection.to_cols_array());
-            
-            // Set position scale factor (converts uint positions to world space)
-            let scale_location = self.gl.as_mut().expect("Not Initialised").get_uniform_location(*self.program.as_mut().expect("Not Initialised"), "u_position_scale")
-                .expect("Cannot get scale uniform location");
-            self.gl.as_mut().expect("Not Initialised").uniform_1_f32(Some(&scale_location), 0.001); // Adjust this value to scale your point cloud
-            
-            let point_size_location = self.gl.as_mut().expect("Not Initialised").get_uniform_location(*self.program.as_mut().expect("Not Initialised"), "u_point_size_scale")
-                .expect("Cannot get point size scale location");
-            self.gl.as_mut().expect("Not Initialised").uniform_1_f32(Some(&point_size_location), self.camera.as_mut().expect("Not Initialised").point_size_scale);
+            let program = gl.create_program()?;
+            let stages = [
+                (glow::VERTEX_SHADER, GLYPH_VERTEX_SHADER),
+                (glow::GEOMETRY_SHADER, GLYPH_GEOMETRY_SHADER),
+                (glow::FRAGMENT_SHADER, GLYPH_FRAGMENT_SHADER),
+            ];
+            for (kind, source) in stages {
+                let shader = gl.create_shader(kind)?;
+                gl.shader_source(shader, source);
+                gl.compile_shader(shader);
+                if !gl.get_shader_compile_status(shader) {
+                    return Err(format!("Failed to compile normal glyphs: {}", gl.get_shader_info_log(shader)));
+                }
+                gl.attach_shader(program, shader);
+                gl.delete_shader(shader);
+            }
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                return Err(format!("Failed to link normal glyphs: {}", gl.get_program_info_log(program)));
+            }
+            Ok(program)
+        }
+    }
 
-            self.gl.as_mut().expect("Not Initialised").bind_vertex_array(self.vao);
-            self.gl.as_mut().expect("Not Initialised").bind_buffer(glow::ARRAY_BUFFER, Some(*self.vbo.as_mut().expect("Not Initialised")));
-            
-            self.gl.as_mut().expect("Not Initialised").buffer_sub_data_u8_slice(
-                glow::ARRAY_BUFFER,
-                0,
-                bytemuck::cast_slice(&self.points.as_mut().expect("Not Initialised")),
-            );
-            
-            self.gl.as_mut().expect("Not Initialised").enable(glow::PROGRAM_POINT_SIZE);
-            self.gl.as_mut().expect("Not Initialised").enable(glow::DEPTH_TEST);
+    /// The layer with this id, created empty the first time it is asked for.
+    pub fn layer(&mut self, id: u64) -> &mut RenderLayer {
+        self.layers.entry(id).or_default()
+    }
 
+    /// Drops every layer not in `ids`, freeing its GPU buffers.
+    pub fn retain_layers(&mut self, ids: &[u64]) {
+        let gl = self.gl.as_ref().expect("Not Initialised");
+        self.layers.retain(|id, layer| {
+            let keep = ids.contains(id);
+            if !keep {
+                layer.delete_nodes(gl);
+            }
+            keep
+        });
+    }
 
-            self.gl.as_mut().expect("Not Initialised").clear_depth_f32(1.0);
-            self.gl.as_mut().expect("Not Initialised").depth_func(glow::LESS);
-            self.gl.as_mut().expect("Not Initialised").depth_mask(true);
+    /// The view-projection `render` draws with in a viewport of this size.
+    pub fn view_projection(&self, rect: Rect) -> Mat4 {
+        let camera = self.camera.as_ref().expect("Not Initialised");
+        camera.get_projection_matrix(rect.width() / rect.height()) * camera.get_view_matrix()
+    }
 
-            // self.gl.clear_color(0.3, 0.3, 0.3, 1.0);
-            self.gl.as_mut().expect("Not Initialised").clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
-            
-            self.gl.as_mut().expect("Not Initialised").draw_arrays(glow::POINTS, 0, (self.points.as_mut().as_mut().expect("Not Initialised").len() / 7) as i32);
-            
-            self.gl.as_mut().expect("Not Initialised").disable(glow::DEPTH_TEST);
-            self.gl.as_mut().expect("Not Initialised").disable(glow::PROGRAM_POINT_SIZE);
+    /// The visible point nearest the eye among those within `radius` pixels of `pointer`.
+    /// Only nodes inside a narrow frustum around the pointer are searched, at full detail
+    /// for layers in memory and as far as they are paged in for cached ones.
+    pub fn pick(&self, rect: Rect, pointer: egui::Pos2, radius: f32) -> Option<Pick> {
+        let view_projection = self.view_projection(rect);
+        let cursor = glam::Vec2::new(
+            (pointer.x - rect.left()) / rect.width() * 2.0 - 1.0,
+            1.0 - (pointer.y - rect.top()) / rect.height() * 2.0,
+        );
+        // Half the pick window in normalised device coordinates
+        let window = glam::Vec2::new(radius / rect.width() * 2.0, radius / rect.height() * 2.0);
+        // Stretches the window around the cursor over the whole clip volume
+        let pick_matrix = Mat4::from_scale(Vec3::new(1.0 / window.x, 1.0 / window.y, 1.0))
+            * Mat4::from_translation(Vec3::new(-cursor.x, -cursor.y, 0.0));
+
+        let mut best: Option<(f32, Pick)> = None;
+        for (&id, layer) in &self.layers {
+            if !layer.style.visible || layer.octree.nodes.is_empty() {
+                continue;
+            }
+            let mvp = view_projection * layer.style.model;
+            let planes = frustum_planes(pick_matrix * mvp);
+            let mut stack = vec![0u32];
+            while let Some(node) = stack.pop() {
+                let n = &layer.octree.nodes[node as usize];
+                if !box_in_frustum(&planes, Vec3::from(n.min), n.max()) {
+                    continue;
+                }
+                stack.extend_from_slice(&n.children);
+
+                let cached;
+                let words = match &layer.cache {
+                    Some(cache) => match cache.peek(node) {
+                        Some(words) => {
+                            cached = words;
+                            &cached[..]
+                        }
+                        None => continue,
+                    },
+                    None => &layer.points[n.first * WORDS_PER_POINT..(n.first + n.count) * WORDS_PER_POINT],
+                };
+                for (offset, point) in words.chunks_exact(WORDS_PER_POINT).enumerate() {
+                    let local = Vec3::new(f32::from_bits(point[0]), f32::from_bits(point[1]), f32::from_bits(point[2]));
+                    if !self.clip.keeps(layer.style.model.transform_point3(local)) {
+                        continue;
+                    }
+                    let clip = mvp * local.extend(1.0);
+                    if clip.w <= 0.0 {
+                        continue;
+                    }
+                    let ndc = clip.truncate() / clip.w;
+                    if ndc.z.abs() > 1.0 || ((ndc.truncate() - cursor) / window).length() > 1.0 {
+                        continue;
+                    }
+                    if best.as_ref().is_none_or(|(depth, _)| ndc.z < *depth) {
+                        best = Some((ndc.z, Pick { layer: id, node, offset, local, word: point[3] }));
+                    }
+                }
+            }
         }
+        best.map(|(_, pick)| pick)
     }
 
+    /// Draws a frame of `width` by `height` pixels offscreen, on black, and reads it back
+    /// as RGB rows from the top. Must run where `gl` is current, as in a paint callback;
+    /// the framebuffer, viewport and scissor in use are put back afterwards.
+    pub fn render_image(&mut self, width: u32, height: u32) -> Result<Vec<u8>, String> {
+        use glow::HasContext;
 
+        let gl = self.gl.clone().expect("Not Initialised");
+        let (w, h) = (width as i32, height as i32);
+        let mut pixels = vec![0u8; width as usize * height as usize * 3];
+        unsafe {
+            let mut viewport = [0; 4];
+            gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+            let mut clear_color = [0.0; 4];
+            gl.get_parameter_f32_slice(glow::COLOR_CLEAR_VALUE, &mut clear_color);
+            let previous = gl.get_parameter_framebuffer(glow::DRAW_FRAMEBUFFER_BINDING);
+            let scissor = gl.is_enabled(glow::SCISSOR_TEST);
+
+            let framebuffer = gl.create_framebuffer()?;
+            let color = gl.create_renderbuffer()?;
+            let depth = gl.create_renderbuffer()?;
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(color));
+            gl.renderbuffer_storage(glow::RENDERBUFFER, glow::RGBA8, w, h);
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth));
+            gl.renderbuffer_storage(glow::RENDERBUFFER, glow::DEPTH_COMPONENT24, w, h);
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::RENDERBUFFER, Some(color));
+            gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::DEPTH_ATTACHMENT, glow::RENDERBUFFER, Some(depth));
+
+            let complete = gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE;
+            if complete {
+                gl.viewport(0, 0, w, h);
+                gl.disable(glow::SCISSOR_TEST);
+                gl.clear_color(0.0, 0.0, 0.0, 1.0);
+                self.render(Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(width as f32, height as f32)), None);
+
+                gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+                gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+                gl.read_pixels(0, 0, w, h, glow::RGB, glow::UNSIGNED_BYTE, glow::PixelPackData::Slice(&mut pixels));
+                gl.pixel_store_i32(glow::PACK_ALIGNMENT, 4);
+            }
 
+            gl.bind_framebuffer(glow::FRAMEBUFFER, previous);
+            gl.delete_framebuffer(framebuffer);
+            gl.delete_renderbuffer(color);
+            gl.delete_renderbuffer(depth);
+            gl.viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            if scissor {
+                gl.enable(glow::SCISSOR_TEST);
+            }
+            let [r, g, b, a] = clear_color;
+            gl.clear_color(r, g, b, a);
+            if !complete {
+                return Err(format!("Cannot render a {}x{} image offscreen", width, height));
+            }
+        }
 
+        // GL reads from the bottom row up
+        let row = width as usize * 3;
+        let flipped = pixels.chunks_exact(row).rev().flatten().copied().collect();
+        Ok(flipped)
+    }
 
+    /// Uploads one node's points into a buffer of its own.
+    fn upload_node(gl: &glow::Context, words: &[u32]) -> glow::Buffer {
+        use glow::HasContext;
 
+        unsafe {
+            let buffer = gl.create_buffer().expect("Cannot create vertex buffer");
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, bytemuck::cast_slice(words), glow::STATIC_DRAW);
+            buffer
+        }
+    }
+    
+    pub fn render(&mut self, rect: Rect, input_state: Option<InputState>) {
+        use glow::HasContext;
+        
+        // Update camera
+        let mut moving = self.camera.as_mut().expect("Not Initialised").animate();
+        if let Some(i) = input_state{
+            moving |= self.camera.as_mut().expect("Not Initialised").update(i);
+        }
+        self.frame += 1;
+        
+        unsafe {
+            self.gl.as_mut().expect("Not Initialised").use_program(self.program);
+            
+            // Set up view-projection matrix
+            let camera = self.camera.as_ref().expect("Not Initialised");
+            let eye = camera.eye();
+            let orthographic = camera.projection == Projection::Orthographic;
+            let view_projection = self.view_projection(rect);
+            
+            let location = self.gl.as_mut().expect("Not Initialised").get_uniform_location(*self.program.as_mut().expect("Not Initialised"), "u_view_projection")
+                .expect("Cannot get uniform location");
+            let point_size_location = self.gl.as_mut().expect("Not Initialised").get_uniform_location(*self.program.as_mut().expect("Not Initialised"), "u_point_size_scale")
+                .expect("Cannot get point size scale location");
+            let tint_location = self.gl.as_mut().expect("Not Initialised").get_uniform_location(*self.program.as_mut().expect("Not Initialised"), "u_tint")
+                .expect("Cannot get tint location");
+            let program = self.program.expect("Not Initialised");
+            let uniform = |name: &str| self.gl.as_ref().expect("Not Initialised").get_uniform_location(program, name)
+                .unwrap_or_else(|| panic!("Cannot get {} location", name));
+            let model_location = uniform("u_model");
+            let eye_location = uniform("u_eye");
+            let scalar_source_location = uniform("u_scalar_source");
+            let scalar_range_location = uniform("u_scalar_range");
+            let ramp_row_location = uniform("u_ramp_row");
+            let ramps_location = uniform("u_ramps");
+            let fixed_size_location = uniform("u_fixed_size");
+            let clip_plane_count_location = uniform("u_clip_plane_count");
+            let clip_planes_location = uniform("u_clip_planes");
+            let clip_box_location = uniform("u_clip_box");
+            let clip_box_inverse_location = uniform("u_clip_box_inverse");
+            let view_location = uniform("u_view");
+            let lighting_location = uniform("u_lighting");
+            let ambient_location = uniform("u_ambient");
+            let specular_location = uniform("u_specular");
+            let shininess_location = uniform("u_shininess");
+            let splats_location = uniform("u_splats");
 
+            self.gl.as_mut().expect("Not Initialised").bind_vertex_array(self.vao);
+            
+            self.gl.as_mut().expect("Not Initialised").enable(glow::PROGRAM_POINT_SIZE);
+            self.gl.as_mut().expect("Not Initialised").enable(glow::DEPTH_TEST);
+            self.gl.as_mut().expect("Not Initialised").enable(glow::BLEND);
+            // Alpha accumulates as coverage, so the offscreen image ends up premultiplied
+            self.gl.as_mut().expect("Not Initialised").blend_func_separate(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA, glow::ONE, glow::ONE_MINUS_SRC_ALPHA);
+
+            // With shading on, points go to an offscreen target first
+            let target = match &mut self.screen_pass {
+                Some(pass) if self.shading.enabled() => Some(pass.begin(self.gl.as_ref().expect("Not Initialised"))),
+                _ => None,
+            };
 
 
+            self.gl.as_mut().expect("Not Initialised").clear_depth_f32(1.0);
+            self.gl.as_mut().expect("Not Initialised").depth_func(glow::LESS);
+            self.gl.as_mut().expect("Not Initialised").depth_mask(true);
 
+            // self.gl.clear_color(0.3, 0.3, 0.3, 1.0);
+            self.gl.as_mut().expect("Not Initialised").clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
 
-    // Add method to load points from PLY file
-    pub fn load_ply(&mut self, path: String) -> Result<Vec<(i32, i32, i32, Color32)>, String> {
-        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
-        let reader = BufReader::new(file);
-        let mut lines = reader.lines();
+            let gl = self.gl.as_ref().expect("Not Initialised");
+            gl.uniform_3_f32(Some(&eye_location), eye.x, eye.y, eye.z);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, self.ramps);
+            gl.uniform_1_i32(Some(&ramps_location), 0);
+            let camera = self.camera.as_ref().expect("Not Initialised");
+            let pixels_per_unit = camera.pixels_per_unit(rect.height());
+            let point_size_scale = camera.point_size_scale;
+            let depth_offset = camera.depth_offset();
+            gl.uniform_1_f32(Some(&fixed_size_location), camera.fixed_point_size());
+            let clip_planes: Vec<f32> = self.clip.planes.iter().flat_map(|plane| plane.to_array()).collect();
+            gl.uniform_1_i32(Some(&clip_plane_count_location), self.clip.planes.len() as i32);
+            if !clip_planes.is_empty() {
+                gl.uniform_4_f32_slice(Some(&clip_planes_location), &clip_planes);
+            }
+            let (clip_box, to_box) = match self.clip.clip_box {
+                None => (0, Mat4::IDENTITY),
+                Some((to_box, keep_inside)) => (if keep_inside { 1 } else { 2 }, to_box),
+            };
+            gl.uniform_1_i32(Some(&clip_box_location), clip_box);
+            gl.uniform_matrix_4_f32_slice(Some(&clip_box_inverse_location), false, &to_box.to_cols_array());
+            gl.uniform_matrix_4_f32_slice(Some(&view_location), false, &camera.get_view_matrix().to_cols_array());
+            let lighting = &self.lighting;
+            gl.uniform_1_i32(Some(&lighting_location), match lighting.model {
+                LightingModel::Off => 0,
+                LightingModel::Lambert => 1,
+                LightingModel::Phong => 2,
+            });
+            gl.uniform_1_f32(Some(&ambient_location), lighting.ambient);
+            gl.uniform_1_f32(Some(&specular_location), lighting.specular);
+            gl.uniform_1_f32(Some(&shininess_location), lighting.shininess);
+            gl.uniform_1_i32(Some(&splats_location), lighting.splats as i32);
+
+            // The glyph program keeps its own copy of the clip volume
+            let glyph_program = self.glyph_program.expect("Not Initialised");
+            let glyph_uniform = |name: &str| gl.get_uniform_location(glyph_program, name)
+                .unwrap_or_else(|| panic!("Cannot get glyph {} location", name));
+            let glyph_model_location = glyph_uniform("u_model");
+            let glyph_view_projection_location = glyph_uniform("u_scene_view_projection");
+            let glyph_tint_location = glyph_uniform("u_tint");
+            if lighting.glyphs {
+                gl.use_program(Some(glyph_program));
+                gl.uniform_matrix_4_f32_slice(Some(&glyph_view_projection_location), false, &view_projection.to_cols_array());
+                gl.uniform_1_f32(Some(&glyph_uniform("u_glyph_length")), lighting.glyph_length);
+                gl.uniform_1_i32(Some(&glyph_uniform("u_clip_plane_count")), self.clip.planes.len() as i32);
+                if !clip_planes.is_empty() {
+                    gl.uniform_4_f32_slice(Some(&glyph_uniform("u_clip_planes")), &clip_planes);
+                }
+                gl.uniform_1_i32(Some(&glyph_uniform("u_clip_box")), clip_box);
+                gl.uniform_matrix_4_f32_slice(Some(&glyph_uniform("u_clip_box_inverse")), false, &to_box.to_cols_array());
+                gl.use_program(self.program);
+            }
+            let visible = self.layers.values().filter(|l| l.style.visible).count().max(1);
+            let budget = self.lod.point_budget / visible;
+            let upload_limit = if moving { UPLOADS_WHILE_MOVING } else { UPLOADS_PER_FRAME };
+            let mut uploaded = 0;
+            let mut stats = RenderStats::default();
+            let stride = (WORDS_PER_POINT * std::mem::size_of::<u32>()) as i32;
+
+            // Opaque layers first, so translucent ones blend over them
+            let mut order: Vec<(bool, u64)> = self.layers.iter()
+                .map(|(&id, layer)| (layer.style.tint[3] < 1.0, id))
+                .collect();
+            order.sort_unstable();
+
+            for (translucent, layer_id) in order {
+                let layer = self.layers.get_mut(&layer_id).unwrap();
+                if layer.dirty {
+                    layer.delete_nodes(gl);
+                    layer.dirty = false;
+                }
+                if let Some(cache) = &mut layer.cache {
+                    cache.memory_budget = self.lod.cache_memory_mb * 1024 * 1024;
+                    cache.update();
+                    stats.cache_bytes += cache.resident_bytes();
+                }
+                if !layer.style.visible {
+                    continue;
+                }
 
-        // Parse header
-        let header = Self::parse_ply_header(&mut lines)?;
-        
-        // Clear existing points
-        self.clear();
-        
-        // Reserve capacity
-        self.points.as_mut().as_mut().expect("Not Initialised").reserve(header.vertex_count * 7);
-        
-        // Parse vertices based on format
-        if header.is_binary {
-            return Err("Binary PLY files not yet supported".to_string());
-        } else {
-            self.parse_ascii_ply_data(lines, header)
-        }
+                let model = layer.style.model;
+                let layer_view_projection = view_projection * model;
+                gl.uniform_matrix_4_f32_slice(Some(&location), false, &layer_view_projection.to_cols_array());
+                gl.uniform_1_f32(Some(&point_size_location), point_size_scale * layer.style.point_size);
+                gl.uniform_4_f32_slice(Some(&tint_location), &layer.style.tint);
+                gl.uniform_matrix_4_f32_slice(Some(&model_location), false, &model.to_cols_array());
+                let (source, range, ramp) = match layer.style.scalar {
+                    None => (0, [0.0, 1.0], ColorRamp::default()),
+                    Some(ScalarStyle { source, range, ramp }) => (match source {
+                        ScalarSource::Elevation => 1,
+                        ScalarSource::DistanceToCamera => 2,
+                        ScalarSource::Point => 3,
+                    }, range, ramp),
+                };
+                gl.uniform_1_i32(Some(&scalar_source_location), source);
+                gl.uniform_2_f32(Some(&scalar_range_location), range[0], range[1]);
+                gl.uniform_1_f32(Some(&ramp_row_location), (ramp.row() as f32 + 0.5) / ColorRamp::ALL.len() as f32);
+                // Translucent points should not hide what is drawn after them
+                gl.depth_mask(!translucent);
+
+                // Select in the layer's own coordinates
+                let (scale, _, _) = model.to_scale_rotation_translation();
+                let lod_view = LodView {
+                    view_projection: layer_view_projection,
+                    eye: model.inverse().transform_point3(eye),
+                    pixels_per_unit: pixels_per_unit * scale.max_element(),
+                    orthographic,
+                    clip_planes: self.clip.local_planes(model),
+                };
+                let octree = layer.octree.clone();
+                let selected = octree.select(&lod_view, self.lod.max_spacing_px, budget);
+                let mut drawn = Vec::new();
+
+                for id in selected {
+                    let node = &octree.nodes[id as usize];
+                    if !layer.nodes.contains_key(&id) {
+                        // Parents come first in the selection, so a skipped child just stays coarse a little longer
+                        if uploaded >= upload_limit {
+                            stats.pending_nodes += 1;
+                            continue;
+                        }
+                        let buffer = match &mut layer.cache {
+                            Some(cache) => match cache.get(id) {
+                                Some(words) => Self::upload_node(gl, &words),
+                                None => {
+                                    // Still on its way from disk
+                                    stats.pending_nodes += 1;
+                                    continue;
+                                }
+                            },
+                            None => Self::upload_node(gl, &layer.points[node.first * WORDS_PER_POINT..(node.first + node.count) * WORDS_PER_POINT]),
+                        };
+                        layer.nodes.insert(id, GpuNode { buffer, count: node.count, last_used: self.frame });
+                        uploaded += node.count;
+                    }
 
-    }
+                    let gpu_node = layer.nodes.get_mut(&id).unwrap();
+                    gpu_node.last_used = self.frame;
+                    gl.bind_buffer(glow::ARRAY_BUFFER, Some(gpu_node.buffer));
+                    gl.vertex_attrib_pointer_f32(0, 3, glow::FLOAT, false, stride, 0);
+                    gl.vertex_attrib_pointer_f32(1, 4, glow::UNSIGNED_BYTE, true, stride, 12);
+                    gl.vertex_attrib_pointer_f32(2, 1, glow::FLOAT, false, stride, 12);
+                    gl.vertex_attrib_pointer_i32(3, 1, glow::UNSIGNED_INT, stride, 16);
+                    gl.vertex_attrib_pointer_f32(4, 4, glow::INT_2_10_10_10_REV, true, stride, 20);
+                    gl.draw_arrays(glow::POINTS, 0, gpu_node.count as i32);
+
+                    stats.drawn_points += gpu_node.count;
+                    stats.drawn_nodes += 1;
+                    drawn.push((gpu_node.buffer, gpu_node.count));
+                }
 
-    fn parse_ply_header<B: BufRead>(lines: &mut std::io::Lines<B>) -> Result<PlyHeader, String> {
-        let mut vertex_count = 0;
-        let mut has_colors = false;
-        let mut is_binary = false;
-        let in_header = true;
-        
-        while in_header {
-            let line = lines.next()
-                .ok_or("Unexpected end of file").unwrap().unwrap()
-                .trim().to_string();
-                
-            match line.as_str() {
-                "ply" => continue,
-                "format ascii 1.0" => is_binary = false,
-                "format binary_little_endian 1.0" => is_binary = true,
-                "end_header" => break,
-                _ => {
-                    if line.starts_with("element vertex ") {
-                        vertex_count = line.split_whitespace()
-                            .last()
-                            .ok_or("Invalid vertex count")?
-                            .parse()
-                            .map_err(|_| "Invalid vertex count")?;
-                    } else if line.starts_with("property") && line.contains("red") {
-                        has_colors = true;
+                if self.lighting.glyphs {
+                    gl.use_program(Some(glyph_program));
+                    gl.uniform_matrix_4_f32_slice(Some(&glyph_model_location), false, &model.to_cols_array());
+                    gl.uniform_4_f32_slice(Some(&glyph_tint_location), &layer.style.tint);
+                    for (buffer, count) in drawn {
+                        gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+                        gl.vertex_attrib_pointer_f32(0, 3, glow::FLOAT, false, stride, 0);
+                        gl.vertex_attrib_pointer_f32(4, 4, glow::INT_2_10_10_10_REV, true, stride, 20);
+                        gl.draw_arrays(glow::POINTS, 0, count as i32);
                     }
+                    gl.use_program(self.program);
                 }
+                layer.evict(gl, self.frame, budget * 2);
             }
-        }
-        
-        Ok(PlyHeader {
-            vertex_count,
-            has_colors,
-            is_binary,
-        })
-    }
+            self.stats = stats;
 
-    fn parse_ascii_ply_data<B: BufRead>(
-        &mut self,
-        lines: std::io::Lines<B>,
-        header: PlyHeader,
-    ) -> Result<Vec<(i32, i32, i32, Color32)>, String> {
-        let mut vec: Vec<(i32, i32, i32, Color32)> = Vec::new();
-
-        for line in lines.take(header.vertex_count) {
-            let line = line.map_err(|e| format!("Failed to read line: {}", e))?;
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            
-            if parts.len() < 3 {
-                return Err("Invalid vertex data".to_string());
+            if let (Some(target), Some(pass)) = (target, &self.screen_pass) {
+                gl.depth_mask(true);
+                pass.finish(gl, target, &self.shading, camera.clip_range(), orthographic.then_some(depth_offset));
             }
             
-            // Parse position
-            let x = parts[0].parse::<f32>().map_err(|_| "Invalid X coordinate")?;
-            let y = parts[1].parse::<f32>().map_err(|_| "Invalid Y coordinate")?;
-            let z = parts[2].parse::<f32>().map_err(|_| "Invalid Z coordinate")?;
-            
-            // Convert to fixed point (scale by 1000 for better precision)
-            let x = (x * 1000.0) as i32;
-            let y = (y * 1000.0) as i32;
-            let z = (z * 1000.0) as i32;
-            
-            // Parse colors if present
-            let color = if header.has_colors && parts.len() >= 6 {
-                let r = parts[3].parse::<u8>().unwrap_or(255);
-                let g = parts[4].parse::<u8>().unwrap_or(255);
-                let b = parts[5].parse::<u8>().unwrap_or(255);
-                Color32::from_rgb(r, g, b)
-            } else {
-                Color32::WHITE
-            };
-            
-            vec.push((x,y,z,color));
-
-            // self.add_point(x, y, z, color);
+            gl.depth_mask(true);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.disable(glow::BLEND);
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::PROGRAM_POINT_SIZE);
         }
-        
-        Ok(vec)
     }
-
 }
 
 impl Drop for PointRenderer {
     fn drop(&mut self) {
         // Clean up GPU resources
+        if let Some(gl) = &self.gl {
+            for layer in self.layers.values_mut() {
+                layer.delete_nodes(gl);
+            }
+            if let Some(ramps) = self.ramps {
+                use glow::HasContext;
+                unsafe { gl.delete_texture(ramps) };
+            }
+            if let Some(program) = self.glyph_program {
+                use glow::HasContext;
+                unsafe { gl.delete_program(program) };
+            }
+            if let Some(pass) = &self.screen_pass {
+                pass.destroy(gl);
+            }
+        }
     }
 }
 
@@ -510,16 +1479,159 @@ impl Drop for PointRenderer {
 
 
 
+/// A file being read on a worker thread; the pane keeps showing the old cloud until it finishes.
+struct LoadJob {
+    path: String,
+    progress: LoadProgress,
+    result: mpsc::Receiver<Result<(PointCloud, Octree), String>>,
+}
+
+impl LoadJob {
+    fn start(path: String, options: LoadOptions) -> Self {
+        let progress = LoadProgress::default();
+        let (sender, result) = mpsc::channel();
+        let worker_progress = progress.clone();
+        let worker_path = path.clone();
+        std::thread::spawn(move || {
+            let loaded = point_cloud::load_with_progress(&worker_path, &options, &worker_progress)
+                .map(|cloud| {
+                    let octree = Octree::build(&cloud);
+                    (cloud, octree)
+                });
+            // The pane may have been closed in the meantime
+            let _ = sender.send(loaded);
+        });
+        Self { path, progress, result }
+    }
+
+    /// The loader's result once it is done, `None` while it is still running.
+    fn poll(&self) -> Option<Result<(PointCloud, Octree), String>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err("Loader thread stopped unexpectedly".to_string())),
+        }
+    }
+}
+
+
+/// What a cache is written from.
+enum CacheSource {
+    /// A loaded layer's points, copied for the worker
+    Cloud(PointCloud),
+    /// A point file, streamed so it never has to fit in memory
+    File(String, LoadOptions),
+}
+
+/// A cache being written on a worker thread, reporting through `progress` like a [`LoadJob`].
+struct ConvertJob {
+    path: String,
+    progress: LoadProgress,
+    result: mpsc::Receiver<Result<(), String>>,
+}
+
+impl ConvertJob {
+    fn start(path: String, source: CacheSource) -> Self {
+        let progress = LoadProgress::default();
+        let (sender, result) = mpsc::channel();
+        let worker_progress = progress.clone();
+        let worker_path = path.clone();
+        std::thread::spawn(move || {
+            let written = match source {
+                CacheSource::Cloud(cloud) => cache::write_cache(&worker_path, &cloud, &worker_progress),
+                CacheSource::File(source, options) => {
+                    cache::convert_file(&source, &options, &worker_path, &worker_progress)
+                }
+            };
+            let _ = sender.send(written);
+        });
+        Self { path, progress, result }
+    }
+
+    /// The converter's result once it is done, `None` while it is still running.
+    fn poll(&self) -> Option<Result<(), String>> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result),
+            Err(mpsc::TryRecvError::Empty) => None,
+            Err(mpsc::TryRecvError::Disconnected) => Some(Err("Cache thread stopped unexpectedly".to_string())),
+        }
+    }
+}
+
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct PointRendererPane {
     #[serde(skip)]
     renderer: Arc<Mutex<PointRenderer>>,
     #[serde(skip)]
-    points: Vec<(i32, i32, i32, Color32)>,
+    layers: Vec<Layer>,
+    #[serde(skip)]
+    next_layer_id: u64,
+    /// Layer the editors and save dialogs act on
+    #[serde(skip)]
+    selected: Option<u64>,
+    /// Origin of the first layer; the scene is drawn relative to it
+    #[serde(skip)]
+    scene_origin: Option<[f64; 3]>,
+    #[serde(skip)]
+    demo_added: bool,
+    #[serde(skip)]
+    tool: Tool,
+    #[serde(skip)]
+    picked: Option<PickedPoint>,
+    #[serde(default)]
+    measurements: Measurements,
+    #[serde(skip)]
+    measurements_open: bool,
+    #[serde(skip)]
+    selection: Selection,
+    #[serde(skip)]
+    selection_open: bool,
+    #[serde(default)]
+    bookmarks: Bookmarks,
+    #[serde(skip)]
+    bookmarks_open: bool,
+    #[serde(default)]
+    clipping: Clipping,
+    #[serde(skip)]
+    clipping_open: bool,
+    #[serde(default)]
+    camera_path: CameraPath,
+    #[serde(skip)]
+    camera_path_open: bool,
+    #[serde(skip)]
+    path_export: Option<Arc<Mutex<PathExport>>>,
     #[serde(skip)]
     file_dialog_open: bool,
     #[serde(skip)]
     cur_path: String,
+    #[serde(default)]
+    load_options: LoadOptions,
+    #[serde(skip)]
+    save_dialog_open: bool,
+    #[serde(skip)]
+    save_path: String,
+    #[serde(skip)]
+    save_format: ply::PlyFormat,
+    #[serde(skip)]
+    loading: Option<LoadJob>,
+    #[serde(skip)]
+    error: Option<String>,
+    #[serde(skip)]
+    cache_dialog_open: bool,
+    #[serde(skip)]
+    cache_path: String,
+    #[serde(skip)]
+    cache_from_file: bool,
+    #[serde(skip)]
+    converting: Option<ConvertJob>,
+    #[serde(skip)]
+    cache_source: String,
+    #[serde(default)]
+    lod: LodSettings,
+    #[serde(default)]
+    shading: ShadingSettings,
+    #[serde(default)]
+    lighting: LightingSettings,
 }
 
 // impl Default for PointRenderer {
@@ -528,15 +1640,129 @@ pub struct PointRendererPane {
 //     }
 // }
 
+impl PointRendererPane {
+    /// Adds a layer, selects it and returns its id.
+    fn add_layer(&mut self, mut layer: Layer) -> u64 {
+        layer.id = self.next_layer_id;
+        self.next_layer_id += 1;
+        self.scene_origin.get_or_insert(layer.cloud.origin);
+        self.selected = Some(layer.id);
+        self.layers.push(layer);
+        self.next_layer_id - 1
+    }
+
+    fn selected_layer(&self) -> Option<&Layer> {
+        self.layers.iter().find(|layer| Some(layer.id) == self.selected)
+    }
+
+    /// Undoes the last selection edit, which may remove a split-off layer, and forgets
+    /// the picked point if it was on a layer that is gone.
+    fn undo_selection(&mut self) {
+        self.selection.undo(&mut self.layers);
+        self.forget_stale_pick();
+    }
+
+    fn forget_stale_pick(&mut self) {
+        if let Some(picked) = &self.picked {
+            if !self.layers.iter().any(|layer| layer.id == picked.layer) {
+                self.picked = None;
+            }
+        }
+    }
+
+    /// Scene bounding box of the visible layers, `None` when nothing is shown.
+    fn scene_bounds(&self) -> Option<(Vec3, Vec3)> {
+        let scene_origin = self.scene_origin.unwrap_or_default();
+        let corners = self.layers.iter()
+            .filter(|layer| layer.visible && !layer.octree.nodes.is_empty())
+            .flat_map(|layer| {
+                let model = layer.model(scene_origin);
+                let root = &layer.octree.nodes[0];
+                let (min, max) = (Vec3::from(root.min), root.max());
+                (0..8).map(move |corner| model.transform_point3(Vec3::select(
+                    glam::BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0), max, min)))
+            });
+        corners.fold(None, |bounds, p| match bounds {
+            None => Some((p, p)),
+            Some((min, max)) => Some((min.min(p), max.max(p))),
+        })
+    }
+
+    /// Moves the camera to show every visible layer; from above when `reset`.
+    fn zoom_to_extents(&self, reset: bool) {
+        let bounds = self.scene_bounds();
+        let mut renderer = self.renderer.lock().expect("Renderer Not Initialized");
+        let Some(camera) = renderer.camera.as_mut() else {
+            return;
+        };
+        let view = if reset { CameraView::default() } else { camera.view() };
+        camera.go_to(match bounds {
+            Some((min, max)) => view.fitted(min, max),
+            None => view,
+        });
+    }
+}
+
+/// What a click in the viewport does; dragging always moves the camera.
+/// With `Navigate`, double-clicking a point makes it the camera's pivot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Tool {
+    #[default]
+    Navigate,
+    Pick,
+    Measure(MeasureKind),
+    Select(SelectShape),
+}
+
+/// How close to the cursor, in points, a click has to land to pick a point.
+const PICK_RADIUS: f32 = 6.0;
+
+/// Layer name for a loaded file: its name without the extension.
+fn layer_name(path: &str) -> String {
+    let path = std::path::Path::new(path);
+    path.file_stem().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
+
 #[typetag::serde]
 impl Pane for PointRendererPane {
     fn new() -> PaneState where Self: Sized {
         let renderer = PointRenderer::default();
         let mut s = Self {
             renderer: Arc::new(Mutex::new(renderer)),
-            points: Vec::new(),
+            layers: Vec::new(),
+            next_layer_id: 0,
+            selected: None,
+            scene_origin: None,
+            demo_added: false,
+            tool: Tool::Navigate,
+            picked: None,
+            measurements: Measurements::default(),
+            measurements_open: false,
+            selection: Selection::default(),
+            selection_open: false,
+            bookmarks: Bookmarks::default(),
+            bookmarks_open: false,
+            clipping: Clipping::default(),
+            clipping_open: false,
+            camera_path: CameraPath::default(),
+            camera_path_open: false,
+            path_export: None,
             file_dialog_open: false,
             cur_path: "./".to_string(),
+            load_options: LoadOptions::default(),
+            save_dialog_open: false,
+            save_path: "./out.ply".to_string(),
+            save_format: ply::PlyFormat::BinaryLittleEndian,
+            loading: None,
+            error: None,
+            cache_dialog_open: false,
+            cache_path: "./cache".to_string(),
+            cache_from_file: false,
+            converting: None,
+            cache_source: String::new(),
+            lod: LodSettings::default(),
+            shading: ShadingSettings::default(),
+            lighting: LightingSettings::default(),
         };
         PaneState {
             id: s.name().to_string(),
@@ -545,7 +1771,7 @@ impl Pane for PointRendererPane {
         }
     }
     fn init(&mut self, pcc: &PsudoCreationContext){
-        self.renderer.lock().expect("Renderer Not Initialized").init(pcc.gl.clone(), 1_000_000);
+        self.renderer.lock().expect("Renderer Not Initialized").init(pcc.gl.clone());
     }
     fn name(&mut self) -> &str {"Point Cloud"}
     fn render(&mut self, ui: &mut Ui){
@@ -557,26 +1783,45 @@ impl Pane for PointRendererPane {
             return;
             // renderer.lock().expect("Renderer Not Initialized").init(ui.ctx()., 1_000_000);
         }
-        renderer.lock().expect("Renderer Not Initialized").clear();
 
         if self.file_dialog_open {
-        egui::Window::new("Load PLY File")
+        egui::Window::new("Load Point Cloud")
             .show(ui.ctx(), |ui| {
-                ui.label("Enter PLY file path:");
+                ui.label("Enter file path (PLY, LAS, PCD, OBJ, XYZ/CSV) or cache directory:");
                 ui.text_edit_singleline(&mut self.cur_path); // Add proper path handling
+
+                let is_cache = cache::is_cache(&self.cur_path);
+                match Format::detect(&self.cur_path) {
+                    _ if is_cache => { ui.label("Octree cache"); }
+                    Ok(Format::Xyz) => {
+                        ui.label(Format::Xyz.name());
+                        self.load_options.xyz.show(ui);
+                    }
+                    Ok(format) => { ui.label(format.name()); }
+                    Err(_) => {}
+                }
                 
                 ui.horizontal(|ui| {
-                    if ui.button("Load").clicked() {
-                        let renderer = &mut renderer.lock().expect("Renderer Not Initialized");
-                            // Add proper path handling and error reporting
-                            let ply = renderer.load_ply(self.cur_path.clone());
-                            if let Err(e) = ply {
-                                eprintln!("Failed to load PLY: {}", e);
-                            }else{
-                                // self.renderer.lock().camera.reset();
-                                self.points = ply.unwrap();
+                    if ui.add_enabled(self.loading.is_none(), egui::Button::new("Load")).clicked() {
+                        self.error = None;
+                        if is_cache {
+                            // Only the hierarchy is read here, points are paged in while drawing
+                            let memory_budget = self.lod.cache_memory_mb * 1024 * 1024;
+                            match CacheReader::open(&self.cur_path, memory_budget) {
+                                Ok(reader) => {
+                                    let cloud = PointCloud { origin: reader.header.origin, ..PointCloud::default() };
+                                    let mut layer = Layer::new(layer_name(&self.cur_path), cloud, reader.header.octree());
+                                    layer.cached_points = Some(reader.header.points);
+                                    layer.cached_attributes = reader.header.attributes.clone();
+                                    layer.dirty = false;
+                                    let id = self.add_layer(layer);
+                                    renderer.lock().expect("Renderer Not Initialized").layer(id).set_cache(reader);
+                                }
+                                Err(e) => self.error = Some(format!("Failed to open cache {}: {}", self.cur_path, e)),
                             }
-                        
+                        } else {
+                            self.loading = Some(LoadJob::start(self.cur_path.clone(), self.load_options.clone()));
+                        }
                         self.file_dialog_open = false;
                     }
                     if ui.button("Cancel").clicked() {
@@ -586,51 +1831,379 @@ impl Pane for PointRendererPane {
             });
         }
 
+        // Add the finished cloud in whole, so a frame never sees a half-read file
+        if let Some(result) = self.loading.as_ref().and_then(LoadJob::poll) {
+            let job = self.loading.take().unwrap();
+            match result {
+                Ok((cloud, octree)) => { self.add_layer(Layer::new(layer_name(&job.path), cloud, octree)); }
+                Err(_) if job.progress.is_cancelled() => {}
+                Err(e) => self.error = Some(format!("Failed to load {}: {}", job.path, e)),
+            }
+        }
+
+        if let Some(job) = &self.loading {
+            ui.ctx().request_repaint();
+            egui::Window::new("Loading")
+                .collapsible(false)
+                .show(ui.ctx(), |ui| {
+                    ui.label(&job.path);
+                    if job.progress.fraction() >= 1.0 {
+                        ui.label("Building octree...");
+                    }
+                    let (read, total) = job.progress.bytes();
+                    ui.add(egui::ProgressBar::new(job.progress.fraction())
+                        .text(format!("{:.1} / {:.1} MB", read as f64 / 1e6, total as f64 / 1e6)));
+                    if job.progress.is_cancelled() {
+                        ui.label("Cancelling...");
+                    } else if ui.button("Cancel").clicked() {
+                        job.progress.cancel();
+                    }
+                });
+        }
+
+        if let Some(error) = &self.error {
+            let mut dismissed = false;
+            egui::Window::new("Error")
+                .collapsible(false)
+                .show(ui.ctx(), |ui| {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                    dismissed = ui.button("Dismiss").clicked();
+                });
+            if dismissed {
+                self.error = None;
+            }
+        }
+
+        if self.save_dialog_open {
+        egui::Window::new("Save PLY File")
+            .show(ui.ctx(), |ui| {
+                ui.label("Enter PLY file path:");
+                ui.text_edit_singleline(&mut self.save_path);
+
+                egui::ComboBox::from_label("Format")
+                    .selected_text(self.save_format.label())
+                    .show_ui(ui, |ui| {
+                        for format in ply::PlyFormat::ALL {
+                            ui.selectable_value(&mut self.save_format, format, format.label());
+                        }
+                    });
+
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        if let Some(layer) = self.selected_layer() {
+                            if let Err(e) = ply::write_ply(&self.save_path, &layer.cloud, self.save_format) {
+                                self.error = Some(format!("Failed to save {}: {}", self.save_path, e));
+                            }
+                        }
+                        self.save_dialog_open = false;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.save_dialog_open = false;
+                    }
+                });
+            });
+        }
+
+        if self.cache_dialog_open {
+        egui::Window::new("Save Octree Cache")
+            .show(ui.ctx(), |ui| {
+                ui.label("Enter cache directory:");
+                ui.text_edit_singleline(&mut self.cache_path);
+
+                let has_cloud = self.selected_layer().is_some_and(|layer| layer.cached_points.is_none());
+                ui.add_enabled_ui(has_cloud, |ui| ui.radio_value(&mut self.cache_from_file, false, "Selected layer"));
+                ui.radio_value(&mut self.cache_from_file, true, "Point file, streamed without loading it");
+                if self.cache_from_file {
+                    ui.text_edit_singleline(&mut self.cache_source);
+                    if let Ok(Format::Xyz) = Format::detect(&self.cache_source) {
+                        self.load_options.xyz.show(ui);
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(self.converting.is_none(), egui::Button::new("Save")).clicked() {
+                        let source = if self.cache_from_file {
+                            Some(CacheSource::File(self.cache_source.clone(), self.load_options.clone()))
+                        } else {
+                            self.selected_layer()
+                                .filter(|layer| layer.cached_points.is_none())
+                                .map(|layer| CacheSource::Cloud(layer.cloud.clone()))
+                        };
+                        if let Some(source) = source {
+                            self.error = None;
+                            self.converting = Some(ConvertJob::start(self.cache_path.clone(), source));
+                        }
+                        self.cache_dialog_open = false;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.cache_dialog_open = false;
+                    }
+                });
+            });
+        }
+
+        if let Some(result) = self.converting.as_ref().and_then(ConvertJob::poll) {
+            let job = self.converting.take().unwrap();
+            match result {
+                Ok(()) => {}
+                Err(_) if job.progress.is_cancelled() => {}
+                Err(e) => self.error = Some(format!("Failed to write cache {}: {}", job.path, e)),
+            }
+        }
+
+        if let Some(job) = &self.converting {
+            ui.ctx().request_repaint();
+            egui::Window::new("Writing Octree Cache")
+                .collapsible(false)
+                .show(ui.ctx(), |ui| {
+                    ui.label(&job.path);
+                    ui.add(egui::ProgressBar::new(job.progress.fraction()).show_percentage());
+                    if job.progress.is_cancelled() {
+                        ui.label("Cancelling...");
+                    } else if ui.button("Cancel").clicked() {
+                        job.progress.cancel();
+                    }
+                });
+        }
+
+        // Fit the clip range to what is shown, however large
+        let bounds = self.scene_bounds();
+        if let Some(camera) = renderer.lock().expect("Renderer Not Initialized").camera.as_mut() {
+            camera.set_scene_bounds(bounds);
+        }
+
         let start_time = Instant::now();
 
         let (rect, response) =
-            ui.allocate_exact_size(egui::Vec2 { x: max_rect.width(), y: max_rect.height() }, egui::Sense::drag());
+            ui.allocate_exact_size(egui::Vec2 { x: max_rect.width(), y: max_rect.height() }, egui::Sense::click_and_drag());
     
 
+        // A press on a clip gizmo's handle drags the handle and nothing else
+        let clip_origin = self.scene_origin.unwrap_or_default();
+        if response.hovered() && ui.input(|i| i.pointer.primary_pressed()) {
+            if let Some(pointer) = ui.input(|i| i.pointer.press_origin()) {
+                let view_projection = renderer.lock().expect("Renderer Not Initialized").view_projection(max_rect);
+                self.clipping.grab(view_projection, max_rect, clip_origin, pointer);
+            }
+        }
+        let gizmo = self.clipping.is_dragging();
+        if gizmo {
+            let view_projection = renderer.lock().expect("Renderer Not Initialized").view_projection(max_rect);
+            self.clipping.drag(view_projection, max_rect, clip_origin, ui.input(|i| i.pointer.delta()));
+        }
+
+        // Drawing a selection takes over primary drags from the camera
+        let drawing = gizmo || matches!(self.tool, Tool::Select(shape) if shape.is_drawn());
+        let typing = ui.ctx().wants_keyboard_input();
         let input_state: Option<InputState> = ui.input(|input_state| 
-            if response.hovered() { //&& response.has_focus() {
-                Some(input_state.clone())
+            if response.hovered() && !(drawing && input_state.pointer.primary_down()) { //&& response.has_focus() {
+                let mut input_state = input_state.clone();
+                // Keys typed into a text field should not fly the camera
+                if typing {
+                    input_state.keys_down.clear();
+                }
+                Some(input_state)
             }else{None}
         );
+        if let Some(i) = &input_state {
+            let renderer = renderer.lock().expect("Renderer Not Initialized");
+            if renderer.camera.as_ref().is_some_and(|camera| camera.fly_direction(i) != Vec3::ZERO) {
+                // Held keys send no events, so keep frames coming while flying
+                ui.ctx().request_repaint();
+            }
+        }
+        if renderer.lock().expect("Renderer Not Initialized").camera.as_ref().is_some_and(Camera::is_animating) {
+            ui.ctx().request_repaint();
+        }
+        if response.hovered() && !typing && ui.input(|i| i.key_pressed(egui::Key::Home)) {
+            self.zoom_to_extents(false);
+        }
 
-        if self.points.is_empty() {
-           let radius = 1000i32;
+        if self.layers.is_empty() && !self.demo_added {
+           let mut cloud = PointCloud::default();
            for i in 0..100000 {
             //    let theta = (i as f32 * 0.1).sin() * std::f32::consts::PI;
             //    let phi = (i as f32 * 0.1).cos() * std::f32::consts::PI;
                
-               let x = (radius as f32 * (i as f32).cos()) as i32;
-               let y = (radius as f32 * (i as f32).sin()) as i32;
-               let z = (i as f32 * 0.05) as i32;
-               
-                // let x = (i as f32 * 0.1) as u32;
-                // let y = (i as f32 * 0.1) as u32 ;
-                // let z = (i as f32 * 0.1) as u32;
+               let x = (i as f32).cos();
+               let y = (i as f32).sin();
+               let z = i as f32 * 0.00005;
 
                // Color based on position
                let color = Color32::from_rgba_premultiplied(
-                   ((x as f32 / radius as f32) * 255.0) as u8,
-                   ((y as f32 / radius as f32) * 255.0) as u8,
-                   ((z as f32 / radius as f32) * 255.0) as u8,
+                   (x * 255.0) as u8,
+                   (y * 255.0) as u8,
+                   (z * 255.0) as u8,
                    255,
                );
                
-               self.points.push((x, y, z, color));
+               // Pushed directly so the spiral stays centred on a zero origin
+               cloud.positions.push([x, y, z]);
+               cloud.colors.push(color);
            }
+           let octree = Octree::build(&cloud);
+           self.add_layer(Layer::new("Spiral", cloud, octree));
+           self.demo_added = true;
+        }
+        if self.layers.is_empty() {
+            self.scene_origin = None;
         }
 
         // let painter = ui.painter();
 
-        for &(x, y, z, color) in &self.points {
-            renderer.lock().expect("Renderer Not Initialized").add_point(x, y, z, color);
+        let (stats, cache_errors) = {
+            let mut renderer = renderer.lock().expect("Renderer Not Initialized");
+            let scene_origin = self.scene_origin.unwrap_or_default();
+            let eye = renderer.camera.as_ref().map_or(Vec3::ZERO, |camera| camera.eye());
+            let mut cache_errors = Vec::new();
+            for layer in &mut self.layers {
+                let render_layer = renderer.layer(layer.id);
+                if layer.dirty && layer.cached_points.is_none() {
+                    render_layer.clear();
+                    let scalars = match &layer.color_mode {
+                        ColorMode::Scalar(ScalarField::Attribute(name)) => layer.cloud.attribute(name),
+                        _ => None,
+                    };
+                    let normals = layer.cloud.normals();
+                    let normal = |i: usize| normals.map(|axes| axes.map(|axis| axis.values[i]));
+                    if let Some(scalars) = scalars {
+                        for &i in &layer.octree.order {
+                            let i = i as usize;
+                            render_layer.add_scalar_point(layer.cloud.positions[i], scalars.values[i], normal(i), layer.is_selected(i));
+                        }
+                    } else {
+                        let colors = layer.color_mode.colors(&layer.cloud);
+                        let colors = colors.as_ref().unwrap_or(&layer.cloud.colors);
+                        for &i in &layer.octree.order {
+                            let i = i as usize;
+                            render_layer.add_point(layer.cloud.positions[i], colors[i], normal(i), layer.is_selected(i));
+                        }
+                    }
+                    render_layer.set_octree(layer.octree.clone());
+                }
+                if layer.cached_points.is_some() {
+                    render_layer.set_cache_coloring(layer.cache_coloring());
+                }
+                layer.dirty = false;
+                layer.update_range(scene_origin, eye);
+                render_layer.style = layer.style(scene_origin);
+                if let Some(e) = render_layer.cache_error() {
+                    cache_errors.push(format!("{}: {}", layer.name, e));
+                }
+            }
+            let ids: Vec<u64> = self.layers.iter().map(|layer| layer.id).collect();
+            renderer.retain_layers(&ids);
+            renderer.lod = self.lod.clone();
+            renderer.shading = self.shading.clone();
+            renderer.lighting = self.lighting.clone();
+            renderer.clip = self.clipping.volume(scene_origin);
+            (renderer.stats, cache_errors)
+        };
+        if stats.pending_nodes > 0 {
+            // Keep drawing frames until every selected node is on the GPU
+            ui.ctx().request_repaint();
         }
 
-        let o = <std::option::Option<Camera> as Clone>::clone(&renderer.lock().expect("Renderer Not Initialized").camera).unwrap().orientation.clone();
+        let scene_origin = self.scene_origin.unwrap_or_default();
+        if self.tool != Tool::Navigate && response.clicked() && !gizmo {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                let pick = renderer.lock().expect("Renderer Not Initialized").pick(max_rect, pointer, PICK_RADIUS);
+                let picked = pick.and_then(|pick| {
+                    self.layers.iter().find(|layer| layer.id == pick.layer).map(|layer| (layer, pick))
+                });
+                match self.tool {
+                    Tool::Pick => self.picked = picked.map(|(layer, pick)| layer.inspect(&pick)),
+                    Tool::Measure(kind) => if let Some((layer, pick)) = picked {
+                        let scene = layer.model(scene_origin).transform_point3(pick.local);
+                        self.measurements.add_point(kind, std::array::from_fn(|axis| scene_origin[axis] + scene[axis] as f64));
+                    },
+                    // Placed shapes are centred on the picked point, of any layer
+                    Tool::Select(shape @ (SelectShape::Sphere | SelectShape::Box)) => if let Some((layer, pick)) = picked {
+                        let center = layer.model(scene_origin).transform_point3(pick.local);
+                        let combine = Combine::from_modifiers(ui.input(|i| i.modifiers));
+                        if let Some(layer) = self.layers.iter_mut().find(|layer| Some(layer.id) == self.selected && layer.cached_points.is_none()) {
+                            match shape {
+                                SelectShape::Sphere => self.selection.select_sphere(layer, scene_origin, center, combine),
+                                _ => self.selection.select_box(layer, scene_origin, center, combine),
+                            }
+                        }
+                    },
+                    Tool::Select(_) | Tool::Navigate => {}
+                }
+            }
+        }
+        // Double-clicking a point orbits around it from then on
+        if self.tool == Tool::Navigate && response.double_clicked() && !gizmo {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                let mut renderer = renderer.lock().expect("Renderer Not Initialized");
+                let pick = renderer.pick(max_rect, pointer, PICK_RADIUS);
+                let pivot = pick.and_then(|pick| {
+                    let layer = self.layers.iter().find(|layer| layer.id == pick.layer)?;
+                    Some(layer.model(scene_origin).transform_point3(pick.local))
+                });
+                if let (Some(pivot), Some(camera)) = (pivot, renderer.camera.as_mut()) {
+                    camera.set_pivot(pivot);
+                }
+            }
+        }
+        if let Tool::Select(shape) = self.tool {
+            if shape.is_drawn() && !gizmo {
+                if let Some(pointer) = response.interact_pointer_pos() {
+                    if response.drag_started_by(egui::PointerButton::Primary) {
+                        self.selection.begin_stroke(pointer);
+                    } else if response.dragged_by(egui::PointerButton::Primary) {
+                        self.selection.extend_stroke(shape, pointer);
+                    }
+                }
+                if response.drag_stopped_by(egui::PointerButton::Primary) {
+                    let view_projection = renderer.lock().expect("Renderer Not Initialized").view_projection(max_rect);
+                    let combine = Combine::from_modifiers(ui.input(|i| i.modifiers));
+                    let layer = self.layers.iter_mut().find(|layer| Some(layer.id) == self.selected && layer.cached_points.is_none());
+                    if let Some(layer) = layer {
+                        self.selection.end_stroke(shape, layer, view_projection, max_rect, scene_origin, combine);
+                    }
+                }
+            }
+            if response.hovered() && ui.input_mut(|i| i.consume_key(egui::Modifiers::COMMAND, egui::Key::Z)) {
+                self.undo_selection();
+            }
+        }
+        if !ui.input(|i| i.pointer.primary_down()) {
+            self.clipping.release();
+        }
+        if let Tool::Measure(_) = self.tool {
+            if response.double_clicked() || (response.hovered() && ui.input(|i| i.key_pressed(egui::Key::Enter))) {
+                self.measurements.finish();
+            }
+            if response.hovered() && ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.measurements.current = None;
+            }
+        }
+        // Forget the point once its layer is gone
+        self.forget_stale_pick();
+        let view_projection = renderer.lock().expect("Renderer Not Initialized").view_projection(max_rect);
+
+        let o = <std::option::Option<Camera> as Clone>::clone(&renderer.lock().expect("Renderer Not Initialized").camera).unwrap().orientation;
+
+        if self.camera_path.playing {
+            let duration = self.camera_path.duration();
+            self.camera_path.time = (self.camera_path.time + ui.input(|i| i.stable_dt)).min(duration);
+            self.camera_path.playing = self.camera_path.time < duration;
+            if let Some(view) = self.camera_path.view_at(self.camera_path.time, scene_origin) {
+                renderer.lock().expect("Renderer Not Initialized").camera.as_mut().expect("Not Initialised").set_view(view);
+            }
+            ui.ctx().request_repaint();
+        }
+        if let Some(export) = &self.path_export {
+            // One frame per paint, drawn offscreen ahead of the viewport
+            let export = export.clone();
+            let renderer = renderer.clone();
+            let cb = egui_glow::CallbackFn::new(move |_info, _painter| {
+                export.lock().expect("Export Not Initialized").step(&mut renderer.lock().expect("Renderer Not Initialized"));
+            });
+            ui.painter().add(egui::PaintCallback { rect: max_rect, callback: Arc::new(cb) });
+            ui.ctx().request_repaint();
+        }
 
         let cb = egui_glow::CallbackFn::new(move |_info, _painter| {
             renderer.lock().expect("Renderer Not Initialized").render(max_rect, input_state.clone());
@@ -681,13 +2254,291 @@ impl Pane for PointRendererPane {
             format!("{} ms",end_time.duration_since(start_time).as_millis()), 
             FontId::monospace(text_size), Color32::WHITE);
 
+        let total: usize = self.layers.iter().map(Layer::len).sum();
         ui.painter().text(max_rect.min + egui::Vec2 {x:0.,y:text_size}, Align2::LEFT_TOP, 
-            format!("{} points", self.points.len()), 
+            format!("{} of {} points in {} layers, {} nodes{}", stats.drawn_points, total, self.layers.len(), stats.drawn_nodes,
+                if stats.pending_nodes > 0 { ", refining" } else { "" }), 
             FontId::monospace(text_size), Color32::WHITE);
+
+        let mut lines = Vec::new();
+        if let Some(layer) = self.selected_layer() {
+            if !layer.cloud.attributes.is_empty() {
+                let names: Vec<&str> = layer.cloud.attribute_names().collect();
+                lines.push((format!("{} attributes: {}", layer.name, names.join(", ")), Color32::WHITE));
+            }
+        }
+        if stats.cache_bytes > 0 {
+            lines.push((format!("cache: {:.0} MB in memory", stats.cache_bytes as f64 / 1e6), Color32::WHITE));
+        }
+        for e in cache_errors {
+            lines.push((e, ui.visuals().error_fg_color));
+        }
+        for (row, (text, color)) in lines.into_iter().enumerate() {
+            ui.painter().text(max_rect.min + egui::Vec2 {x:0.,y:text_size*(2 + row) as f32}, Align2::LEFT_TOP,
+                text, FontId::monospace(text_size), color);
+        }
+
+        if let Some(layer) = self.selected_layer() {
+            layer.paint_legend(ui.painter(), max_rect);
+        }
+
+        self.measurements.paint(ui.painter(), max_rect, view_projection, scene_origin);
+        self.clipping.paint(ui.painter(), view_projection, max_rect, scene_origin);
+        if let Tool::Select(shape) = self.tool {
+            self.selection.paint(ui.painter(), shape);
+        }
+        if self.selection_open {
+            let mut open = true;
+            let next_id = self.next_layer_id;
+            let layer = self.layers.iter_mut().find(|layer| Some(layer.id) == self.selected);
+            let mut split = None;
+            let mut undo = false;
+            egui::Window::new("Selection")
+                .open(&mut open)
+                .show(ui.ctx(), |ui| {
+                    split = self.selection.show(ui, layer, next_id);
+                    ui.separator();
+                    undo = ui.add_enabled(self.selection.can_undo(), egui::Button::new("Undo")).clicked();
+                });
+            self.selection_open = open;
+            if let Some(split) = split {
+                self.add_layer(split);
+            }
+            if undo {
+                self.undo_selection();
+            }
+        }
+        if self.bookmarks_open {
+            let mut renderer = self.renderer.lock().expect("Renderer Not Initialized");
+            if let Some(camera) = renderer.camera.as_mut() {
+                let current = camera.view();
+                let mut target = None;
+                egui::Window::new("Bookmarks")
+                    .open(&mut self.bookmarks_open)
+                    .show(ui.ctx(), |ui| target = self.bookmarks.show(ui, &current, scene_origin));
+                if let Some(view) = target {
+                    camera.go_to(view);
+                }
+            }
+        }
+        let export_progress = match self.path_export.as_ref().map(|export| export.lock().expect("Export Not Initialized").poll()) {
+            Some(Err(e)) => {
+                self.error = Some(format!("Failed to export frames: {}", e));
+                self.path_export = None;
+                None
+            }
+            Some(Ok(progress)) => Some(progress),
+            None => None,
+        };
+        if self.path_export.as_ref().is_some_and(|export| export.lock().expect("Export Not Initialized").is_done()) {
+            self.path_export = None;
+        }
+        if self.camera_path_open {
+            let mut renderer = self.renderer.lock().expect("Renderer Not Initialized");
+            if let Some(camera) = renderer.camera.as_mut() {
+                let current = camera.view();
+                let mut event = None;
+                egui::Window::new("Camera Path")
+                    .open(&mut self.camera_path_open)
+                    .show(ui.ctx(), |ui| event = self.camera_path.show(ui, &current, scene_origin, export_progress));
+                match event {
+                    Some(PathEvent::Seek) => if let Some(view) = self.camera_path.view_at(self.camera_path.time, scene_origin) {
+                        camera.set_view(view);
+                    },
+                    Some(PathEvent::Export) => {
+                        let path = &self.camera_path;
+                        match PathExport::start(&path.export_dir, path.width, path.height, path.frames(scene_origin)) {
+                            Ok(export) => self.path_export = Some(Arc::new(Mutex::new(export))),
+                            Err(e) => self.error = Some(e),
+                        }
+                    }
+                    Some(PathEvent::CancelExport) => self.path_export = None,
+                    None => {}
+                }
+            }
+        }
+        if self.clipping_open {
+            let bounds = self.scene_bounds();
+            let can_crop = self.selected_layer().is_some_and(|layer| layer.cached_points.is_none());
+            let mut crop = false;
+            egui::Window::new("Clipping")
+                .open(&mut self.clipping_open)
+                .show(ui.ctx(), |ui| crop = self.clipping.show(ui, bounds, scene_origin, can_crop));
+            if crop {
+                let volume = self.clipping.volume(scene_origin);
+                if let Some(layer) = self.layers.iter_mut().find(|layer| Some(layer.id) == self.selected && layer.cached_points.is_none()) {
+                    let model = layer.model(scene_origin);
+                    let keep: Vec<bool> = layer.cloud.positions.iter()
+                        .map(|&p| volume.keeps(model.transform_point3(Vec3::from(p))))
+                        .collect();
+                    self.selection.crop_to(layer, &keep);
+                }
+            }
+        }
+        if self.measurements_open {
+            egui::Window::new("Measurements")
+                .open(&mut self.measurements_open)
+                .show(ui.ctx(), |ui| self.measurements.show(ui));
+        }
+
+        if let Some(picked) = &self.picked {
+            let Some(layer) = self.layers.iter().find(|layer| layer.id == picked.layer) else {
+                self.picked = None;
+                return;
+            };
+            let model = layer.style(scene_origin).model;
+            if let Some(screen) = measurements::to_screen(view_projection, max_rect, model.transform_point3(picked.local)) {
+                ui.painter().circle_stroke(screen, PICK_RADIUS, Stroke { width: 2.0, color: Color32::YELLOW });
+            }
+
+            let mut open = true;
+            egui::Window::new(format!("Picked Point ({})", layer.name))
+                .id(egui::Id::new("picked_point"))
+                .open(&mut open)
+                .show(ui.ctx(), |ui| picked.show(ui));
+            if !open {
+                self.picked = None;
+            }
+        }
     }
     fn context_menu(&mut self, ui: &mut Ui) {
-        if ui.button("Load PLY").clicked() {
+        if ui.button("Load Point Cloud").clicked() {
             self.file_dialog_open = true;
         }
+        let has_cloud = self.selected_layer().is_some_and(|layer| layer.cached_points.is_none());
+        if ui.add_enabled(has_cloud, egui::Button::new("Save PLY")).clicked() {
+            self.save_dialog_open = true;
+        }
+        if ui.button("Save Octree Cache").clicked() {
+            // Without a loaded cloud only a file can be converted
+            self.cache_from_file |= !has_cloud;
+            self.cache_dialog_open = true;
+        }
+        ui.menu_button("Tool", |ui| {
+            ui.radio_value(&mut self.tool, Tool::Navigate, "Navigate");
+            ui.radio_value(&mut self.tool, Tool::Pick, "Pick Points");
+            for kind in MeasureKind::ALL {
+                if ui.radio_value(&mut self.tool, Tool::Measure(kind), format!("Measure {}", kind.label())).clicked() {
+                    self.measurements_open = true;
+                }
+            }
+            for shape in SelectShape::ALL {
+                if ui.radio_value(&mut self.tool, Tool::Select(shape), format!("Select {}", shape.label())).clicked() {
+                    self.selection_open = true;
+                }
+            }
+        });
+        if self.tool != Tool::Pick {
+            self.picked = None;
+        }
+        if ui.button("Measurements").clicked() {
+            self.measurements_open = true;
+        }
+        if ui.button("Selection").clicked() {
+            self.selection_open = true;
+        }
+        if ui.button("Clipping").clicked() {
+            self.clipping_open = true;
+        }
+        ui.menu_button("Camera", |ui| {
+            if let Some(camera) = self.renderer.lock().expect("Renderer Not Initialized").camera.as_mut() {
+                camera.show(ui);
+            }
+            ui.separator();
+            if ui.button("Zoom to Extents (Home)").clicked() {
+                self.zoom_to_extents(false);
+            }
+            if ui.button("Reset View").clicked() {
+                self.zoom_to_extents(true);
+            }
+            if ui.button("Bookmarks").clicked() {
+                self.bookmarks_open = true;
+            }
+            if ui.button("Camera Path").clicked() {
+                self.camera_path_open = true;
+            }
+        });
+        ui.menu_button("Layers", |ui| {
+            layers::show_layers(&mut self.layers, &mut self.selected, ui);
+        });
+        ui.menu_button("Shading", |ui| {
+            self.shading.show(ui);
+        });
+        ui.menu_button("Normals", |ui| {
+            if !self.layers.iter().any(|layer| layer.cloud.normals().is_some()) {
+                ui.label("No layer has normals");
+            }
+            self.lighting.show(ui);
+        });
+        ui.menu_button("Level of Detail", |ui| {
+            let mut millions = self.lod.point_budget as f64 / 1e6;
+            ui.horizontal(|ui| {
+                ui.label("Point budget");
+                if ui.add(egui::DragValue::new(&mut millions).range(0.1..=100.0).speed(0.1).suffix(" M")).changed() {
+                    self.lod.point_budget = (millions * 1e6) as usize;
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Max point spacing");
+                ui.add(egui::Slider::new(&mut self.lod.max_spacing_px, 0.5..=16.0).suffix(" px").logarithmic(true));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Cache memory");
+                ui.add(egui::DragValue::new(&mut self.lod.cache_memory_mb).range(64..=65536).suffix(" MB"));
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Depth of every corner of the box in normalised device coordinates.
+    fn corner_depths(camera: &Camera, min: Vec3, max: Vec3) -> Vec<f32> {
+        let view_projection = camera.get_projection_matrix(1.5) * camera.get_view_matrix();
+        (0..8)
+            .map(|corner| Vec3::select(glam::BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0), max, min))
+            .map(|p| view_projection.project_point3(p).z)
+            .collect()
+    }
+
+    #[test]
+    fn a_fitted_large_scene_is_not_clipped() {
+        // A 10 km aerial tile
+        let (min, max) = (Vec3::new(-5000.0, -5000.0, 0.0), Vec3::new(5000.0, 5000.0, 300.0));
+        for projection in [Projection::Perspective, Projection::Orthographic] {
+            for tilt in [0.0, 1.0, std::f32::consts::FRAC_PI_2] {
+                let mut camera = Camera::new();
+                let view = CameraView { orientation: turntable(0.3, tilt), projection, ..CameraView::default() };
+                camera.set_view(view.fitted(min, max));
+                camera.set_scene_bounds(Some((min, max)));
+
+                let depths = corner_depths(&camera, min, max);
+                assert!(depths.iter().all(|z| (0.0..=1.0).contains(z)), "{:?} at tilt {}: {:?}", projection, tilt, depths);
+            }
+        }
+    }
+
+    #[test]
+    fn an_orthographic_view_keeps_the_scene_behind_the_eye() {
+        let (min, max) = (Vec3::splat(-10.0), Vec3::splat(10.0));
+        let mut camera = Camera::new();
+        // The eye is inside the scene, far closer than its size
+        camera.set_view(CameraView { distance: 1.0, projection: Projection::Orthographic, ..CameraView::default() });
+        camera.set_scene_bounds(Some((min, max)));
+
+        assert!(camera.depth_offset() > 0.0);
+        assert!(corner_depths(&camera, min, max).iter().all(|z| (0.0..=1.0).contains(z)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn point_sizes_do_not_depend_on_the_clip_range() {
+        let mut camera = Camera::new();
+        camera.set_view(CameraView { distance: 50.0, projection: Projection::Orthographic, ..CameraView::default() });
+        let before = camera.fixed_point_size();
+        camera.set_scene_bounds(Some((Vec3::splat(-1e4), Vec3::splat(1e4))));
+        assert_eq!(camera.fixed_point_size(), before);
+        assert_eq!(before, POINT_SIZE_DEPTH / 50.0);
+    }
+}
@@ -0,0 +1,438 @@
+use egui::{Color32, Modifiers, Pos2, Rect, Stroke, Ui};
+use glam::{Mat4, Vec3};
+use std::sync::Arc;
+use crate::panes::layers::Layer;
+use crate::panes::measurements::to_screen;
+use crate::point_cloud::octree::{box_in_frustum, frustum_planes, Octree};
+use crate::point_cloud::PointCloud;
+
+/// Steps kept for undo; each edit of the points holds a copy of the layer's cloud.
+const MAX_UNDO: usize = 16;
+
+/// How points are chosen. Rectangle and lasso are drawn on screen, sphere and box
+/// are placed around a picked point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectShape {
+    Rectangle,
+    Lasso,
+    Sphere,
+    Box,
+}
+
+impl SelectShape {
+    pub const ALL: [SelectShape; 4] = [SelectShape::Rectangle, SelectShape::Lasso, SelectShape::Sphere, SelectShape::Box];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SelectShape::Rectangle => "Rectangle",
+            SelectShape::Lasso => "Lasso",
+            SelectShape::Sphere => "Sphere",
+            SelectShape::Box => "Box",
+        }
+    }
+
+    /// Drawn by dragging, so primary drags must not move the camera.
+    pub fn is_drawn(&self) -> bool {
+        matches!(self, SelectShape::Rectangle | SelectShape::Lasso)
+    }
+}
+
+/// How a new selection combines with the existing one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Combine {
+    Replace,
+    Add,
+    Remove,
+}
+
+impl Combine {
+    /// Shift adds to the selection, Ctrl (Cmd) takes away from it.
+    pub fn from_modifiers(modifiers: Modifiers) -> Self {
+        if modifiers.shift {
+            Combine::Add
+        } else if modifiers.command {
+            Combine::Remove
+        } else {
+            Combine::Replace
+        }
+    }
+}
+
+/// A layer's state before an edit, to go back to on undo.
+struct UndoStep {
+    layer: u64,
+    selection: Vec<bool>,
+    /// Points before an edit that changed them; `None` when only the selection changed
+    points: Option<(PointCloud, Arc<Octree>)>,
+    /// Layer split off by the edit, removed again on undo
+    created: Option<u64>,
+}
+
+/// Selection tool settings, the shape being drawn and the undo history.
+pub struct Selection {
+    pub sphere_radius: f32,
+    /// Half the box's size along each scene axis
+    pub box_extent: [f32; 3],
+    /// Pointer positions of the rectangle or lasso being dragged
+    stroke: Vec<Pos2>,
+    undo: Vec<UndoStep>,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self {
+            sphere_radius: 1.0,
+            box_extent: [1.0; 3],
+            stroke: Vec::new(),
+            undo: Vec::new(),
+        }
+    }
+}
+
+impl Selection {
+    pub fn begin_stroke(&mut self, pointer: Pos2) {
+        self.stroke = vec![pointer];
+    }
+
+    pub fn extend_stroke(&mut self, shape: SelectShape, pointer: Pos2) {
+        match shape {
+            SelectShape::Rectangle => {
+                self.stroke.truncate(1);
+                self.stroke.push(pointer);
+            }
+            // Skip pointer jitter, the lasso only needs its outline
+            _ if self.stroke.last().is_some_and(|last| last.distance(pointer) < 2.0) => {}
+            _ => self.stroke.push(pointer),
+        }
+    }
+
+    /// Selects the layer's points inside the finished rectangle or lasso.
+    pub fn end_stroke(&mut self, shape: SelectShape, layer: &mut Layer, view_projection: Mat4, viewport: Rect, scene_origin: [f64; 3], combine: Combine) {
+        let stroke = std::mem::take(&mut self.stroke);
+        if stroke.len() < 2 {
+            return;
+        }
+        let bounds = Rect::from_points(&stroke);
+        let inside: Box<dyn Fn(Pos2) -> bool> = match shape {
+            SelectShape::Lasso if stroke.len() > 2 => Box::new(move |p| in_polygon(&stroke, p)),
+            SelectShape::Lasso => return,
+            _ => Box::new(move |p| bounds.contains(p)),
+        };
+        let mvp = view_projection * layer.model(scene_origin);
+        let indices = screen_indices(layer, mvp, viewport, bounds, &*inside);
+        self.select(layer, &indices, combine);
+    }
+
+    /// Selects the layer's points within `sphere_radius` of `center`, in scene coordinates.
+    pub fn select_sphere(&mut self, layer: &mut Layer, scene_origin: [f64; 3], center: Vec3, combine: Combine) {
+        let model = layer.model(scene_origin);
+        let radius = self.sphere_radius;
+        let indices = scene_indices(layer, |p| model.transform_point3(p).distance(center) <= radius);
+        self.select(layer, &indices, combine);
+    }
+
+    /// Selects the layer's points in the axis aligned box of `box_extent` around `center`.
+    pub fn select_box(&mut self, layer: &mut Layer, scene_origin: [f64; 3], center: Vec3, combine: Combine) {
+        let model = layer.model(scene_origin);
+        let extent = Vec3::from(self.box_extent);
+        let indices = scene_indices(layer, |p| (model.transform_point3(p) - center).abs().cmple(extent).all());
+        self.select(layer, &indices, combine);
+    }
+
+    fn select(&mut self, layer: &mut Layer, indices: &[usize], combine: Combine) {
+        if combine == Combine::Remove && layer.selection.is_empty() {
+            return;
+        }
+        self.record(layer, false, None);
+        let mut selection = match combine {
+            Combine::Replace => vec![false; layer.cloud.len()],
+            _ => std::mem::take(&mut layer.selection),
+        };
+        selection.resize(layer.cloud.len(), false);
+        for &i in indices {
+            selection[i] = combine != Combine::Remove;
+        }
+        set_selection(layer, selection);
+    }
+
+    pub fn invert(&mut self, layer: &mut Layer) {
+        self.record(layer, false, None);
+        let mut selection = std::mem::take(&mut layer.selection);
+        selection.resize(layer.cloud.len(), false);
+        selection.iter_mut().for_each(|s| *s = !*s);
+        set_selection(layer, selection);
+    }
+
+    pub fn clear(&mut self, layer: &mut Layer) {
+        if layer.selection.is_empty() {
+            return;
+        }
+        self.record(layer, false, None);
+        set_selection(layer, Vec::new());
+    }
+
+    /// Removes the selected points from the layer.
+    pub fn delete(&mut self, layer: &mut Layer) {
+        let keep: Vec<bool> = (0..layer.cloud.len()).map(|i| !layer.is_selected(i)).collect();
+        self.record(layer, true, None);
+        replace_points(layer, layer.cloud.filter(&keep));
+    }
+
+    /// Keeps only the selected points.
+    pub fn crop(&mut self, layer: &mut Layer) {
+        let keep: Vec<bool> = (0..layer.cloud.len()).map(|i| layer.is_selected(i)).collect();
+        self.crop_to(layer, &keep);
+    }
+
+    /// Keeps only the points `keep` marks, undoably like the other edits.
+    pub fn crop_to(&mut self, layer: &mut Layer, keep: &[bool]) {
+        self.record(layer, true, None);
+        replace_points(layer, layer.cloud.filter(keep));
+    }
+
+    /// Moves the selected points out of the layer into a new one, which takes the
+    /// layer's placement and look. `id` is the new layer's id.
+    pub fn split(&mut self, layer: &mut Layer, id: u64) -> Layer {
+        let selected = std::mem::take(&mut layer.selection);
+        let mut split = Layer::new(format!("{} (split)", layer.name), layer.cloud.filter(&selected), Octree::default());
+        split.octree = Arc::new(Octree::build(&split.cloud));
+        split.id = id;
+        split.translation = layer.translation;
+        split.rotation = layer.rotation;
+        split.scale = layer.scale;
+        split.point_size = layer.point_size;
+        split.color_mode = layer.color_mode.clone();
+        split.ramp = layer.ramp;
+
+        layer.selection = selected;
+        self.record(layer, true, Some(id));
+        let keep: Vec<bool> = layer.selection.iter().map(|&s| !s).collect();
+        replace_points(layer, layer.cloud.filter(&keep));
+        split
+    }
+
+    fn record(&mut self, layer: &Layer, points: bool, created: Option<u64>) {
+        if self.undo.len() == MAX_UNDO {
+            self.undo.remove(0);
+        }
+        self.undo.push(UndoStep {
+            layer: layer.id,
+            selection: layer.selection.clone(),
+            points: points.then(|| (layer.cloud.clone(), layer.octree.clone())),
+            created,
+        });
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Reverts the last selection change or edit. Steps for layers that were removed since are skipped.
+    pub fn undo(&mut self, layers: &mut Vec<Layer>) {
+        while let Some(step) = self.undo.pop() {
+            let Some(layer) = layers.iter_mut().find(|layer| layer.id == step.layer) else {
+                continue;
+            };
+            if let Some((cloud, octree)) = step.points {
+                layer.cloud = cloud;
+                layer.octree = octree;
+            }
+            set_selection(layer, step.selection);
+            if let Some(created) = step.created {
+                layers.retain(|layer| layer.id != created);
+            }
+            return;
+        }
+    }
+
+    /// The rectangle or lasso being dragged.
+    pub fn paint(&self, painter: &egui::Painter, shape: SelectShape) {
+        let stroke = Stroke { width: 1.5, color: Color32::from_rgb(255, 200, 30) };
+        match shape {
+            SelectShape::Rectangle if self.stroke.len() == 2 => {
+                painter.rect_stroke(Rect::from_points(&self.stroke), 0.0, stroke);
+            }
+            SelectShape::Lasso if self.stroke.len() > 1 => {
+                let mut outline = self.stroke.clone();
+                outline.push(self.stroke[0]);
+                painter.add(egui::Shape::line(outline, stroke));
+            }
+            _ => {}
+        }
+    }
+
+    /// Settings for the placed shapes and the edits, acting on `layer`.
+    /// Returns a layer split off from it, given id `next_id`.
+    pub fn show(&mut self, ui: &mut Ui, layer: Option<&mut Layer>, next_id: u64) -> Option<Layer> {
+        ui.horizontal(|ui| {
+            ui.label("Sphere radius");
+            ui.add(egui::DragValue::new(&mut self.sphere_radius).range(0.001..=f32::MAX).speed(0.05));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Box half size");
+            for v in &mut self.box_extent {
+                ui.add(egui::DragValue::new(v).range(0.001..=f32::MAX).speed(0.05));
+            }
+        });
+        ui.label("Shift adds to the selection, Ctrl removes from it");
+        ui.separator();
+
+        let mut split = None;
+        match layer {
+            Some(layer) if layer.cached_points.is_none() => {
+                let count = layer.selected_count();
+                ui.label(format!("{}: {} of {} points selected", layer.name, count, layer.cloud.len()));
+                ui.horizontal(|ui| {
+                    if ui.button("Invert").clicked() {
+                        self.invert(layer);
+                    }
+                    if ui.add_enabled(count > 0, egui::Button::new("Clear")).clicked() {
+                        self.clear(layer);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(count > 0, egui::Button::new("Delete")).clicked() {
+                        self.delete(layer);
+                    }
+                    if ui.add_enabled(count > 0, egui::Button::new("Crop")).clicked() {
+                        self.crop(layer);
+                    }
+                    if ui.add_enabled(count > 0, egui::Button::new("Split to Layer")).clicked() {
+                        split = Some(self.split(layer, next_id));
+                    }
+                });
+            }
+            Some(_) => {
+                ui.label("Points streamed from a cache cannot be edited");
+            }
+            None => {
+                ui.label("Select a layer to edit");
+            }
+        }
+        split
+    }
+}
+
+fn set_selection(layer: &mut Layer, selection: Vec<bool>) {
+    layer.selection = if selection.iter().any(|&s| s) { selection } else { Vec::new() };
+    layer.dirty = true;
+}
+
+fn replace_points(layer: &mut Layer, cloud: PointCloud) {
+    layer.octree = Arc::new(Octree::build(&cloud));
+    layer.cloud = cloud;
+    layer.selection.clear();
+    layer.dirty = true;
+}
+
+/// Cloud indices of the points whose projection falls in `bounds` and passes `inside`.
+/// Octree nodes outside the frustum through `bounds` are skipped whole.
+fn screen_indices(layer: &Layer, mvp: Mat4, viewport: Rect, bounds: Rect, inside: &dyn Fn(Pos2) -> bool) -> Vec<usize> {
+    let mut indices = Vec::new();
+    if layer.octree.nodes.is_empty() {
+        return indices;
+    }
+    // Maps the part of the viewport inside `bounds` onto the whole clip volume
+    let ndc = |p: Pos2| glam::Vec2::new(
+        (p.x - viewport.left()) / viewport.width() * 2.0 - 1.0,
+        1.0 - (p.y - viewport.top()) / viewport.height() * 2.0,
+    );
+    let center = ndc(bounds.center());
+    let half = ((ndc(bounds.max) - ndc(bounds.min)) / 2.0).abs().max(glam::Vec2::splat(1e-6));
+    let bounds_matrix = Mat4::from_scale(Vec3::new(1.0 / half.x, 1.0 / half.y, 1.0))
+        * Mat4::from_translation(Vec3::new(-center.x, -center.y, 0.0));
+    let planes = frustum_planes(bounds_matrix * mvp);
+
+    let octree = &layer.octree;
+    let mut stack = vec![0u32];
+    while let Some(node) = stack.pop() {
+        let n = &octree.nodes[node as usize];
+        if !box_in_frustum(&planes, Vec3::from(n.min), n.max()) {
+            continue;
+        }
+        stack.extend_from_slice(&n.children);
+        for &i in &octree.order[n.first..n.first + n.count] {
+            let i = i as usize;
+            if let Some(screen) = to_screen(mvp, viewport, Vec3::from(layer.cloud.positions[i])) {
+                if inside(screen) {
+                    indices.push(i);
+                }
+            }
+        }
+    }
+    indices
+}
+
+/// Cloud indices of the points, in the layer's own coordinates, that pass `inside`.
+fn scene_indices(layer: &Layer, inside: impl Fn(Vec3) -> bool) -> Vec<usize> {
+    layer.cloud.positions.iter()
+        .enumerate()
+        .filter(|(_, &p)| inside(Vec3::from(p)))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Even-odd test of `p` against the closed outline `polygon`.
+fn in_polygon(polygon: &[Pos2], p: Pos2) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.y > p.y) != (b.y > p.y) && p.x < (b.x - a.x) * (p.y - a.y) / (b.y - a.y) + a.x {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outline(points: &[(f32, f32)]) -> Vec<Pos2> {
+        points.iter().map(|&(x, y)| Pos2::new(x, y)).collect()
+    }
+
+    #[test]
+    fn square() {
+        let square = outline(&[(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)]);
+        assert!(in_polygon(&square, Pos2::new(5.0, 5.0)));
+        assert!(in_polygon(&square, Pos2::new(0.5, 9.5)));
+        assert!(!in_polygon(&square, Pos2::new(-1.0, 5.0)));
+        assert!(!in_polygon(&square, Pos2::new(5.0, 11.0)));
+        assert!(!in_polygon(&square, Pos2::new(11.0, 5.0)));
+    }
+
+    #[test]
+    fn concave_notch_is_outside() {
+        // A U shape open at the top
+        let u = outline(&[(0.0, 0.0), (9.0, 0.0), (9.0, 9.0), (6.0, 9.0), (6.0, 3.0), (3.0, 3.0), (3.0, 9.0), (0.0, 9.0)]);
+        assert!(!in_polygon(&u, Pos2::new(4.5, 6.0)));
+        assert!(in_polygon(&u, Pos2::new(1.5, 6.0)));
+        assert!(in_polygon(&u, Pos2::new(7.5, 6.0)));
+        assert!(in_polygon(&u, Pos2::new(4.5, 1.5)));
+    }
+
+    #[test]
+    fn rays_through_vertices_count_once() {
+        let diamond = outline(&[(0.0, -1.0), (1.0, 0.0), (0.0, 1.0), (-1.0, 0.0)]);
+        assert!(in_polygon(&diamond, Pos2::new(0.0, 0.0)));
+        assert!(!in_polygon(&diamond, Pos2::new(-2.0, 0.0)));
+        assert!(!in_polygon(&diamond, Pos2::new(2.0, 0.0)));
+    }
+
+    #[test]
+    fn self_intersecting_lasso_is_even_odd() {
+        // A pentagram: the points are inside, the pentagon in the middle is crossed twice
+        let star: Vec<Pos2> = (0..5)
+            .map(|i| {
+                let angle = std::f32::consts::TAU * (i * 2 % 5) as f32 / 5.0;
+                Pos2::new(angle.sin() * 10.0, -angle.cos() * 10.0)
+            })
+            .collect();
+        assert!(!in_polygon(&star, Pos2::new(0.0, 0.0)));
+        assert!(in_polygon(&star, Pos2::new(0.0, -7.0)));
+        assert!(!in_polygon(&star, Pos2::new(0.0, -11.0)));
+    }
+}
@@ -0,0 +1,384 @@
+use eframe::egui_glow;
+use egui_glow::glow;
+use glow::HasContext;
+
+// Fullscreen triangle that shades the offscreen point image from its depth
+const VERTEX_SHADER: &str = r#"
+    #version 330 core
+    out vec2 v_uv;
+
+    void main() {
+        // Three vertices covering the viewport, no buffer needed
+        vec2 corner = vec2(float((gl_VertexID << 1) & 2), float(gl_VertexID & 2));
+        v_uv = corner;
+        gl_Position = vec4(corner * 2.0 - 1.0, 0.0, 1.0);
+    }
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+    #version 330 core
+    in vec2 v_uv;
+    out vec4 FragColor;
+
+    uniform sampler2D u_color;  // Premultiplied by alpha
+    uniform sampler2D u_depth;
+    uniform vec2 u_near_far;
+    uniform bool u_orthographic;
+    uniform float u_depth_offset;  // How far behind the camera an orthographic view is drawn from
+    uniform vec2 u_pixel;       // Size of one pixel in uv units
+
+    uniform float u_edl_strength;   // 0 turns eye-dome lighting off
+    uniform float u_edl_radius;     // In pixels
+    uniform float u_ssao_strength;  // 0 turns ambient occlusion off
+    uniform float u_ssao_radius;    // In pixels
+
+    float linear_depth(vec2 uv) {
+        float d = texture(u_depth, uv).r * 2.0 - 1.0;
+        float near = u_near_far.x;
+        float far = u_near_far.y;
+        if (u_orthographic) {
+            // Depth is already linear; measure it from the camera so the log in EDL stays sensitive
+            return max(mix(near, far, d * 0.5 + 0.5) - u_depth_offset, 1e-3);
+        }
+        return 2.0 * near * far / (far + near - d * (far - near));
+    }
+
+    void main() {
+        vec4 color = texture(u_color, v_uv);
+        if (color.a == 0.0) discard;
+
+        float depth = linear_depth(v_uv);
+        float shade = 1.0;
+
+        if (u_edl_strength > 0.0) {
+            // Darken where the neighbours are closer to the eye, in log depth so it works at any distance
+            const vec2 neighbours[8] = vec2[](
+                vec2(1, 0), vec2(-1, 0), vec2(0, 1), vec2(0, -1),
+                vec2(0.707, 0.707), vec2(-0.707, 0.707), vec2(0.707, -0.707), vec2(-0.707, -0.707)
+            );
+            float response = 0.0;
+            for (int i = 0; i < 8; i++) {
+                float neighbour = linear_depth(v_uv + neighbours[i] * u_edl_radius * u_pixel);
+                response += max(0.0, log2(depth) - log2(neighbour));
+            }
+            shade *= exp(-response / 8.0 * 300.0 * u_edl_strength);
+        }
+
+        if (u_ssao_strength > 0.0) {
+            // Neighbours in a spiral that sit in front of this pixel, within reach, occlude it
+            const int SAMPLES = 16;
+            float occlusion = 0.0;
+            for (int i = 0; i < SAMPLES; i++) {
+                float angle = float(i) * 2.39996;
+                float radius = u_ssao_radius * sqrt((float(i) + 0.5) / float(SAMPLES));
+                float neighbour = linear_depth(v_uv + vec2(cos(angle), sin(angle)) * radius * u_pixel);
+                float closer = (depth - neighbour) / depth;
+                occlusion += clamp(closer * 20.0, 0.0, 1.0) * (1.0 - smoothstep(0.05, 0.2, closer));
+            }
+            shade *= 1.0 - u_ssao_strength * occlusion / float(SAMPLES);
+        }
+
+        FragColor = vec4(color.rgb * shade, color.a);
+    }
+"#;
+
+/// Screen-space shading applied to the points after they are drawn.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ShadingSettings {
+    pub edl: bool,
+    pub edl_strength: f32,
+    /// How far away, in pixels, depth is compared
+    pub edl_radius: f32,
+    pub ssao: bool,
+    pub ssao_strength: f32,
+    pub ssao_radius: f32,
+}
+
+impl Default for ShadingSettings {
+    fn default() -> Self {
+        Self {
+            edl: false,
+            edl_strength: 1.0,
+            edl_radius: 1.4,
+            ssao: false,
+            ssao_strength: 0.8,
+            ssao_radius: 12.0,
+        }
+    }
+}
+
+impl ShadingSettings {
+    pub fn enabled(&self) -> bool {
+        self.edl || self.ssao
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.edl, "Eye-dome lighting");
+        ui.add_enabled_ui(self.edl, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Strength");
+                ui.add(egui::Slider::new(&mut self.edl_strength, 0.05..=5.0).logarithmic(true));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Radius");
+                ui.add(egui::Slider::new(&mut self.edl_radius, 0.5..=4.0).suffix(" px"));
+            });
+        });
+        ui.checkbox(&mut self.ssao, "Ambient occlusion");
+        ui.add_enabled_ui(self.ssao, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Strength");
+                ui.add(egui::Slider::new(&mut self.ssao_strength, 0.0..=1.0));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Radius");
+                ui.add(egui::Slider::new(&mut self.ssao_radius, 2.0..=48.0).suffix(" px"));
+            });
+        });
+    }
+}
+
+/// How point normals light the points, from a light at the eye.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LightingModel {
+    #[default]
+    Off,
+    /// Diffuse only
+    Lambert,
+    /// Diffuse and specular highlights
+    Phong,
+}
+
+impl LightingModel {
+    pub const ALL: [LightingModel; 3] = [LightingModel::Off, LightingModel::Lambert, LightingModel::Phong];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LightingModel::Off => "Off",
+            LightingModel::Lambert => "Lambert",
+            LightingModel::Phong => "Phong",
+        }
+    }
+}
+
+/// What point normals are used for. Points without normals are drawn as if all of it were off.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct LightingSettings {
+    pub model: LightingModel,
+    /// Light every point gets, facing the eye or not
+    pub ambient: f32,
+    pub specular: f32,
+    pub shininess: f32,
+    /// Draws points as discs lying in their tangent plane instead of facing the screen
+    pub splats: bool,
+    /// Draws each normal as a short line
+    pub glyphs: bool,
+    /// Glyph length in scene units
+    pub glyph_length: f32,
+}
+
+impl Default for LightingSettings {
+    fn default() -> Self {
+        Self {
+            model: LightingModel::Off,
+            ambient: 0.25,
+            specular: 0.4,
+            shininess: 24.0,
+            splats: false,
+            glyphs: false,
+            glyph_length: 0.1,
+        }
+    }
+}
+
+impl LightingSettings {
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Lighting");
+            for model in LightingModel::ALL {
+                ui.radio_value(&mut self.model, model, model.label());
+            }
+        });
+        ui.add_enabled_ui(self.model != LightingModel::Off, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Ambient");
+                ui.add(egui::Slider::new(&mut self.ambient, 0.0..=1.0));
+            });
+        });
+        ui.add_enabled_ui(self.model == LightingModel::Phong, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Specular");
+                ui.add(egui::Slider::new(&mut self.specular, 0.0..=1.0));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Shininess");
+                ui.add(egui::Slider::new(&mut self.shininess, 1.0..=128.0).logarithmic(true));
+            });
+        });
+        ui.checkbox(&mut self.splats, "Oriented splats");
+        ui.checkbox(&mut self.glyphs, "Normal glyphs");
+        ui.add_enabled_ui(self.glyphs, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Length");
+                ui.add(egui::Slider::new(&mut self.glyph_length, 0.001..=100.0).logarithmic(true));
+            });
+        });
+    }
+}
+
+/// What `begin` changed and `finish` puts back, so egui's own drawing carries on unaffected.
+pub struct Target {
+    framebuffer: Option<glow::Framebuffer>,
+    viewport: [i32; 4],
+    scissor: bool,
+    clear_color: [f32; 4],
+}
+
+/// An offscreen colour and depth target the points are drawn into, and the pass that
+/// shades them onto the screen.
+pub struct ScreenPass {
+    program: glow::Program,
+    vao: glow::VertexArray,
+    framebuffer: glow::Framebuffer,
+    color: glow::Texture,
+    depth: glow::Texture,
+    size: (i32, i32),
+}
+
+impl ScreenPass {
+    pub fn new(gl: &glow::Context) -> Result<Self, String> {
+        unsafe {
+            let program = gl.create_program()?;
+            for (kind, source) in [(glow::VERTEX_SHADER, VERTEX_SHADER), (glow::FRAGMENT_SHADER, FRAGMENT_SHADER)] {
+                let shader = gl.create_shader(kind)?;
+                gl.shader_source(shader, source);
+                gl.compile_shader(shader);
+                if !gl.get_shader_compile_status(shader) {
+                    return Err(format!("Failed to compile shading pass: {}", gl.get_shader_info_log(shader)));
+                }
+                gl.attach_shader(program, shader);
+                gl.delete_shader(shader);
+            }
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                return Err(format!("Failed to link shading pass: {}", gl.get_program_info_log(program)));
+            }
+
+            let color = gl.create_texture()?;
+            let depth = gl.create_texture()?;
+            for texture in [color, depth] {
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            Ok(Self {
+                program,
+                vao: gl.create_vertex_array()?,
+                framebuffer: gl.create_framebuffer()?,
+                color,
+                depth,
+                size: (0, 0),
+            })
+        }
+    }
+
+    /// Redirects drawing into the offscreen target, sized to the current viewport, with a
+    /// transparent clear colour so empty pixels stay see-through.
+    pub fn begin(&mut self, gl: &glow::Context) -> Target {
+        unsafe {
+            let mut viewport = [0; 4];
+            gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+            let mut clear_color = [0.0; 4];
+            gl.get_parameter_f32_slice(glow::COLOR_CLEAR_VALUE, &mut clear_color);
+            let target = Target {
+                framebuffer: gl.get_parameter_framebuffer(glow::DRAW_FRAMEBUFFER_BINDING),
+                viewport,
+                scissor: gl.is_enabled(glow::SCISSOR_TEST),
+                clear_color,
+            };
+
+            let size = (viewport[2].max(1), viewport[3].max(1));
+            if size != self.size {
+                self.size = size;
+                gl.bind_texture(glow::TEXTURE_2D, Some(self.color));
+                gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::RGBA8 as i32, size.0, size.1, 0,
+                    glow::RGBA, glow::UNSIGNED_BYTE, None);
+                gl.bind_texture(glow::TEXTURE_2D, Some(self.depth));
+                gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::DEPTH_COMPONENT24 as i32, size.0, size.1, 0,
+                    glow::DEPTH_COMPONENT, glow::UNSIGNED_INT, None);
+                gl.bind_texture(glow::TEXTURE_2D, None);
+
+                gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+                gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, Some(self.color), 0);
+                gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::DEPTH_ATTACHMENT, glow::TEXTURE_2D, Some(self.depth), 0);
+            }
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            gl.viewport(0, 0, size.0, size.1);
+            gl.disable(glow::SCISSOR_TEST);
+            gl.clear_color(0.0, 0.0, 0.0, 0.0);
+            target
+        }
+    }
+
+    /// Switches back to the target `begin` replaced and draws the shaded points onto it.
+    /// `orthographic` holds the view's depth offset when it has no perspective.
+    pub fn finish(&self, gl: &glow::Context, target: Target, settings: &ShadingSettings, near_far: (f32, f32), orthographic: Option<f32>) {
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, target.framebuffer);
+            let [x, y, width, height] = target.viewport;
+            gl.viewport(x, y, width, height);
+            if target.scissor {
+                gl.enable(glow::SCISSOR_TEST);
+            }
+            let [r, g, b, a] = target.clear_color;
+            gl.clear_color(r, g, b, a);
+            // As drawing straight to the target would have
+            gl.clear(glow::COLOR_BUFFER_BIT);
+
+            gl.use_program(Some(self.program));
+            let uniform = |name: &str| gl.get_uniform_location(self.program, name);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.color));
+            gl.uniform_1_i32(uniform("u_color").as_ref(), 0);
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.depth));
+            gl.uniform_1_i32(uniform("u_depth").as_ref(), 1);
+            gl.uniform_2_f32(uniform("u_near_far").as_ref(), near_far.0, near_far.1);
+            gl.uniform_1_i32(uniform("u_orthographic").as_ref(), orthographic.is_some() as i32);
+            gl.uniform_1_f32(uniform("u_depth_offset").as_ref(), orthographic.unwrap_or(0.0));
+            gl.uniform_2_f32(uniform("u_pixel").as_ref(), 1.0 / self.size.0 as f32, 1.0 / self.size.1 as f32);
+            let strength = |enabled: bool, strength: f32| if enabled { strength } else { 0.0 };
+            gl.uniform_1_f32(uniform("u_edl_strength").as_ref(), strength(settings.edl, settings.edl_strength));
+            gl.uniform_1_f32(uniform("u_edl_radius").as_ref(), settings.edl_radius);
+            gl.uniform_1_f32(uniform("u_ssao_strength").as_ref(), strength(settings.ssao, settings.ssao_strength));
+            gl.uniform_1_f32(uniform("u_ssao_radius").as_ref(), settings.ssao_radius);
+
+            gl.disable(glow::DEPTH_TEST);
+            gl.enable(glow::BLEND);
+            gl.blend_func(glow::ONE, glow::ONE_MINUS_SRC_ALPHA);
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+    }
+
+    pub fn destroy(&self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_program(self.program);
+            gl.delete_vertex_array(self.vao);
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_texture(self.color);
+            gl.delete_texture(self.depth);
+        }
+    }
+}
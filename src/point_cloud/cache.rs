@@ -0,0 +1,643 @@
+use glam::Vec3;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use crate::point_cloud::octree::{Octree, OctreeNode, MAX_DEPTH};
+use crate::point_cloud::{self, las, pack_normal, Attribute, LoadOptions, LoadProgress, PointCloud, NORMAL_NAMES};
+
+const HIERARCHY_FILE: &str = "hierarchy.json";
+const POINTS_FILE: &str = "points.bin";
+const CACHE_VERSION: u32 = 2;
+/// x, y, z as little endian f32 followed by r, g, b, a bytes; each attribute adds a
+/// little endian f32 after that.
+const BASE_RECORD_SIZE: usize = 16;
+/// Values of each attribute kept in the hierarchy, for fitting colour ramps without the points.
+const ATTRIBUTE_SAMPLES: usize = 20_000;
+/// Points read from the source at a time.
+const CHUNK_POINTS: usize = 1 << 20;
+/// Nodes with more points than this are split on disk rather than built in memory.
+const IN_MEMORY_POINTS: usize = 4_000_000;
+/// Subdirectory of the cache holding the temporary files of a conversion.
+const SPILL_DIR: &str = "convert.tmp";
+/// Node reads the worker may have queued at once, so a moving camera does not
+/// leave a long tail of requests for nodes that are no longer visible.
+const MAX_IN_FLIGHT: usize = 16;
+
+/// A per-point attribute stored in the cache records.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct CacheAttribute {
+    pub name: String,
+    pub offset: f64,
+    /// Values spread evenly over the points, without the offset
+    pub samples: Vec<f32>,
+}
+
+/// Everything about a cached dataset except the points themselves.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct CacheHeader {
+    pub version: u32,
+    pub origin: [f64; 3],
+    pub points: usize,
+    /// `first` and `count` index records in the points file
+    pub nodes: Vec<OctreeNode>,
+    pub attributes: Vec<CacheAttribute>,
+}
+
+impl CacheHeader {
+    pub fn octree(&self) -> Octree {
+        Octree { nodes: self.nodes.clone(), order: Vec::new() }
+    }
+
+    pub fn record_size(&self) -> usize {
+        BASE_RECORD_SIZE + 4 * self.attributes.len()
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<usize> {
+        self.attributes.iter().position(|a| a.name == name)
+    }
+
+    /// Indices of the normal attributes, as `PointCloud::normals` finds them.
+    pub fn normals(&self) -> Option<[usize; 3]> {
+        NORMAL_NAMES.iter().find_map(|names| {
+            let [x, y, z] = names.map(|name| self.attribute(name));
+            Some([x?, y?, z?])
+        })
+    }
+}
+
+/// What goes into the colour word of the vertices read from a cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CacheColoring {
+    /// The stored RGBA
+    #[default]
+    Rgb,
+    /// ASPRS class colours from this attribute
+    Classification(usize),
+    /// This attribute's value, for the shader's colour ramp
+    Scalar(usize),
+}
+
+/// True if `path` is a directory written by [`write_cache`] or [`convert_file`].
+pub fn is_cache(path: &str) -> bool {
+    Path::new(path).join(HIERARCHY_FILE).is_file()
+}
+
+/// Writes `cloud` into directory `path` as an on-disk octree, the same way [`convert_file`] does.
+pub fn write_cache(path: &str, cloud: &PointCloud, progress: &LoadProgress) -> Result<(), String> {
+    convert(path, progress, IN_MEMORY_POINTS, |each| {
+        for start in (0..cloud.len()).step_by(CHUNK_POINTS) {
+            progress.check()?;
+            progress.report(start as u64, cloud.len() as u64);
+            let range = start..cloud.len().min(start + CHUNK_POINTS);
+            each(PointCloud {
+                origin: cloud.origin,
+                positions: cloud.positions[range.clone()].to_vec(),
+                colors: cloud.colors[range.clone()].to_vec(),
+                attributes: cloud.attributes.iter().map(|a| Attribute {
+                    name: a.name.clone(),
+                    values: a.values[range.clone()].to_vec(),
+                    offset: a.offset,
+                }).collect(),
+            })?;
+        }
+        Ok(())
+    })
+}
+
+/// Converts the point file at `source` into a cache in directory `path` without ever
+/// holding all of it in memory.
+pub fn convert_file(source: &str, options: &LoadOptions, path: &str, progress: &LoadProgress) -> Result<(), String> {
+    convert(path, progress, IN_MEMORY_POINTS, |each| point_cloud::load_chunks(source, options, progress, CHUNK_POINTS, each))
+}
+
+/// Writes the points `source` hands over in chunks into directory `path`: the hierarchy
+/// as JSON and the points, node by node, as fixed-size records with every attribute.
+///
+/// The points first go to a temporary file around one origin. Nodes too large to build in
+/// memory are then split on disk: their records are streamed, the first to land in each
+/// grid cell is kept, as `Octree::build` does, and the rest are binned into a temporary
+/// file per child. Nodes of up to `in_memory` points are read back and built with
+/// `Octree::build_below`.
+fn convert(
+    path: &str,
+    progress: &LoadProgress,
+    in_memory: usize,
+    source: impl FnOnce(&mut dyn FnMut(PointCloud) -> Result<(), String>) -> Result<(), String>,
+) -> Result<(), String> {
+    let dir = Path::new(path);
+    let spill_dir = dir.join(SPILL_DIR);
+    std::fs::create_dir_all(&spill_dir).map_err(|e| format!("Failed to create cache directory: {}", e))?;
+    // An older cache here stops being one until the new hierarchy is written, so an
+    // interrupted conversion cannot leave it pointing into the new points file
+    let _ = std::fs::remove_file(dir.join(HIERARCHY_FILE));
+    let converted = build_cache(dir, &spill_dir, progress, in_memory, source);
+    // Left over if the conversion failed or was cancelled
+    let _ = std::fs::remove_dir_all(&spill_dir);
+    converted
+}
+
+fn build_cache(
+    dir: &Path,
+    spill_dir: &Path,
+    progress: &LoadProgress,
+    in_memory: usize,
+    source: impl FnOnce(&mut dyn FnMut(PointCloud) -> Result<(), String>) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut spills = 0;
+    let mut all = Spill::create(spill_dir, &mut spills)?;
+    let mut origin = None;
+    let mut attributes: Vec<CacheAttribute> = Vec::new();
+    let (mut lo, mut hi) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN));
+    // Every `stride`th point is sampled; the stride doubles whenever the samples fill up
+    let mut stride = 1;
+    let mut record = Vec::new();
+
+    source(&mut |chunk: PointCloud| {
+        let origin = *origin.get_or_insert_with(|| {
+            attributes = chunk.attributes.iter()
+                .map(|a| CacheAttribute { name: a.name.clone(), offset: a.offset, samples: Vec::new() })
+                .collect();
+            record = vec![0u8; BASE_RECORD_SIZE + 4 * attributes.len()];
+            chunk.origin
+        });
+        if !chunk.attribute_names().eq(attributes.iter().map(|a| a.name.as_str())) {
+            return Err("Point attributes change partway through the source".to_string());
+        }
+        for i in 0..chunk.len() {
+            // Chunks have origins of their own
+            let position: [f32; 3] = std::array::from_fn(|axis| {
+                (chunk.positions[i][axis] as f64 + chunk.origin[axis] - origin[axis]) as f32
+            });
+            lo = lo.min(Vec3::from(position));
+            hi = hi.max(Vec3::from(position));
+
+            let sampled = all.count % stride == 0;
+            let values = chunk.attributes.iter().zip(&mut attributes).map(|(a, cached)| {
+                let value = (a.values[i] as f64 + a.offset - cached.offset) as f32;
+                if sampled {
+                    cached.samples.push(value);
+                }
+                value
+            });
+            encode_record(&mut record, position, chunk.colors[i].to_array(), values);
+            all.write(&record)?;
+
+            if attributes.first().is_some_and(|a| a.samples.len() >= 2 * ATTRIBUTE_SAMPLES) {
+                for attribute in &mut attributes {
+                    attribute.samples = attribute.samples.iter().step_by(2).copied().collect();
+                }
+                stride *= 2;
+            }
+        }
+        Ok(())
+    })?;
+
+    let record_size = BASE_RECORD_SIZE + 4 * attributes.len();
+    let total = all.count;
+    let io_err = |e: std::io::Error| format!("Failed to write cache: {}", e);
+    let file = File::create(dir.join(POINTS_FILE)).map_err(|e| format!("Failed to create file: {}", e))?;
+    let mut points = BufWriter::new(file);
+    let mut written = 0;
+    let mut nodes = Vec::new();
+    let mut stack = Vec::new();
+    if total > 0 {
+        nodes.push(OctreeNode::bounding(lo, hi));
+        stack.push((0, all.finish()?));
+    }
+
+    while let Some((node, (spill, count))) = stack.pop() {
+        progress.check()?;
+        progress.report(written as u64, total as u64);
+        let parent = nodes[node as usize].clone();
+        let read_err = |e: std::io::Error| format!("Failed to read temporary points: {}", e);
+
+        if parent.depth >= MAX_DEPTH {
+            // A leaf however many points it has, so they are copied as they are
+            let mut reader = File::open(&spill).map_err(read_err)?;
+            std::io::copy(&mut reader, &mut points).map_err(io_err)?;
+            let n = &mut nodes[node as usize];
+            n.first = written;
+            n.count = count;
+            written += count;
+        } else if count <= in_memory {
+            let bytes = std::fs::read(&spill).map_err(read_err)?;
+            let positions: Vec<[f32; 3]> = bytes.chunks_exact(record_size).map(record_position).collect();
+            let subtree = Octree::build_below(parent, &positions);
+            for &i in &subtree.order {
+                let i = i as usize;
+                points.write_all(&bytes[i * record_size..(i + 1) * record_size]).map_err(io_err)?;
+            }
+
+            // The subtree's root is this node, the rest go after the nodes so far
+            let base = nodes.len() as u32 - 1;
+            let renumber = |k: u32| if k == 0 { node } else { base + k };
+            for (k, mut n) in subtree.nodes.into_iter().enumerate() {
+                n.first += written;
+                n.children = n.children.into_iter().map(renumber).collect();
+                if k == 0 {
+                    nodes[node as usize] = n;
+                } else {
+                    nodes.push(n);
+                }
+            }
+            written += subtree.order.len();
+        } else {
+            let mut reader = BufReader::new(File::open(&spill).map_err(read_err)?);
+            let mut record = vec![0u8; record_size];
+            let mut taken = HashSet::new();
+            let mut octants: [Option<Spill>; 8] = Default::default();
+            let first = written;
+            for _ in 0..count {
+                reader.read_exact(&mut record).map_err(read_err)?;
+                let position = record_position(&record);
+                if taken.insert(parent.cell(position)) {
+                    points.write_all(&record).map_err(io_err)?;
+                    written += 1;
+                } else {
+                    let octant = &mut octants[parent.octant(position)];
+                    let child = match octant {
+                        Some(child) => child,
+                        None => octant.insert(Spill::create(spill_dir, &mut spills)?),
+                    };
+                    child.write(&record)?;
+                }
+            }
+
+            nodes[node as usize].first = first;
+            nodes[node as usize].count = written - first;
+            for (octant, child) in octants.into_iter().enumerate() {
+                let Some(child) = child else { continue };
+                let id = nodes.len() as u32;
+                nodes.push(parent.child(octant));
+                nodes[node as usize].children.push(id);
+                stack.push((id, child.finish()?));
+            }
+        }
+        let _ = std::fs::remove_file(&spill);
+    }
+    points.flush().map_err(io_err)?;
+
+    let header = CacheHeader {
+        version: CACHE_VERSION,
+        origin: origin.unwrap_or_default(),
+        points: written,
+        nodes,
+        attributes,
+    };
+    let json = serde_json::to_string(&header).map_err(|e| format!("Failed to encode hierarchy: {}", e))?;
+    std::fs::write(dir.join(HIERARCHY_FILE), json).map_err(|e| format!("Failed to write hierarchy: {}", e))
+}
+
+/// Records waiting in a temporary file for the node they fall in to be built.
+struct Spill {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    count: usize,
+}
+
+impl Spill {
+    fn create(dir: &Path, spills: &mut usize) -> Result<Self, String> {
+        let path = dir.join(format!("{}.bin", spills));
+        *spills += 1;
+        let file = File::create(&path).map_err(|e| format!("Failed to create temporary file: {}", e))?;
+        Ok(Self { path, writer: BufWriter::new(file), count: 0 })
+    }
+
+    fn write(&mut self, record: &[u8]) -> Result<(), String> {
+        self.count += 1;
+        self.writer.write_all(record).map_err(|e| format!("Failed to write temporary points: {}", e))
+    }
+
+    /// The file and how many records it holds.
+    fn finish(mut self) -> Result<(PathBuf, usize), String> {
+        self.writer.flush().map_err(|e| format!("Failed to write temporary points: {}", e))?;
+        Ok((self.path, self.count))
+    }
+}
+
+fn encode_record(record: &mut [u8], position: [f32; 3], rgba: [u8; 4], values: impl Iterator<Item = f32>) {
+    for (axis, v) in position.iter().enumerate() {
+        record[axis * 4..axis * 4 + 4].copy_from_slice(&v.to_le_bytes());
+    }
+    record[12..16].copy_from_slice(&rgba);
+    for (a, value) in values.enumerate() {
+        let at = BASE_RECORD_SIZE + a * 4;
+        record[at..at + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn record_position(record: &[u8]) -> [f32; 3] {
+    std::array::from_fn(|axis| f32::from_le_bytes(record[axis * 4..axis * 4 + 4].try_into().unwrap()))
+}
+
+/// Decodes records into the renderer's vertex words: three f32 bit patterns, the colour
+/// word `coloring` asks for, flags, which are always clear for cached points, and normal.
+fn records_to_words(bytes: &[u8], header: &CacheHeader, coloring: CacheColoring) -> Vec<u32> {
+    let record_size = header.record_size();
+    let normals = header.normals();
+    let attribute = |record: &[u8], a: usize| {
+        let at = BASE_RECORD_SIZE + a * 4;
+        f32::from_le_bytes(record[at..at + 4].try_into().unwrap())
+    };
+    let mut words = Vec::with_capacity(bytes.len() / record_size * 6);
+    for record in bytes.chunks_exact(record_size) {
+        for axis in 0..3 {
+            words.push(u32::from_le_bytes(record[axis * 4..axis * 4 + 4].try_into().unwrap()));
+        }
+        words.push(match coloring {
+            CacheColoring::Rgb => u32::from_ne_bytes(record[12..16].try_into().unwrap()),
+            CacheColoring::Classification(a) => {
+                u32::from_ne_bytes(las::classification_color(attribute(record, a) as u8).to_array())
+            }
+            CacheColoring::Scalar(a) => attribute(record, a).to_bits(),
+        });
+        words.push(0);
+        words.push(pack_normal(normals.map(|axes| axes.map(|a| attribute(record, a)))));
+    }
+    words
+}
+
+struct Resident {
+    words: Arc<Vec<u32>>,
+    last_used: u64,
+}
+
+/// Pages the nodes of a cache in from disk on a worker thread, keeping at most
+/// `memory_budget` bytes of them in memory.
+pub struct CacheReader {
+    pub header: CacheHeader,
+    requests: mpsc::Sender<(u32, CacheColoring)>,
+    loaded: mpsc::Receiver<(u32, CacheColoring, Result<Vec<u32>, String>)>,
+    /// Colouring of the resident nodes and of the reads asked for
+    coloring: CacheColoring,
+    resident: HashMap<u32, Resident>,
+    in_flight: HashSet<u32>,
+    frame: u64,
+    pub memory_budget: usize,
+    /// First read error, reported once
+    pub error: Option<String>,
+}
+
+impl CacheReader {
+    pub fn open(path: &str, memory_budget: usize) -> Result<Self, String> {
+        let dir = Path::new(path);
+        let json = std::fs::read_to_string(dir.join(HIERARCHY_FILE))
+            .map_err(|e| format!("Failed to read hierarchy: {}", e))?;
+        let header: CacheHeader = serde_json::from_str(&json).map_err(|e| format!("Invalid cache hierarchy: {}", e))?;
+        if header.version != CACHE_VERSION {
+            return Err(format!("Unsupported cache version {}", header.version));
+        }
+        let mut file = File::open(dir.join(POINTS_FILE)).map_err(|e| format!("Failed to open file: {}", e))?;
+        let len = file.metadata().map_err(|e| format!("Failed to read file: {}", e))?.len();
+        let record_size = header.record_size();
+        if len < (header.points * record_size) as u64 {
+            return Err("Cache points file is shorter than the hierarchy declares".to_string());
+        }
+
+        let (requests, worker_requests) = mpsc::channel::<(u32, CacheColoring)>();
+        let (worker_loaded, loaded) = mpsc::channel();
+        let worker_header = header.clone();
+        std::thread::spawn(move || {
+            // Ends when the reader, and with it the request sender, is dropped
+            for (node, coloring) in worker_requests {
+                let n = &worker_header.nodes[node as usize];
+                let mut bytes = vec![0u8; n.count * record_size];
+                let read = file.seek(SeekFrom::Start((n.first * record_size) as u64))
+                    .and_then(|_| file.read_exact(&mut bytes))
+                    .map(|_| records_to_words(&bytes, &worker_header, coloring))
+                    .map_err(|e| format!("Failed to read cache node {}: {}", node, e));
+                if worker_loaded.send((node, coloring, read)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            header,
+            requests,
+            loaded,
+            coloring: CacheColoring::Rgb,
+            resident: HashMap::new(),
+            in_flight: HashSet::new(),
+            frame: 0,
+            memory_budget,
+            error: None,
+        })
+    }
+
+    /// Takes in finished reads and drops the least recently used nodes over budget.
+    /// Call once per frame before `get`.
+    pub fn update(&mut self) {
+        self.frame += 1;
+        while let Ok((node, coloring, read)) = self.loaded.try_recv() {
+            if coloring != self.coloring {
+                // Asked for before the colouring changed
+                continue;
+            }
+            self.in_flight.remove(&node);
+            match read {
+                Ok(words) => {
+                    self.resident.insert(node, Resident { words: Arc::new(words), last_used: self.frame });
+                }
+                Err(e) => {
+                    self.error.get_or_insert(e);
+                }
+            }
+        }
+
+        let bytes = |r: &Resident| r.words.len() * 4;
+        let mut used: usize = self.resident.values().map(bytes).sum();
+        if used <= self.memory_budget {
+            return;
+        }
+        let mut stale: Vec<(u64, u32)> = self.resident.iter()
+            .filter(|(_, r)| r.last_used + 1 < self.frame)
+            .map(|(&id, r)| (r.last_used, id))
+            .collect();
+        stale.sort_unstable();
+        for (_, id) in stale {
+            if used <= self.memory_budget {
+                break;
+            }
+            used -= bytes(&self.resident.remove(&id).unwrap());
+        }
+    }
+
+    /// The node's vertex words if they are in memory; otherwise asks the worker for them.
+    pub fn get(&mut self, node: u32) -> Option<Arc<Vec<u32>>> {
+        if let Some(resident) = self.resident.get_mut(&node) {
+            resident.last_used = self.frame;
+            return Some(resident.words.clone());
+        }
+        if self.error.is_none() && self.in_flight.len() < MAX_IN_FLIGHT && self.in_flight.insert(node) {
+            let _ = self.requests.send((node, self.coloring));
+        }
+        None
+    }
+
+    /// Changes what the colour word of the vertices holds, dropping every node read with
+    /// the old colouring. Returns whether it changed.
+    pub fn set_coloring(&mut self, coloring: CacheColoring) -> bool {
+        if coloring == self.coloring {
+            return false;
+        }
+        self.coloring = coloring;
+        self.resident.clear();
+        self.in_flight.clear();
+        true
+    }
+
+    /// The node's vertex words if they are in memory, without asking for them otherwise.
+    pub fn peek(&self, node: u32) -> Option<Arc<Vec<u32>>> {
+        self.resident.get(&node).map(|resident| resident.words.clone())
+    }
+
+    pub fn resident_bytes(&self) -> usize {
+        self.resident.values().map(|r| r.words.len() * 4).sum()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use egui::Color32;
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    const POINTS: usize = 60_000;
+    const GPS_BASE: f64 = 1e9;
+
+    fn temp_dir(name: &str) -> String {
+        let dir = std::env::temp_dir().join(format!("rushroom-cache-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.to_str().unwrap().to_string()
+    }
+
+    fn normal(i: usize) -> [f32; 3] {
+        [(i % 3) as f32, 1.0, -((i % 5) as f32)]
+    }
+
+    fn color(i: usize) -> Color32 {
+        Color32::from_rgb(i as u8, (i >> 8) as u8, 7)
+    }
+
+    /// Points `start..end` of a thin, dense slab, so nodes keep splitting, as a chunk
+    /// with an origin and GPS time offset of its own.
+    fn chunk(start: usize, end: usize) -> PointCloud {
+        let mut cloud = PointCloud::default();
+        for i in start..end {
+            let (x, y) = ((i * 7919 % POINTS) as f64 / 600.0, (i * 104_729 % POINTS) as f64 / 600.0);
+            cloud.push([100_000.0 + x, 200_000.0 + y, (i % 10) as f64 * 0.001], color(i));
+        }
+        let offset = GPS_BASE + start as f64 * 0.5;
+        let attribute = |name: &str, values: Vec<f32>, offset: f64| Attribute { name: name.to_string(), values, offset };
+        cloud.attributes = vec![
+            attribute("id", (start..end).map(|i| i as f32).collect(), 0.0),
+            attribute("gps_time", (start..end).map(|i| ((i - start) as f64 * 0.5) as f32).collect(), offset),
+        ];
+        for (axis, name) in NORMAL_NAMES[0].iter().enumerate() {
+            cloud.attributes.push(attribute(name, (start..end).map(|i| normal(i)[axis]).collect(), 0.0));
+        }
+        cloud
+    }
+
+    /// The words of every node, read through the paging thread with `coloring`.
+    fn read_all(reader: &mut CacheReader, coloring: CacheColoring) -> Vec<Vec<u32>> {
+        reader.set_coloring(coloring);
+        let mut nodes = vec![None; reader.header.nodes.len()];
+        let started = Instant::now();
+        while nodes.iter().any(Option::is_none) {
+            assert!(started.elapsed() < Duration::from_secs(30), "cache nodes never arrived");
+            reader.update();
+            for (node, words) in nodes.iter_mut().enumerate() {
+                if words.is_none() {
+                    *words = reader.get(node as u32).map(|words| words.to_vec());
+                }
+            }
+            assert_eq!(reader.error, None);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        nodes.into_iter().map(Option::unwrap).collect()
+    }
+
+    #[test]
+    fn spilled_cache_round_trips_every_point_once() {
+        let path = temp_dir("round-trip");
+        convert(&path, &LoadProgress::default(), 5_000, |each| {
+            for start in (0..POINTS).step_by(7_000) {
+                each(chunk(start, POINTS.min(start + 7_000)))?;
+            }
+            Ok(())
+        }).unwrap();
+        assert!(is_cache(&path));
+        assert!(!Path::new(&path).join(SPILL_DIR).exists());
+
+        let mut reader = CacheReader::open(&path, usize::MAX).unwrap();
+        let header = reader.header.clone();
+        assert_eq!(header.points, POINTS);
+        assert_eq!(header.origin, chunk(0, 1).origin);
+        // Deep enough that nodes were split on disk, and then built in memory
+        assert!(header.nodes.iter().any(|n| n.depth >= 2));
+        let names: Vec<&str> = header.attributes.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["id", "gps_time", "nx", "ny", "nz"]);
+        assert_eq!(header.attributes[1].offset, GPS_BASE);
+        assert!(!header.attributes[0].samples.is_empty());
+
+        let ids = read_all(&mut reader, CacheColoring::Scalar(0));
+        let gps = read_all(&mut reader, CacheColoring::Scalar(1));
+        let rgb = read_all(&mut reader, CacheColoring::Rgb);
+        let mut seen = vec![false; POINTS];
+        for node in 0..header.nodes.len() {
+            let n = &header.nodes[node];
+            assert_eq!(ids[node].len(), n.count * 6);
+            for (k, point) in ids[node].chunks_exact(6).enumerate() {
+                let id = f32::from_bits(point[3]) as usize;
+                assert!(!std::mem::replace(&mut seen[id], true), "point {} read twice", id);
+
+                let expected = chunk(id, id + 1);
+                let position: [f32; 3] = std::array::from_fn(|axis| {
+                    (expected.position(0)[axis] - header.origin[axis]) as f32
+                });
+                for axis in 0..3 {
+                    let read = f32::from_bits(point[axis]);
+                    assert!((read - position[axis]).abs() < 1e-3, "point {} axis {}: {} != {}", id, axis, read, position[axis]);
+                    assert!(read >= n.min[axis] && read <= n.min[axis] + n.size);
+                }
+                assert_eq!(point[5], pack_normal(Some(normal(id))));
+                assert_eq!(f32::from_bits(gps[node][k * 6 + 3]) as f64 + GPS_BASE, GPS_BASE + id as f64 * 0.5);
+                assert_eq!(rgb[node][k * 6 + 3], u32::from_ne_bytes(color(id).to_array()));
+            }
+        }
+        assert!(seen.into_iter().all(|s| s));
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn a_cancelled_conversion_leaves_no_cache() {
+        let path = temp_dir("cancelled");
+        // A good cache there beforehand must not survive as one either
+        write_cache(&path, &chunk(0, 100), &LoadProgress::default()).unwrap();
+        assert!(is_cache(&path));
+
+        // Cancelled after reading the source, while building the hierarchy
+        let progress = LoadProgress::default();
+        let converted = convert(&path, &progress, 5_000, |each| {
+            each(chunk(0, 20_000))?;
+            progress.cancel();
+            Ok(())
+        });
+        assert_eq!(converted, Err("cancelled".to_string()));
+        assert!(!is_cache(&path));
+        assert!(!Path::new(&path).join(SPILL_DIR).exists());
+        assert!(CacheReader::open(&path, usize::MAX).is_err());
+
+        // And cancelled before it starts
+        let progress = LoadProgress::default();
+        progress.cancel();
+        assert!(write_cache(&path, &chunk(0, 100), &progress).is_err());
+        assert!(!is_cache(&path));
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+}
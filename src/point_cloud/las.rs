@@ -0,0 +1,398 @@
+use egui::Color32;
+use std::io::{Read, Seek, SeekFrom};
+use crate::point_cloud::{Attribute, Chunks, LoadProgress, PointCloud};
+
+/// The parts of the LAS public header block the reader needs.
+#[derive(Clone, Debug)]
+pub struct LasHeader {
+    pub version: (u8, u8),
+    pub point_offset: u32,
+    /// Point data record format, 0-10, with the LAZ compression bits removed.
+    pub point_format: u8,
+    pub record_length: u16,
+    pub points: u64,
+    pub scale: [f64; 3],
+    pub offset: [f64; 3],
+    /// LAZ files set bit 7 (and sometimes bit 6) of the point format.
+    pub compressed: bool,
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn le_u64(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn le_i32(bytes: &[u8], at: usize) -> i32 {
+    i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn le_f64(bytes: &[u8], at: usize) -> f64 {
+    f64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+pub fn parse_header<R: Read>(reader: &mut R) -> Result<LasHeader, String> {
+    // 227 bytes up to 1.2, 235 for 1.3 and 375 for 1.4
+    let mut bytes = vec![0u8; 227];
+    reader.read_exact(&mut bytes).map_err(|e| format!("Failed to read LAS header: {}", e))?;
+    if &bytes[..4] != b"LASF" {
+        return Err("Not a LAS file (missing LASF signature)".to_string());
+    }
+
+    let version = (bytes[24], bytes[25]);
+    let header_size = le_u16(&bytes, 94) as usize;
+    if header_size > bytes.len() {
+        let mut rest = vec![0u8; header_size - bytes.len()];
+        reader.read_exact(&mut rest).map_err(|e| format!("Failed to read LAS header: {}", e))?;
+        bytes.extend(rest);
+    }
+
+    let format_byte = bytes[104];
+    let legacy_points = le_u32(&bytes, 107) as u64;
+    // LAS 1.4 keeps a 64-bit count and leaves the legacy one at zero for big files and new formats
+    let points = if version >= (1, 4) && bytes.len() >= 255 && legacy_points == 0 {
+        le_u64(&bytes, 247)
+    } else {
+        legacy_points
+    };
+
+    let triple = |at: usize| [le_f64(&bytes, at), le_f64(&bytes, at + 8), le_f64(&bytes, at + 16)];
+    Ok(LasHeader {
+        version,
+        point_offset: le_u32(&bytes, 96),
+        point_format: format_byte & 0x3f,
+        record_length: le_u16(&bytes, 105),
+        points,
+        scale: triple(131),
+        offset: triple(155),
+        compressed: format_byte & 0x80 != 0,
+    })
+}
+
+/// Byte offsets of the fields that move around between point formats.
+struct RecordLayout {
+    /// Formats 6-10 use wider return and classification fields.
+    extended: bool,
+    gps_time: Option<usize>,
+    rgb: Option<usize>,
+    min_length: usize,
+}
+
+impl RecordLayout {
+    fn new(format: u8) -> Result<Self, String> {
+        let (extended, gps_time, rgb, min_length) = match format {
+            0 => (false, None, None, 20),
+            1 => (false, Some(20), None, 28),
+            2 => (false, None, Some(20), 26),
+            3 => (false, Some(20), Some(28), 34),
+            4 => (false, Some(20), None, 57),
+            5 => (false, Some(20), Some(28), 63),
+            6 => (true, Some(22), None, 30),
+            7 => (true, Some(22), Some(30), 36),
+            8 => (true, Some(22), Some(30), 38),
+            9 => (true, Some(22), None, 59),
+            10 => (true, Some(22), Some(30), 67),
+            other => return Err(format!("Unsupported LAS point format {}", other)),
+        };
+        Ok(Self { extended, gps_time, rgb, min_length })
+    }
+}
+
+/// Reads LAS 1.0-1.4 point formats 0-10. Positions get the header scale and offset applied;
+/// intensity, returns, classification and GPS time are kept as attributes.
+///
+/// LAZ is not supported: compressed files are recognised by the compression bits of the
+/// point format and fail with an error saying so.
+pub(crate) fn read_las(path: &str, progress: &LoadProgress, chunks: &mut Chunks<'_>) -> Result<PointCloud, String> {
+    let mut reader = progress.open(path)?;
+
+    let header = parse_header(&mut reader)?;
+    if header.compressed {
+        return Err("LAZ compressed point data is not supported; decompress it to LAS first (e.g. with laszip)".to_string());
+    }
+    let layout = RecordLayout::new(header.point_format)?;
+    let record_length = header.record_length as usize;
+    if record_length < layout.min_length {
+        return Err(format!(
+            "LAS {}.{} point format {} needs {} byte records, header declares {}",
+            header.version.0, header.version.1, header.point_format, layout.min_length, record_length,
+        ));
+    }
+
+    reader.seek(SeekFrom::Start(header.point_offset as u64))
+        .map_err(|e| format!("Failed to seek to LAS point data: {}", e))?;
+
+    let count = header.points as usize;
+    let capacity = chunks.capacity(progress.capacity(count, record_length));
+    let mut cloud = PointCloud::with_capacity(capacity);
+    cloud.attributes = ["intensity", "return_number", "number_of_returns", "classification"].into_iter()
+        .chain(layout.gps_time.map(|_| "gps_time"))
+        .map(|name| Attribute::new(name, capacity))
+        .collect();
+    let mut rgb: Vec<[u16; 3]> = Vec::with_capacity(if layout.rgb.is_some() { capacity } else { 0 });
+    let mut shift = None;
+
+    let mut record = vec![0u8; record_length];
+    for i in 0..count {
+        reader.read_exact(&mut record)
+            .map_err(|e| format!("Failed to read point {} of {}: {}", i, count, e))?;
+
+        let position: [f64; 3] = std::array::from_fn(|axis| {
+            le_i32(&record, axis * 4) as f64 * header.scale[axis] + header.offset[axis]
+        });
+        cloud.push(position, Color32::WHITE);
+
+        let (number, of) = if layout.extended {
+            (record[14] & 0x0f, record[14] >> 4)
+        } else {
+            (record[14] & 0x07, (record[14] >> 3) & 0x07)
+        };
+        let class = if layout.extended { record[16] } else { record[15] & 0x1f };
+        let [intensity, return_number, number_of_returns, classification, rest @ ..] = &mut cloud.attributes[..] else {
+            unreachable!("LAS clouds start with four attributes");
+        };
+        intensity.values.push(le_u16(&record, 12) as f32);
+        return_number.values.push(number as f32);
+        number_of_returns.values.push(of as f32);
+        classification.values.push(class as f32);
+
+        if let (Some(at), [gps_time]) = (layout.gps_time, rest) {
+            // Stored relative to the first point, absolute GPS times are too large for f32
+            let time = le_f64(&record, at);
+            if i == 0 {
+                gps_time.offset = time;
+            }
+            gps_time.values.push((time - gps_time.offset) as f32);
+        }
+        if let Some(at) = layout.rgb {
+            rgb.push([le_u16(&record, at), le_u16(&record, at + 2), le_u16(&record, at + 4)]);
+        }
+        if chunks.full(&cloud) {
+            apply_rgb(&mut cloud, &mut rgb, &mut shift);
+            chunks.flush(&mut cloud)?;
+        }
+    }
+
+    apply_rgb(&mut cloud, &mut rgb, &mut shift);
+    Ok(cloud)
+}
+
+/// Colours the points from their RGB fields. The spec asks for 16-bit colour but plenty of
+/// writers store 0-255, so the first call settles that from the values read so far.
+fn apply_rgb(cloud: &mut PointCloud, rgb: &mut Vec<[u16; 3]>, shift: &mut Option<u32>) {
+    if rgb.is_empty() {
+        return;
+    }
+    let shift = *shift.get_or_insert_with(|| if rgb.iter().flatten().any(|&c| c > 255) { 8 } else { 0 });
+    for (color, [r, g, b]) in cloud.colors.iter_mut().zip(rgb.drain(..)) {
+        *color = Color32::from_rgb((r >> shift) as u8, (g >> shift) as u8, (b >> shift) as u8);
+    }
+}
+
+/// Colours for the standard ASPRS classes, used when colouring by classification.
+pub fn classification_color(class: u8) -> Color32 {
+    match class {
+        2 => Color32::from_rgb(166, 118, 62),   // ground
+        3 => Color32::from_rgb(150, 220, 110),  // low vegetation
+        4 => Color32::from_rgb(70, 180, 60),    // medium vegetation
+        5 => Color32::from_rgb(25, 110, 35),    // high vegetation
+        6 => Color32::from_rgb(220, 70, 50),    // building
+        7 | 18 => Color32::from_rgb(230, 0, 230), // noise
+        9 => Color32::from_rgb(40, 110, 230),   // water
+        10 => Color32::from_rgb(120, 80, 150),  // rail
+        11 => Color32::from_rgb(90, 90, 90),    // road surface
+        13 | 14 => Color32::from_rgb(250, 220, 40), // wire guard and conductor
+        15 => Color32::from_rgb(240, 150, 30),  // transmission tower
+        16 => Color32::from_rgb(200, 200, 90),  // wire connector
+        17 => Color32::from_rgb(150, 150, 200), // bridge deck
+        // never classified, unclassified, overlap and user classes
+        _ => Color32::from_gray(170),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One point of a fixture, in the file's integer coordinates.
+    struct Record {
+        xyz: [i32; 3],
+        intensity: u16,
+        return_number: u8,
+        number_of_returns: u8,
+        class: u8,
+        gps_time: f64,
+        rgb: [u16; 3],
+    }
+
+    fn record(xyz: [i32; 3], class: u8) -> Record {
+        Record { xyz, intensity: 0, return_number: 1, number_of_returns: 1, class, gps_time: 0.0, rgb: [0; 3] }
+    }
+
+    /// A LAS file holding `records` in point `format`, written the way the spec lays it out.
+    fn las_file(name: &str, version: (u8, u8), format: u8, records: &[Record]) -> String {
+        let header_size: usize = if version >= (1, 4) { 375 } else { 227 };
+        let layout = RecordLayout::new(format).unwrap();
+        // A few padding bytes, as writers with extra bytes leave
+        let record_length = layout.min_length + 3;
+
+        let mut bytes = vec![0u8; header_size];
+        bytes[..4].copy_from_slice(b"LASF");
+        (bytes[24], bytes[25]) = version;
+        bytes[94..96].copy_from_slice(&(header_size as u16).to_le_bytes());
+        bytes[96..100].copy_from_slice(&(header_size as u32).to_le_bytes());
+        bytes[104] = format;
+        bytes[105..107].copy_from_slice(&(record_length as u16).to_le_bytes());
+        if version >= (1, 4) {
+            // Only the 64-bit count, as 1.4 writers do for formats 6-10
+            bytes[247..255].copy_from_slice(&(records.len() as u64).to_le_bytes());
+        } else {
+            bytes[107..111].copy_from_slice(&(records.len() as u32).to_le_bytes());
+        }
+        for (axis, (scale, offset)) in [(0.01, 1000.0), (0.01, 2000.0), (0.001, -5.0)].into_iter().enumerate() {
+            bytes[131 + axis * 8..139 + axis * 8].copy_from_slice(&f64::to_le_bytes(scale));
+            bytes[155 + axis * 8..163 + axis * 8].copy_from_slice(&f64::to_le_bytes(offset));
+        }
+
+        for r in records {
+            let mut record = vec![0u8; record_length];
+            for axis in 0..3 {
+                record[axis * 4..axis * 4 + 4].copy_from_slice(&r.xyz[axis].to_le_bytes());
+            }
+            record[12..14].copy_from_slice(&r.intensity.to_le_bytes());
+            if layout.extended {
+                record[14] = r.return_number | r.number_of_returns << 4;
+                record[16] = r.class;
+            } else {
+                record[14] = r.return_number | r.number_of_returns << 3;
+                // Synthetic, key-point and withheld flags share the byte with the class
+                record[15] = r.class | 0xe0;
+            }
+            if let Some(at) = layout.gps_time {
+                record[at..at + 8].copy_from_slice(&r.gps_time.to_le_bytes());
+            }
+            if let Some(at) = layout.rgb {
+                for (c, channel) in r.rgb.iter().enumerate() {
+                    record[at + c * 2..at + c * 2 + 2].copy_from_slice(&channel.to_le_bytes());
+                }
+            }
+            bytes.extend(record);
+        }
+
+        let path = std::env::temp_dir().join(format!("rushroom-las-{}-{}.las", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn read(path: &str) -> Result<PointCloud, String> {
+        let read = read_las(path, &LoadProgress::default(), &mut Chunks::whole());
+        std::fs::remove_file(path).unwrap();
+        read
+    }
+
+    fn values(cloud: &PointCloud, name: &str) -> Vec<f64> {
+        let attribute = cloud.attribute(name).unwrap();
+        (0..cloud.len()).map(|i| attribute.get(i)).collect()
+    }
+
+    #[test]
+    fn format_0_applies_scale_and_offset() {
+        let mut first = record([150, -250, 7000], 2);
+        first.intensity = 1234;
+        first.return_number = 2;
+        first.number_of_returns = 3;
+        let path = las_file("format0", (1, 2), 0, &[first, record([-100_000, 0, 0], 6)]);
+        let cloud = read(&path).unwrap();
+
+        assert_eq!(cloud.len(), 2);
+        assert_eq!(cloud.position(0), [1001.5, 1997.5, 2.0]);
+        assert_eq!(cloud.position(1), [0.0, 2000.0, -5.0]);
+        assert_eq!(values(&cloud, "intensity"), [1234.0, 0.0]);
+        assert_eq!(values(&cloud, "return_number"), [2.0, 1.0]);
+        assert_eq!(values(&cloud, "number_of_returns"), [3.0, 1.0]);
+        // The flag bits above the class are dropped
+        assert_eq!(values(&cloud, "classification"), [2.0, 6.0]);
+        assert!(cloud.attribute("gps_time").is_none());
+        assert_eq!(cloud.colors, [Color32::WHITE; 2]);
+    }
+
+    #[test]
+    fn format_2_reads_8_and_16_bit_colour() {
+        let mut eight = record([0; 3], 1);
+        eight.rgb = [255, 128, 0];
+        let path = las_file("format2-8bit", (1, 2), 2, &[eight]);
+        assert_eq!(read(&path).unwrap().colors, [Color32::from_rgb(255, 128, 0)]);
+
+        let mut sixteen = record([0; 3], 1);
+        sixteen.rgb = [65535, 32768, 256];
+        let mut dark = record([0; 3], 1);
+        dark.rgb = [255, 0, 0];
+        let path = las_file("format2-16bit", (1, 2), 2, &[sixteen, dark]);
+        assert_eq!(read(&path).unwrap().colors, [Color32::from_rgb(255, 128, 1), Color32::from_rgb(0, 0, 0)]);
+    }
+
+    #[test]
+    fn format_6_reads_extended_fields_and_the_64_bit_count() {
+        let mut first = record([0, 0, 0], 40);
+        first.return_number = 9;
+        first.number_of_returns = 15;
+        first.gps_time = 400_000_000.25;
+        let mut second = record([1, 1, 1], 7);
+        second.gps_time = 400_000_010.75;
+        let path = las_file("format6", (1, 4), 6, &[first, second]);
+        let cloud = read(&path).unwrap();
+
+        assert_eq!(cloud.len(), 2);
+        assert_eq!(values(&cloud, "return_number"), [9.0, 1.0]);
+        assert_eq!(values(&cloud, "number_of_returns"), [15.0, 1.0]);
+        // Classes above 31 only fit the extended field
+        assert_eq!(values(&cloud, "classification"), [40.0, 7.0]);
+        // Kept exact through the offset despite the size of the times
+        assert_eq!(values(&cloud, "gps_time"), [400_000_000.25, 400_000_010.75]);
+    }
+
+    #[test]
+    fn format_7_reads_colour_after_gps_time() {
+        let mut point = record([100, 200, 300], 9);
+        point.gps_time = 12.5;
+        point.rgb = [0x1200, 0x3400, 0xff00];
+        let path = las_file("format7", (1, 4), 7, &[point]);
+        let cloud = read(&path).unwrap();
+
+        let [x, y, z] = cloud.position(0);
+        assert_eq!([x, y], [1001.0, 2002.0]);
+        assert!((z - -4.7).abs() < 1e-6);
+        assert_eq!(values(&cloud, "gps_time"), [12.5]);
+        assert_eq!(values(&cloud, "classification"), [9.0]);
+        assert_eq!(cloud.colors, [Color32::from_rgb(0x12, 0x34, 0xff)]);
+    }
+
+    #[test]
+    fn rejects_compressed_and_short_records() {
+        let path = las_file("laz", (1, 2), 0, &[record([0; 3], 1)]);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[104] |= 0x80;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(read(&path).unwrap_err().starts_with("LAZ compressed point data is not supported"));
+
+        let path = las_file("short", (1, 2), 1, &[record([0; 3], 1)]);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[105..107].copy_from_slice(&20u16.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert_eq!(read(&path).unwrap_err(), "LAS 1.2 point format 1 needs 28 byte records, header declares 20");
+    }
+
+    #[test]
+    fn a_truncated_file_is_an_error() {
+        let path = las_file("truncated", (1, 2), 0, &[record([0; 3], 1), record([0; 3], 1)]);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+        assert!(read(&path).unwrap_err().starts_with("Failed to read point 1 of 2"));
+    }
+}
@@ -0,0 +1,291 @@
+pub mod cache;
+pub mod las;
+pub mod obj;
+pub mod octree;
+pub mod pcd;
+pub mod ply;
+pub mod progress;
+pub mod ramp;
+pub mod xyz;
+
+use egui::Color32;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+pub use progress::LoadProgress;
+
+/// Attribute names normals are stored under: `nx` as in PLY, `normal_x` as in PCD.
+pub(crate) const NORMAL_NAMES: [[&str; 3]; 2] = [["nx", "ny", "nz"], ["normal_x", "normal_y", "normal_z"]];
+
+/// A named per-point value kept alongside position and colour, e.g. intensity or `nx`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attribute {
+    pub name: String,
+    pub values: Vec<f32>,
+    /// Added to every value; keeps large quantities like GPS time precise in `f32`.
+    pub offset: f64,
+}
+
+impl Attribute {
+    pub fn new(name: impl Into<String>, capacity: usize) -> Self {
+        Self { name: name.into(), values: Vec::with_capacity(capacity), offset: 0.0 }
+    }
+
+    /// The value of point `i` with the offset applied.
+    pub fn get(&self, i: usize) -> f64 {
+        self.values[i] as f64 + self.offset
+    }
+}
+
+/// In-memory point cloud shared by the loaders, the renderer and pipeline nodes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PointCloud {
+    /// World position the points are stored relative to, so georeferenced
+    /// coordinates in the millions keep their precision in `f32`.
+    pub origin: [f64; 3],
+    /// Positions relative to `origin`
+    pub positions: Vec<[f32; 3]>,
+    /// Straight (not premultiplied) RGBA as read from the file. `Color32` is only the
+    /// container here, so build these with `from_rgba_premultiplied`, which keeps the bytes.
+    pub colors: Vec<Color32>,
+    /// Each attribute holds exactly one value per point.
+    pub attributes: Vec<Attribute>,
+}
+
+impl PointCloud {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            origin: [0.0; 3],
+            positions: Vec::with_capacity(capacity),
+            colors: Vec::with_capacity(capacity),
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Adds a point at an absolute position. The first point of an empty cloud,
+    /// rounded to whole units, becomes the origin.
+    pub fn push(&mut self, position: [f64; 3], color: Color32) {
+        if self.positions.is_empty() {
+            self.origin = position.map(f64::round);
+        }
+        let origin = self.origin;
+        self.positions.push(std::array::from_fn(|i| (position[i] - origin[i]) as f32));
+        self.colors.push(color);
+    }
+
+    /// Absolute position of point `i`.
+    pub fn position(&self, i: usize) -> [f64; 3] {
+        let relative = self.positions[i];
+        std::array::from_fn(|axis| self.origin[axis] + relative[axis] as f64)
+    }
+
+    /// The points where `keep` is true, with their colours and attributes, around the same origin.
+    pub fn filter(&self, keep: &[bool]) -> PointCloud {
+        let pick = |i: &usize| keep[*i];
+        let count = (0..self.len()).filter(pick).count();
+        let mut cloud = PointCloud::with_capacity(count);
+        cloud.origin = self.origin;
+        for i in (0..self.len()).filter(pick) {
+            cloud.positions.push(self.positions[i]);
+            cloud.colors.push(self.colors[i]);
+        }
+        cloud.attributes = self.attributes.iter().map(|a| Attribute {
+            name: a.name.clone(),
+            values: (0..self.len()).filter(pick).map(|i| a.values[i]).collect(),
+            offset: a.offset,
+        }).collect();
+        cloud
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|a| a.name == name)
+    }
+
+    /// The x, y and z components of the point normals, if the cloud has all three.
+    pub fn normals(&self) -> Option<[&Attribute; 3]> {
+        NORMAL_NAMES.iter().find_map(|names| {
+            let [x, y, z] = names.map(|name| self.attribute(name));
+            Some([x?, y?, z?])
+        })
+    }
+
+    pub fn attribute_names(&self) -> impl Iterator<Item = &str> {
+        self.attributes.iter().map(|a| a.name.as_str())
+    }
+}
+
+/// Packs a normal into GL's signed, normalised 10-10-10-2 format, as the renderer's vertex
+/// words hold it. Zero stands for a point without a normal.
+pub fn pack_normal(normal: Option<[f32; 3]>) -> u32 {
+    let Some(normal) = normal else { return 0 };
+    let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    if length == 0.0 || !length.is_finite() {
+        return 0;
+    }
+    normal.iter().enumerate().fold(0, |word, (axis, c)| {
+        let component = (c / length * 511.0).round() as i32 as u32 & 0x3ff;
+        word | component << (axis * 10)
+    })
+}
+
+/// Where a reader puts its points: all in the cloud it returns, or handed on a chunk at a
+/// time so the file never has to fit in memory.
+pub(crate) struct Chunks<'a> {
+    size: usize,
+    each: Option<&'a mut dyn FnMut(PointCloud) -> Result<(), String>>,
+}
+
+impl<'a> Chunks<'a> {
+    /// Keeps every point in the returned cloud.
+    pub(crate) fn whole() -> Self {
+        Self { size: usize::MAX, each: None }
+    }
+
+    pub(crate) fn new(size: usize, each: &'a mut dyn FnMut(PointCloud) -> Result<(), String>) -> Self {
+        Self { size: size.max(1), each: Some(each) }
+    }
+
+    /// How many of `count` points a reader should allocate room for at once.
+    pub(crate) fn capacity(&self, count: usize) -> usize {
+        count.min(self.size)
+    }
+
+    /// True once `cloud` holds a chunk's worth of points. Readers call `flush` then.
+    pub(crate) fn full(&self, cloud: &PointCloud) -> bool {
+        cloud.len() >= self.size
+    }
+
+    /// Hands the points of `cloud` on, leaving it empty but with the same attributes.
+    /// Does nothing when keeping every point, so readers also call it once at the end.
+    pub(crate) fn flush(&mut self, cloud: &mut PointCloud) -> Result<(), String> {
+        let Some(each) = &mut self.each else {
+            return Ok(());
+        };
+        if cloud.is_empty() {
+            return Ok(());
+        }
+        let empty = PointCloud {
+            origin: cloud.origin,
+            positions: Vec::new(),
+            colors: Vec::new(),
+            attributes: cloud.attributes.iter()
+                .map(|a| Attribute { name: a.name.clone(), values: Vec::new(), offset: a.offset })
+                .collect(),
+        };
+        each(std::mem::replace(cloud, empty))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Ply,
+    Xyz,
+    Pcd,
+    Obj,
+    Las,
+}
+
+impl Format {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Ply => "PLY",
+            Format::Xyz => "XYZ/CSV",
+            Format::Pcd => "PCD",
+            Format::Obj => "OBJ",
+            Format::Las => "LAS",
+        }
+    }
+
+    fn from_extension(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        Some(match extension.as_str() {
+            "ply" => Format::Ply,
+            "xyz" | "csv" | "txt" | "pts" | "asc" => Format::Xyz,
+            "pcd" => Format::Pcd,
+            "obj" => Format::Obj,
+            "las" => Format::Las,
+            _ => return None,
+        })
+    }
+
+    /// Looks at the first bytes of the file for a signature, falling back to XYZ for plain text.
+    fn from_contents(path: &str) -> Result<Self, String> {
+        let mut head = [0u8; 256];
+        let mut file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        let len = file.read(&mut head).map_err(|e| format!("Failed to read file: {}", e))?;
+        if head[..len].starts_with(b"LASF") {
+            return Ok(Format::Las);
+        }
+        let head = String::from_utf8_lossy(&head[..len]);
+
+        if head.starts_with("ply") {
+            return Ok(Format::Ply);
+        }
+        if head.starts_with("# .PCD") || head.starts_with("VERSION") {
+            return Ok(Format::Pcd);
+        }
+        let first_data = head.lines().map(str::trim).find(|l| !l.is_empty() && !l.starts_with('#'));
+        match first_data {
+            Some(line) if line.starts_with("v ") || line.starts_with("o ") || line.starts_with("mtllib") => Ok(Format::Obj),
+            Some(_) => Ok(Format::Xyz),
+            None => Err("Cannot detect point cloud format of an empty file".to_string()),
+        }
+    }
+
+    /// Picks the reader by file extension, or by content if the extension is unknown.
+    pub fn detect(path: &str) -> Result<Self, String> {
+        match Self::from_extension(path) {
+            Some(format) => Ok(format),
+            None => Self::from_contents(path),
+        }
+    }
+}
+
+/// Reader settings for formats that cannot describe themselves.
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct LoadOptions {
+    pub xyz: xyz::XyzColumns,
+}
+
+pub fn load(path: &str, options: &LoadOptions) -> Result<PointCloud, String> {
+    load_with_progress(path, options, &LoadProgress::default())
+}
+
+/// Like [`load`], reporting through `progress` and stopping with an error once it is cancelled.
+pub fn load_with_progress(path: &str, options: &LoadOptions, progress: &LoadProgress) -> Result<PointCloud, String> {
+    read(path, options, progress, &mut Chunks::whole())
+}
+
+/// Like [`load_with_progress`], but hands the points to `each` in chunks of up to `size`
+/// instead of keeping them. Each chunk has an origin of its own. OBJ pairs normals with
+/// vertices only at the end of the file, so it comes as a single chunk.
+pub fn load_chunks(
+    path: &str,
+    options: &LoadOptions,
+    progress: &LoadProgress,
+    size: usize,
+    each: &mut dyn FnMut(PointCloud) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut chunks = Chunks::new(size, each);
+    let mut rest = read(path, options, progress, &mut chunks)?;
+    chunks.flush(&mut rest)
+}
+
+fn read(path: &str, options: &LoadOptions, progress: &LoadProgress, chunks: &mut Chunks<'_>) -> Result<PointCloud, String> {
+    match Format::detect(path)? {
+        Format::Ply => ply::read_ply(path, progress, chunks),
+        Format::Xyz => xyz::read_xyz(path, &options.xyz, progress, chunks),
+        Format::Pcd => pcd::read_pcd(path, progress, chunks),
+        Format::Obj => obj::read_obj(path, progress),
+        Format::Las => las::read_las(path, progress, chunks),
+    }
+}
@@ -0,0 +1,102 @@
+use egui::Color32;
+use std::io::BufRead;
+use crate::point_cloud::{Attribute, LoadProgress, PointCloud};
+
+/// Reads the `v x y z [r g b]` lines of an OBJ file; faces and everything else are ignored.
+/// `vn` lines become `nx`, `ny` and `nz` attributes when there is exactly one per vertex,
+/// as point cloud exporters write them.
+pub fn read_obj(path: &str, progress: &LoadProgress) -> Result<PointCloud, String> {
+    let reader = progress.open(path)?;
+    let mut cloud = PointCloud::default();
+    let mut normals = ["nx", "ny", "nz"].map(|name| Attribute::new(name, 0));
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read line: {}", e))?;
+        let mut parts = line.split_whitespace();
+        let keyword = parts.next();
+        if keyword == Some("vn") {
+            let values: Vec<f32> = parts
+                .map(|p| p.parse::<f32>().map_err(|_| format!("Line {}: invalid normal value '{}'", line_number + 1, p)))
+                .collect::<Result<_, _>>()?;
+            if values.len() != 3 {
+                return Err(format!("Line {}: normal needs 3 components", line_number + 1));
+            }
+            for (attribute, value) in normals.iter_mut().zip(values) {
+                attribute.values.push(value);
+            }
+            continue;
+        }
+        if keyword != Some("v") {
+            continue;
+        }
+        let values: Vec<f64> = parts
+            .map(|p| p.parse::<f64>().map_err(|_| format!("Line {}: invalid vertex value '{}'", line_number + 1, p)))
+            .collect::<Result<_, _>>()?;
+        if values.len() < 3 {
+            return Err(format!("Line {}: vertex needs at least 3 coordinates", line_number + 1));
+        }
+
+        let position = [values[0], values[1], values[2]];
+
+        // Vertex colours are a common extension, given as 0-1 floats after the position
+        let color = if values.len() >= 6 {
+            let channel = |c: f64| (c * 255.0).clamp(0.0, 255.0) as u8;
+            Color32::from_rgb(channel(values[3]), channel(values[4]), channel(values[5]))
+        } else {
+            Color32::WHITE
+        };
+        cloud.push(position, color);
+    }
+
+    if !cloud.is_empty() && normals[0].values.len() == cloud.len() {
+        cloud.attributes.extend(normals);
+    }
+    Ok(cloud)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(name: &str, contents: &str) -> Result<PointCloud, String> {
+        let path = std::env::temp_dir().join(format!("rushroom-obj-{}-{}.obj", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        let read = read_obj(path.to_str().unwrap(), &LoadProgress::default());
+        std::fs::remove_file(&path).unwrap();
+        read
+    }
+
+    #[test]
+    fn vertices_and_colours_are_read_and_the_rest_ignored() {
+        let contents = "# mesh\nmtllib scene.mtl\no part\nv 1 2 3\nv 4 5 6 1 0.5 0\nvt 0.5 0.5\nf 1 2 1\n";
+        let cloud = read("vertices", contents).unwrap();
+
+        assert_eq!(cloud.len(), 2);
+        assert_eq!(cloud.position(0), [1.0, 2.0, 3.0]);
+        assert_eq!(cloud.position(1), [4.0, 5.0, 6.0]);
+        assert_eq!(cloud.colors, [Color32::WHITE, Color32::from_rgb(255, 127, 0)]);
+        assert!(cloud.attributes.is_empty());
+    }
+
+    #[test]
+    fn one_normal_per_vertex_becomes_attributes() {
+        let cloud = read("normals", "v 0 0 0\nvn 0 0 1\nv 1 0 0\nvn 1 0 0\n").unwrap();
+        let [nx, _, nz] = cloud.normals().unwrap();
+        assert_eq!(nx.values, [0.0, 1.0]);
+        assert_eq!(nz.values, [1.0, 0.0]);
+    }
+
+    #[test]
+    fn mesh_normals_that_do_not_match_the_vertices_are_dropped() {
+        let cloud = read("mesh-normals", "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n").unwrap();
+        assert_eq!(cloud.len(), 3);
+        assert!(cloud.normals().is_none());
+    }
+
+    #[test]
+    fn malformed_lines_are_errors() {
+        assert_eq!(read("short", "v 1 2\n"), Err("Line 1: vertex needs at least 3 coordinates".to_string()));
+        assert_eq!(read("invalid", "v 1 2 x\n"), Err("Line 1: invalid vertex value 'x'".to_string()));
+        assert_eq!(read("normal", "v 1 2 3\nvn 0 1\n"), Err("Line 2: normal needs 3 components".to_string()));
+    }
+}
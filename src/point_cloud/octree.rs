@@ -0,0 +1,393 @@
+use glam::{Mat4, UVec3, Vec3, Vec4};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use crate::point_cloud::PointCloud;
+
+/// Cells per axis of the sampling grid; a node keeps at most one point per cell.
+pub(crate) const GRID: u32 = 128;
+/// Nodes with fewer points than this keep all of them instead of splitting.
+pub(crate) const MAX_LEAF_POINTS: usize = 20_000;
+pub(crate) const MAX_DEPTH: u32 = 20;
+
+/// A cube of space holding an evenly spread sample of the points inside it.
+/// Children refine it with the points it left out, so a node and all its
+/// ancestors together never duplicate a point.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct OctreeNode {
+    /// Corner of the cube, in the cloud's origin-relative coordinates
+    pub min: [f32; 3],
+    pub size: f32,
+    /// Distance between neighbouring points of this node, roughly
+    pub spacing: f32,
+    pub depth: u32,
+    /// Range of this node's own points in `Octree::order`
+    pub first: usize,
+    pub count: usize,
+    pub children: Vec<u32>,
+}
+
+impl OctreeNode {
+    /// An empty node for the cube at `min` with edge `size`.
+    pub(crate) fn cube(min: [f32; 3], size: f32, depth: u32) -> Self {
+        Self { min, size, spacing: size / GRID as f32, depth, first: 0, count: 0, children: Vec::new() }
+    }
+
+    /// The smallest cube holding the box from `lo` to `hi`. A little slack keeps the
+    /// points on the far faces inside the last cell.
+    pub(crate) fn bounding(lo: Vec3, hi: Vec3) -> Self {
+        Self::cube(lo.into(), (hi - lo).max_element().max(f32::EPSILON) * 1.0001, 0)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        Vec3::from(self.min) + Vec3::splat(self.size / 2.0)
+    }
+
+    pub fn max(&self) -> Vec3 {
+        Vec3::from(self.min) + Vec3::splat(self.size)
+    }
+
+    /// Sampling grid cell of a point inside the node, as a single index.
+    pub(crate) fn cell(&self, position: [f32; 3]) -> u32 {
+        let cell = self.grid_cell(position);
+        (cell.x * GRID + cell.y) * GRID + cell.z
+    }
+
+    /// Which child cube a point inside the node falls in.
+    pub(crate) fn octant(&self, position: [f32; 3]) -> usize {
+        let half = self.grid_cell(position) / (GRID / 2);
+        (half.x * 4 + half.y * 2 + half.z) as usize
+    }
+
+    /// The empty child node for `octant`.
+    pub(crate) fn child(&self, octant: usize) -> Self {
+        let offset = Vec3::new((octant >> 2) as f32, ((octant >> 1) & 1) as f32, (octant & 1) as f32);
+        Self::cube((Vec3::from(self.min) + offset * self.size / 2.0).into(), self.size / 2.0, self.depth + 1)
+    }
+
+    fn grid_cell(&self, position: [f32; 3]) -> UVec3 {
+        let local = (Vec3::from(position) - Vec3::from(self.min)) / self.size;
+        (local * GRID as f32).as_uvec3().min(UVec3::splat(GRID - 1))
+    }
+}
+
+/// Level-of-detail hierarchy over a point cloud. Node 0 is the root.
+#[derive(Clone, Debug, Default)]
+pub struct Octree {
+    pub nodes: Vec<OctreeNode>,
+    /// Cloud point indices, grouped so each node's points are contiguous
+    pub order: Vec<u32>,
+}
+
+/// A node waiting to be built, with the points that fall inside it.
+struct Pending {
+    node: u32,
+    points: Vec<u32>,
+}
+
+impl Octree {
+    pub fn build(cloud: &PointCloud) -> Self {
+        if cloud.is_empty() {
+            return Octree::default();
+        }
+        let (mut lo, mut hi) = (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN));
+        for &p in &cloud.positions {
+            lo = lo.min(Vec3::from(p));
+            hi = hi.max(Vec3::from(p));
+        }
+        Self::build_below(OctreeNode::bounding(lo, hi), &cloud.positions)
+    }
+
+    /// Builds the hierarchy below `root`, whose cube holds every one of `positions`.
+    /// `order` indexes `positions`.
+    pub(crate) fn build_below(root: OctreeNode, positions: &[[f32; 3]]) -> Self {
+        let mut octree = Octree { nodes: vec![root], order: Vec::with_capacity(positions.len()) };
+        let mut stack = vec![Pending { node: 0, points: (0..positions.len() as u32).collect() }];
+
+        while let Some(Pending { node, points }) = stack.pop() {
+            let first = octree.order.len();
+
+            if points.len() <= MAX_LEAF_POINTS || octree.nodes[node as usize].depth >= MAX_DEPTH {
+                octree.order.extend_from_slice(&points);
+                let n = &mut octree.nodes[node as usize];
+                n.first = first;
+                n.count = points.len();
+                continue;
+            }
+
+            // Keep the first point to land in each grid cell, hand the rest down
+            let parent = &octree.nodes[node as usize];
+            let mut taken = HashSet::new();
+            let mut octants: [Vec<u32>; 8] = Default::default();
+            for i in points {
+                let position = positions[i as usize];
+                if taken.insert(parent.cell(position)) {
+                    octree.order.push(i);
+                } else {
+                    octants[parent.octant(position)].push(i);
+                }
+            }
+
+            let count = octree.order.len() - first;
+            let mut children = Vec::new();
+            for (octant, points) in octants.into_iter().enumerate() {
+                if points.is_empty() {
+                    continue;
+                }
+                let child = octree.nodes.len() as u32;
+                octree.nodes.push(octree.nodes[node as usize].child(octant));
+                children.push(child);
+                stack.push(Pending { node: child, points });
+            }
+
+            let n = &mut octree.nodes[node as usize];
+            n.first = first;
+            n.count = count;
+            n.children = children;
+        }
+
+        octree
+    }
+
+    /// Picks the nodes to draw for a view: visible ones, coarsest first, refining
+    /// while a node's point spacing covers more than `max_spacing_px` pixels on screen
+    /// and the point budget lasts.
+    pub fn select(&self, view: &LodView, max_spacing_px: f32, budget: usize) -> Vec<u32> {
+        let mut selected = Vec::new();
+        if self.nodes.is_empty() {
+            return selected;
+        }
+
+        let mut planes = frustum_planes(view.view_projection).to_vec();
+        planes.extend_from_slice(&view.clip_planes);
+        let mut queue = BinaryHeap::new();
+        queue.push(Candidate { priority: f32::MAX, node: 0 });
+        let mut points = 0;
+
+        while let Some(Candidate { node, .. }) = queue.pop() {
+            let n = &self.nodes[node as usize];
+            if !box_in_frustum(&planes, Vec3::from(n.min), n.max()) {
+                continue;
+            }
+            if points + n.count > budget {
+                break;
+            }
+            points += n.count;
+            selected.push(node);
+
+            if view.projected(n, n.spacing) > max_spacing_px {
+                for &child in &n.children {
+                    let c = &self.nodes[child as usize];
+                    queue.push(Candidate { priority: view.projected(c, c.size), node: child });
+                }
+            }
+        }
+        selected
+    }
+}
+
+/// What node selection needs to know about the camera.
+pub struct LodView {
+    pub view_projection: Mat4,
+    pub eye: Vec3,
+    /// Pixels per world unit at distance 1, i.e. viewport height / (2 tan(fov / 2)),
+    /// or at any distance for an orthographic view
+    pub pixels_per_unit: f32,
+    pub orthographic: bool,
+    /// More planes nodes must not be entirely outside of, pointing inwards as
+    /// `frustum_planes` does
+    pub clip_planes: Vec<Vec4>,
+}
+
+impl LodView {
+    /// Size on screen in pixels of `length` world units at the node's distance.
+    fn projected(&self, node: &OctreeNode, length: f32) -> f32 {
+        if self.orthographic {
+            return length * self.pixels_per_unit;
+        }
+        let radius = node.size * 0.5 * 3f32.sqrt();
+        let distance = (node.center().distance(self.eye) - radius).max(1e-3);
+        length / distance * self.pixels_per_unit
+    }
+}
+
+struct Candidate {
+    priority: f32,
+    node: u32,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.total_cmp(&other.priority)
+    }
+}
+
+/// The six clip planes of a view-projection matrix, pointing inwards.
+pub fn frustum_planes(m: Mat4) -> [Vec4; 6] {
+    let rows = [m.row(0), m.row(1), m.row(2), m.row(3)];
+    [
+        rows[3] + rows[0],
+        rows[3] - rows[0],
+        rows[3] + rows[1],
+        rows[3] - rows[1],
+        rows[3] + rows[2],
+        rows[3] - rows[2],
+    ]
+}
+
+/// False only if the box is entirely outside one of the planes.
+pub fn box_in_frustum(planes: &[Vec4], min: Vec3, max: Vec3) -> bool {
+    planes.iter().all(|plane| {
+        // The corner furthest along the plane normal
+        let corner = Vec3::select(plane.truncate().cmpge(Vec3::ZERO), max, min);
+        plane.truncate().dot(corner) + plane.w >= 0.0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use egui::Color32;
+    use super::*;
+
+    /// `count` points spread pseudo-randomly over the cube from 0 to 10, around a zero origin.
+    fn cloud(count: usize) -> PointCloud {
+        let mut seed = 1u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 40) as f32 / (1u64 << 24) as f32 * 10.0
+        };
+        PointCloud {
+            positions: (0..count).map(|_| [next(), next(), next()]).collect(),
+            colors: vec![Color32::WHITE; count],
+            ..PointCloud::default()
+        }
+    }
+
+    fn inside(node: &OctreeNode, p: [f32; 3]) -> bool {
+        (0..3).all(|axis| p[axis] >= node.min[axis] && p[axis] <= node.min[axis] + node.size)
+    }
+
+    /// Looks down at the cloud with an orthographic camera seeing all of it.
+    fn view(pixels_per_unit: f32) -> LodView {
+        let eye = Vec3::new(5.0, 5.0, 100.0);
+        let view = Mat4::look_at_rh(eye, Vec3::new(5.0, 5.0, 0.0), Vec3::Y);
+        LodView {
+            view_projection: Mat4::orthographic_rh_gl(-20.0, 20.0, -20.0, 20.0, 0.1, 1000.0) * view,
+            eye,
+            pixels_per_unit,
+            orthographic: true,
+            clip_planes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn empty_and_small_clouds() {
+        assert!(Octree::build(&PointCloud::default()).nodes.is_empty());
+
+        let small = Octree::build(&cloud(100));
+        assert_eq!(small.nodes.len(), 1);
+        assert_eq!((small.nodes[0].first, small.nodes[0].count), (0, 100));
+        assert_eq!(small.order, (0..100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn every_point_is_in_exactly_one_node_inside_its_cube() {
+        let cloud = cloud(100_000);
+        let octree = Octree::build(&cloud);
+        assert!(octree.nodes.len() > 1);
+
+        let mut seen = vec![false; cloud.len()];
+        for node in &octree.nodes {
+            let points = &octree.order[node.first..node.first + node.count];
+            for &i in points {
+                assert!(!std::mem::replace(&mut seen[i as usize], true), "point {} kept twice", i);
+                assert!(inside(node, cloud.positions[i as usize]));
+            }
+            if node.children.is_empty() {
+                assert!(node.count <= MAX_LEAF_POINTS);
+            } else {
+                // An inner node keeps one point per grid cell
+                let cells: HashSet<u32> = points.iter().map(|&i| node.cell(cloud.positions[i as usize])).collect();
+                assert_eq!(cells.len(), points.len());
+            }
+            for &child in &node.children {
+                let child = &octree.nodes[child as usize];
+                assert_eq!((child.depth, child.size), (node.depth + 1, node.size / 2.0));
+                assert!(inside(node, child.min) && inside(node, child.max().into()));
+            }
+        }
+        assert!(seen.into_iter().all(|s| s));
+    }
+
+    #[test]
+    fn coincident_points_stop_at_the_depth_limit() {
+        let mut cloud = cloud(1);
+        for _ in 0..MAX_LEAF_POINTS * 2 {
+            cloud.push(cloud.position(0), Color32::WHITE);
+        }
+        // One more point elsewhere gives the root a size
+        cloud.push([100.0, 100.0, 100.0], Color32::WHITE);
+
+        let octree = Octree::build(&cloud);
+        assert_eq!(octree.order.len(), cloud.len());
+        assert!(octree.nodes.iter().all(|n| n.depth <= MAX_DEPTH));
+        assert!(octree.nodes.iter().any(|n| n.depth == MAX_DEPTH && n.count > MAX_LEAF_POINTS));
+    }
+
+    #[test]
+    fn select_refines_with_zoom() {
+        let octree = Octree::build(&cloud(100_000));
+        let all = octree.order.len();
+
+        // Zoomed out the root's spacing is under a pixel
+        assert_eq!(octree.select(&view(1.0), 1.0, all), vec![0]);
+
+        // Zoomed in, every node is wanted and the budget covers them
+        let mut selected = octree.select(&view(10_000.0), 1.0, all);
+        selected.sort();
+        assert_eq!(selected, (0..octree.nodes.len() as u32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn select_stays_within_the_budget() {
+        let octree = Octree::build(&cloud(100_000));
+        let budget = octree.nodes[0].count + 1;
+        let selected = octree.select(&view(10_000.0), 1.0, budget);
+        assert_eq!(selected[0], 0);
+        assert!(selected.iter().map(|&n| octree.nodes[n as usize].count).sum::<usize>() <= budget);
+
+        // Not even the root fits
+        assert!(octree.select(&view(10_000.0), 1.0, octree.nodes[0].count - 1).is_empty());
+    }
+
+    #[test]
+    fn select_skips_nodes_outside_the_view() {
+        let octree = Octree::build(&cloud(100_000));
+        let mut behind = view(10_000.0);
+        behind.eye = Vec3::new(5.0, 5.0, -100.0);
+        behind.view_projection = Mat4::orthographic_rh_gl(-20.0, 20.0, -20.0, 20.0, 0.1, 1000.0)
+            * Mat4::look_at_rh(behind.eye, Vec3::new(5.0, 5.0, -200.0), Vec3::Y);
+        assert!(octree.select(&behind, 1.0, usize::MAX).is_empty());
+
+        // A clip plane keeping only x < 2 leaves out the nodes entirely beyond it
+        let mut clipped = view(10_000.0);
+        clipped.clip_planes.push(Vec4::new(-1.0, 0.0, 0.0, 2.0));
+        let selected = octree.select(&clipped, 1.0, usize::MAX);
+        assert!(!selected.is_empty());
+        assert!(selected.iter().all(|&n| octree.nodes[n as usize].min[0] <= 2.0));
+        assert!(selected.len() < octree.nodes.len());
+    }
+}
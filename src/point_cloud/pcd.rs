@@ -0,0 +1,378 @@
+use egui::Color32;
+use std::io::{BufRead, Read};
+use crate::point_cloud::{Attribute, Chunks, LoadProgress, PointCloud};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PcdData {
+    Ascii,
+    Binary,
+    BinaryCompressed,
+}
+
+#[derive(Clone, Debug)]
+struct PcdField {
+    name: String,
+    size: usize,
+    /// `I` signed, `U` unsigned or `F` floating point
+    kind: char,
+    count: usize,
+}
+
+impl PcdField {
+    fn decode(&self, bytes: &[u8]) -> f64 {
+        macro_rules! le {
+            ($t:ty, $n:expr) => {{
+                let mut buf = [0u8; $n];
+                buf.copy_from_slice(&bytes[..$n]);
+                <$t>::from_le_bytes(buf) as f64
+            }};
+        }
+        match (self.kind, self.size) {
+            ('I', 1) => bytes[0] as i8 as f64,
+            ('U', 1) => bytes[0] as f64,
+            ('I', 2) => le!(i16, 2),
+            ('U', 2) => le!(u16, 2),
+            ('I', 4) => le!(i32, 4),
+            ('U', 4) => le!(u32, 4),
+            ('F', 4) => le!(f32, 4),
+            ('I', 8) => le!(i64, 8),
+            ('U', 8) => le!(u64, 8),
+            ('F', 8) => le!(f64, 8),
+            _ => f64::NAN,
+        }
+    }
+
+    /// PCL packs colour into the bits of a float (`rgb`) or a uint (`rgba`).
+    fn decode_color(&self, bytes: &[u8]) -> Color32 {
+        let bits = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let [b, g, r, a] = bits.to_le_bytes();
+        let a = if self.name == "rgba" { a } else { 255 };
+        Color32::from_rgba_premultiplied(r, g, b, a)
+    }
+}
+
+struct PcdHeader {
+    fields: Vec<PcdField>,
+    points: usize,
+    data: PcdData,
+}
+
+fn parse_header<R: BufRead>(reader: &mut R) -> Result<PcdHeader, String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut kinds: Vec<char> = Vec::new();
+    let mut counts: Vec<usize> = Vec::new();
+    let mut width = 0;
+    let mut height = 1;
+    let mut points = None;
+    let mut line = String::new();
+
+    let data = loop {
+        line.clear();
+        let read = reader.read_line(&mut line).map_err(|e| format!("Failed to read header: {}", e))?;
+        if read == 0 {
+            return Err("Unexpected end of file in PCD header".to_string());
+        }
+        let mut parts = line.split_whitespace();
+        let Some(key) = parts.next() else { continue };
+        let rest: Vec<&str> = parts.collect();
+        let numbers = |name: &str| -> Result<Vec<usize>, String> {
+            rest.iter().map(|v| v.parse().map_err(|_| format!("Invalid PCD {} '{}'", name, v))).collect()
+        };
+
+        match key.to_ascii_uppercase().as_str() {
+            "FIELDS" | "COLUMNS" => names = rest.iter().map(|s| s.to_string()).collect(),
+            "SIZE" => sizes = numbers("SIZE")?,
+            "TYPE" => kinds = rest.iter().map(|s| s.chars().next().unwrap_or('F').to_ascii_uppercase()).collect(),
+            "COUNT" => counts = numbers("COUNT")?,
+            "WIDTH" => width = numbers("WIDTH")?.first().copied().unwrap_or(0),
+            "HEIGHT" => height = numbers("HEIGHT")?.first().copied().unwrap_or(1),
+            "POINTS" => points = numbers("POINTS")?.first().copied(),
+            "DATA" => break match rest.first().copied() {
+                Some("ascii") => PcdData::Ascii,
+                Some("binary") => PcdData::Binary,
+                Some("binary_compressed") => PcdData::BinaryCompressed,
+                other => return Err(format!("Unknown PCD data type {:?}", other)),
+            },
+            // comments, VERSION and VIEWPOINT
+            _ => {}
+        }
+    };
+
+    if sizes.len() != names.len() || kinds.len() != names.len() {
+        return Err("PCD FIELDS, SIZE and TYPE lines do not match".to_string());
+    }
+    if counts.is_empty() {
+        counts = vec![1; names.len()];
+    }
+
+    let fields = names.into_iter().enumerate()
+        .map(|(i, name)| PcdField { name, size: sizes[i], kind: kinds[i], count: counts.get(i).copied().unwrap_or(1) })
+        .collect();
+
+    Ok(PcdHeader {
+        fields,
+        points: points.unwrap_or(width * height),
+        data,
+    })
+}
+
+/// Where x/y/z, colour and attributes come from, as (field index, element within field).
+struct PcdLayout {
+    position: [usize; 3],
+    color: Option<usize>,
+    extra: Vec<(usize, usize)>,
+}
+
+impl PcdLayout {
+    fn new(header: &PcdHeader) -> Result<Self, String> {
+        let find = |name: &str| header.fields.iter().position(|f| f.name == name);
+        let axis = |name: &str| find(name).ok_or_else(|| format!("PCD file has no '{}' field", name));
+        let position = [axis("x")?, axis("y")?, axis("z")?];
+        let color = find("rgb").or_else(|| find("rgba")).filter(|&i| header.fields[i].size == 4);
+
+        let mut extra = Vec::new();
+        for (i, field) in header.fields.iter().enumerate() {
+            if position.contains(&i) || Some(i) == color || field.name == "_" {
+                continue;
+            }
+            extra.extend((0..field.count).map(|j| (i, j)));
+        }
+        Ok(Self { position, color, extra })
+    }
+
+    fn attributes(&self, header: &PcdHeader, capacity: usize) -> Vec<Attribute> {
+        self.extra.iter()
+            .map(|&(i, j)| {
+                let field = &header.fields[i];
+                let name = if field.count > 1 { format!("{}_{}", field.name, j) } else { field.name.clone() };
+                Attribute::new(name, capacity)
+            })
+            .collect()
+    }
+}
+
+pub(crate) fn read_pcd(path: &str, progress: &LoadProgress, chunks: &mut Chunks<'_>) -> Result<PointCloud, String> {
+    let mut reader = progress.open(path)?;
+
+    let header = parse_header(&mut reader)?;
+    let layout = PcdLayout::new(&header)?;
+    // At least a byte per point, except in compressed data, where the cloud grows past it
+    let capacity = chunks.capacity(progress.capacity(header.points, 1));
+    let mut cloud = PointCloud::with_capacity(capacity);
+    cloud.attributes = layout.attributes(&header, capacity);
+
+    match header.data {
+        PcdData::Ascii => read_ascii(reader, &header, &layout, &mut cloud, chunks)?,
+        PcdData::Binary => {
+            let stride: usize = header.fields.iter().map(|f| f.size * f.count).sum();
+            let mut record = vec![0u8; stride];
+            let offsets = field_offsets(&header, |f| f.size * f.count);
+            for i in 0..header.points {
+                reader.read_exact(&mut record)
+                    .map_err(|e| format!("Failed to read point {} of {}: {}", i, header.points, e))?;
+                push_point(&mut cloud, &header, &layout, |field, element| {
+                    &record[offsets[field] + element * header.fields[field].size..]
+                });
+                if chunks.full(&cloud) {
+                    chunks.flush(&mut cloud)?;
+                }
+            }
+        }
+        PcdData::BinaryCompressed => {
+            let mut sizes = [0u8; 8];
+            reader.read_exact(&mut sizes).map_err(|e| format!("Failed to read PCD data: {}", e))?;
+            let compressed = u32::from_le_bytes([sizes[0], sizes[1], sizes[2], sizes[3]]) as usize;
+            let uncompressed = u32::from_le_bytes([sizes[4], sizes[5], sizes[6], sizes[7]]) as usize;
+            // Read up to the declared size rather than allocating it first; it may be a lie
+            let mut input = Vec::new();
+            (&mut reader).take(compressed as u64).read_to_end(&mut input)
+                .map_err(|e| format!("Failed to read PCD data: {}", e))?;
+            if input.len() < compressed {
+                return Err("PCD compressed data is larger than the file".to_string());
+            }
+            let data = lzf_decompress(&input, uncompressed)?;
+
+            let stride: usize = header.fields.iter().map(|f| f.size * f.count).sum();
+            if header.points.checked_mul(stride).is_none_or(|size| size > data.len()) {
+                return Err("PCD compressed data is shorter than the header declares".to_string());
+            }
+            // Compressed data is stored field by field rather than point by point
+            let offsets = field_offsets(&header, |f| f.size * f.count * header.points);
+            if offsets.last().copied().unwrap_or(0) > data.len() {
+                return Err("PCD compressed data is shorter than the header declares".to_string());
+            }
+            for i in 0..header.points {
+                push_point(&mut cloud, &header, &layout, |field, element| {
+                    let f = &header.fields[field];
+                    &data[offsets[field] + (i * f.count + element) * f.size..]
+                });
+                if chunks.full(&cloud) {
+                    chunks.flush(&mut cloud)?;
+                }
+            }
+        }
+    }
+
+    Ok(cloud)
+}
+
+/// Running start offset of each field, plus the total at the end.
+fn field_offsets(header: &PcdHeader, span: impl Fn(&PcdField) -> usize) -> Vec<usize> {
+    let mut offsets = vec![0];
+    for field in &header.fields {
+        offsets.push(offsets.last().unwrap() + span(field));
+    }
+    offsets
+}
+
+fn push_point<'a>(
+    cloud: &mut PointCloud,
+    header: &PcdHeader,
+    layout: &PcdLayout,
+    bytes: impl Fn(usize, usize) -> &'a [u8],
+) {
+    let value = |field: usize, element: usize| header.fields[field].decode(bytes(field, element));
+
+    let position = layout.position.map(|f| value(f, 0));
+    let color = layout.color.map_or(Color32::WHITE, |f| header.fields[f].decode_color(bytes(f, 0)));
+    cloud.push(position, color);
+
+    for (attribute, &(field, element)) in cloud.attributes.iter_mut().zip(&layout.extra) {
+        attribute.values.push(value(field, element) as f32);
+    }
+}
+
+fn read_ascii<R: BufRead>(
+    reader: R,
+    header: &PcdHeader,
+    layout: &PcdLayout,
+    cloud: &mut PointCloud,
+    chunks: &mut Chunks<'_>,
+) -> Result<(), String> {
+    // Index of the first token of each field on a line
+    let starts = field_offsets(header, |f| f.count);
+
+    // Counted apart from the cloud, which is emptied whenever a chunk is handed on
+    let mut read = 0;
+    for line in reader.lines().take(header.points) {
+        let line = line.map_err(|e| format!("Failed to read line: {}", e))?;
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() < *starts.last().unwrap() {
+            return Err(format!("PCD point {} has too few values", read));
+        }
+        let value = |field: usize, element: usize| tokens[starts[field] + element].parse::<f64>().unwrap_or(f64::NAN);
+
+        let position = layout.position.map(|f| value(f, 0));
+        let color = layout.color.map_or(Color32::WHITE, |f| {
+            // Packed colour is written as the float or integer with the same bits
+            let token = tokens[starts[f]];
+            let bits = token.parse::<u32>().unwrap_or_else(|_| token.parse::<f32>().unwrap_or(0.0).to_bits());
+            header.fields[f].decode_color(&bits.to_le_bytes())
+        });
+        cloud.push(position, color);
+
+        for (attribute, &(field, element)) in cloud.attributes.iter_mut().zip(&layout.extra) {
+            attribute.values.push(value(field, element) as f32);
+        }
+        read += 1;
+        if chunks.full(cloud) {
+            chunks.flush(cloud)?;
+        }
+    }
+    if read < header.points {
+        return Err(format!("PCD file ends after {} of {} points", read, header.points));
+    }
+    Ok(())
+}
+
+/// Most bytes LZF can expand one input byte to: a three byte back reference copies 264.
+const MAX_LZF_RATIO: usize = 88;
+
+/// Decompresses LZF, as used by PCL's `binary_compressed` format.
+fn lzf_decompress(input: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let corrupt = || "Corrupt LZF data in PCD file".to_string();
+    let mut out: Vec<u8> = Vec::with_capacity(expected.min(input.len().saturating_mul(MAX_LZF_RATIO)));
+    let mut i = 0;
+
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            // Literal run of ctrl + 1 bytes
+            let run = input.get(i..i + ctrl + 1).ok_or_else(corrupt)?;
+            out.extend_from_slice(run);
+            i += ctrl + 1;
+        } else {
+            // Back reference
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                len += *input.get(i).ok_or_else(corrupt)? as usize;
+                i += 1;
+            }
+            let low = *input.get(i).ok_or_else(corrupt)? as usize;
+            i += 1;
+            let distance = ((ctrl & 0x1f) << 8) + low + 1;
+            let start = out.len().checked_sub(distance).ok_or_else(corrupt)?;
+            for j in 0..len + 2 {
+                out.push(out[start + j]);
+            }
+        }
+    }
+
+    if out.len() != expected {
+        return Err(format!("LZF data decompressed to {} bytes, expected {}", out.len(), expected));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lzf_literal_runs() {
+        assert_eq!(lzf_decompress(&[2, b'a', b'b', b'c', 0, b'd'], 4), Ok(b"abcd".to_vec()));
+        assert_eq!(lzf_decompress(&[], 0), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn lzf_back_references_may_overlap_their_output() {
+        // "a", then 5 bytes from 1 back
+        assert_eq!(lzf_decompress(&[0, b'a', 3 << 5, 0], 6), Ok(b"aaaaaa".to_vec()));
+        // "ab", then a long reference: 7 + 11 + 2 = 20 bytes from 2 back
+        let expected: Vec<u8> = b"ab".iter().copied().cycle().take(22).collect();
+        assert_eq!(lzf_decompress(&[1, b'a', b'b', 7 << 5, 11, 1], 22), Ok(expected));
+        // 300 literal bytes, then 3 bytes from 300 back, which needs the high distance bits
+        let literal: Vec<u8> = (0..300).map(|i| (i % 251) as u8).collect();
+        let mut input: Vec<u8> = literal.chunks(30).flat_map(|run| [29].into_iter().chain(run.iter().copied())).collect();
+        let distance = 300u16 - 1;
+        input.extend([(1 << 5) | (distance >> 8) as u8, distance as u8]);
+        let mut expected = literal.clone();
+        expected.extend_from_slice(&literal[..3]);
+        assert_eq!(lzf_decompress(&input, expected.len()), Ok(expected));
+    }
+
+    #[test]
+    fn lzf_rejects_references_before_the_start() {
+        assert!(lzf_decompress(&[3 << 5, 0], 5).is_err());
+        assert!(lzf_decompress(&[0, b'a', 3 << 5, 1], 6).is_err());
+    }
+
+    #[test]
+    fn lzf_rejects_truncated_input() {
+        // Literal run longer than what is left
+        assert!(lzf_decompress(&[4, b'a', b'b'], 5).is_err());
+        // Back reference missing its distance byte, or its length byte
+        assert!(lzf_decompress(&[0, b'a', 3 << 5], 6).is_err());
+        assert!(lzf_decompress(&[0, b'a', 7 << 5], 10).is_err());
+    }
+
+    #[test]
+    fn lzf_checks_the_decompressed_size() {
+        assert_eq!(
+            lzf_decompress(&[0, b'a'], 2),
+            Err("LZF data decompressed to 1 bytes, expected 2".to_string()),
+        );
+    }
+}
@@ -0,0 +1,784 @@
+use egui::Color32;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use crate::point_cloud::{Attribute, Chunks, LoadProgress, PointCloud};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum PlyFormat {
+    Ascii,
+    #[default]
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+impl PlyFormat {
+    pub const ALL: [PlyFormat; 3] = [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian, PlyFormat::BinaryBigEndian];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PlyFormat::Ascii => "ASCII",
+            PlyFormat::BinaryLittleEndian => "Binary (little endian)",
+            PlyFormat::BinaryBigEndian => "Binary (big endian)",
+        }
+    }
+}
+
+/// PLY scalar property types, including the sized aliases (`uint8`, `float32`, ...).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalarType {
+    Char,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => ScalarType::Char,
+            "uchar" | "uint8" => ScalarType::UChar,
+            "short" | "int16" => ScalarType::Short,
+            "ushort" | "uint16" => ScalarType::UShort,
+            "int" | "int32" => ScalarType::Int,
+            "uint" | "uint32" => ScalarType::UInt,
+            "float" | "float32" => ScalarType::Float,
+            "double" | "float64" => ScalarType::Double,
+            _ => return Err(format!("Unknown PLY property type '{}'", name)),
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            ScalarType::Char | ScalarType::UChar => 1,
+            ScalarType::Short | ScalarType::UShort => 2,
+            ScalarType::Int | ScalarType::UInt | ScalarType::Float => 4,
+            ScalarType::Double => 8,
+        }
+    }
+
+    /// Decodes one value from the start of `bytes`.
+    fn decode(&self, bytes: &[u8], big_endian: bool) -> f64 {
+        macro_rules! decode {
+            ($t:ty, $n:expr) => {{
+                let mut buf = [0u8; $n];
+                buf.copy_from_slice(&bytes[..$n]);
+                if big_endian { <$t>::from_be_bytes(buf) as f64 } else { <$t>::from_le_bytes(buf) as f64 }
+            }};
+        }
+        match self {
+            ScalarType::Char => bytes[0] as i8 as f64,
+            ScalarType::UChar => bytes[0] as f64,
+            ScalarType::Short => decode!(i16, 2),
+            ScalarType::UShort => decode!(u16, 2),
+            ScalarType::Int => decode!(i32, 4),
+            ScalarType::UInt => decode!(u32, 4),
+            ScalarType::Float => decode!(f32, 4),
+            ScalarType::Double => decode!(f64, 8),
+        }
+    }
+
+    /// Colours stored as floats are in 0..1, integers in 0..255.
+    fn color_scale(&self) -> f64 {
+        match self {
+            ScalarType::Float | ScalarType::Double => 255.0,
+            _ => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlyProperty {
+    Scalar { name: String, kind: ScalarType },
+    /// A length prefix of type `count` followed by that many `item`s.
+    List { name: String, count: ScalarType, item: ScalarType },
+}
+
+impl PlyProperty {
+    pub fn name(&self) -> &str {
+        match self {
+            PlyProperty::Scalar { name, .. } | PlyProperty::List { name, .. } => name,
+        }
+    }
+
+    pub fn scalar_type(&self) -> Option<ScalarType> {
+        match self {
+            PlyProperty::Scalar { kind, .. } => Some(*kind),
+            PlyProperty::List { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlyElement {
+    pub name: String,
+    pub count: usize,
+    pub properties: Vec<PlyProperty>,
+}
+
+impl PlyElement {
+    /// Size of one binary record, or `None` if a list property makes it variable.
+    fn record_size(&self) -> Option<usize> {
+        self.properties.iter().map(|p| p.scalar_type().map(|t| t.size())).sum()
+    }
+
+    fn property_index(&self, names: &[&str]) -> Option<usize> {
+        self.properties.iter().position(|p| p.scalar_type().is_some() && names.contains(&p.name()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlyHeader {
+    pub format: PlyFormat,
+    pub elements: Vec<PlyElement>,
+    pub comments: Vec<String>,
+}
+
+/// Where each needed value sits in a vertex record, in declared property order.
+struct VertexLayout {
+    position: [usize; 3],
+    color: Option<[usize; 3]>,
+    alpha: Option<usize>,
+    color_scale: f64,
+    /// Every other property, kept as a named attribute. Lists only keep their length,
+    /// see `attribute_name`.
+    extra: Vec<usize>,
+    /// Which of `extra` are doubles, and so stored relative to the first point.
+    double: Vec<bool>,
+}
+
+impl VertexLayout {
+    fn new(element: &PlyElement) -> Result<Self, String> {
+        let axis = |name: &str| element.property_index(&[name])
+            .ok_or_else(|| format!("PLY vertex has no '{}' property", name));
+
+        let position = [axis("x")?, axis("y")?, axis("z")?];
+        let color = match (
+            element.property_index(&["red", "r", "diffuse_red"]),
+            element.property_index(&["green", "g", "diffuse_green"]),
+            element.property_index(&["blue", "b", "diffuse_blue"]),
+        ) {
+            (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+            _ => None,
+        };
+        let alpha = color.and(element.property_index(&["alpha", "a", "diffuse_alpha"]));
+        let color_scale = color.map_or(1.0, |[r, _, _]| {
+            element.properties[r].scalar_type().map_or(1.0, |t| t.color_scale())
+        });
+
+        let used: Vec<usize> = position.iter().chain(color.iter().flatten()).chain(alpha.iter()).copied().collect();
+        let extra = (0..element.properties.len())
+            .filter(|i| !used.contains(i))
+            .collect::<Vec<_>>();
+        let double = extra.iter()
+            .map(|&i| element.properties[i].scalar_type() == Some(ScalarType::Double))
+            .collect();
+
+        Ok(Self { position, color, alpha, color_scale, extra, double })
+    }
+
+    /// Scalars keep their name; a list becomes `<name>_count`, holding its length, since
+    /// a point attribute has one value per point.
+    fn attribute_name(property: &PlyProperty) -> String {
+        match property {
+            PlyProperty::Scalar { name, .. } => name.clone(),
+            PlyProperty::List { name, .. } => format!("{}_count", name),
+        }
+    }
+
+    /// Doubles (gps_time and the like) are too large for `f32`, so they are kept relative
+    /// to the first point's value, as the LAS reader does.
+    fn set_offsets(&self, cloud: &mut PointCloud, values: &[f64]) {
+        for ((attribute, &i), &double) in cloud.attributes.iter_mut().zip(&self.extra).zip(&self.double) {
+            if double && values[i].is_finite() {
+                attribute.offset = values[i];
+            }
+        }
+    }
+
+    fn push(&self, cloud: &mut PointCloud, values: &[f64]) {
+        let position = self.position.map(|i| values[i]);
+
+        let channel = |i: usize| (values[i] * self.color_scale).clamp(0.0, 255.0) as u8;
+        let color = match self.color {
+            Some([r, g, b]) => {
+                let a = self.alpha.map_or(255, channel);
+                // Kept as straight alpha, see `PointCloud::colors`
+                Color32::from_rgba_premultiplied(channel(r), channel(g), channel(b), a)
+            }
+            None => Color32::WHITE,
+        };
+        cloud.push(position, color);
+
+        for (attribute, &i) in cloud.attributes.iter_mut().zip(&self.extra) {
+            attribute.values.push((values[i] - attribute.offset) as f32);
+        }
+    }
+}
+
+/// Reads element records from the body one at a time.
+/// Scalar properties fill one slot each; list properties are consumed and their length stored.
+enum Body<R> {
+    Ascii(std::io::Lines<R>),
+    Binary { reader: R, big_endian: bool },
+}
+
+impl<R: BufRead> Body<R> {
+    fn read_record(&mut self, element: &PlyElement, values: &mut [f64]) -> Result<(), String> {
+        match self {
+            Body::Ascii(lines) => {
+                let line = lines.next()
+                    .ok_or_else(|| format!("Unexpected end of file in element '{}'", element.name))?
+                    .map_err(|e| format!("Failed to read line: {}", e))?;
+                let mut parts = line.split_whitespace().map(|p| p.parse::<f64>());
+                let mut next = |name: &str| match parts.next() {
+                    Some(Ok(v)) => Ok(v),
+                    _ => Err(format!("Invalid value for '{}' in element '{}'", name, element.name)),
+                };
+                for (value, property) in values.iter_mut().zip(&element.properties) {
+                    *value = next(property.name())?;
+                    if let PlyProperty::List { .. } = property {
+                        for _ in 0..*value as usize {
+                            next(property.name())?;
+                        }
+                    }
+                }
+            }
+            Body::Binary { reader, big_endian } => {
+                let mut buf = [0u8; 8];
+                let mut read = |kind: ScalarType| -> Result<f64, String> {
+                    let bytes = &mut buf[..kind.size()];
+                    reader.read_exact(bytes)
+                        .map_err(|e| format!("Failed to read element '{}': {}", element.name, e))?;
+                    Ok(kind.decode(bytes, *big_endian))
+                };
+                for (value, property) in values.iter_mut().zip(&element.properties) {
+                    match property {
+                        PlyProperty::Scalar { kind, .. } => *value = read(*kind)?,
+                        PlyProperty::List { count, item, .. } => {
+                            *value = read(*count)?;
+                            for _ in 0..*value as usize {
+                                read(*item)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads fixed-size binary records whole, which is much faster than per property.
+    /// Returns false if the element has to go through `read_record` instead.
+    fn read_fixed(
+        &mut self,
+        element: &PlyElement,
+        each: &mut impl FnMut(&[f64]) -> Result<(), String>,
+    ) -> Result<bool, String> {
+        let (Body::Binary { reader, big_endian }, Some(size)) = (self, element.record_size()) else {
+            return Ok(false);
+        };
+        let mut record = vec![0u8; size];
+        let mut values = vec![0f64; element.properties.len()];
+        for i in 0..element.count {
+            reader.read_exact(&mut record)
+                .map_err(|e| format!("Failed to read {} {} of {}: {}", element.name, i, element.count, e))?;
+            let mut offset = 0;
+            for (value, property) in values.iter_mut().zip(&element.properties) {
+                if let Some(kind) = property.scalar_type() {
+                    *value = kind.decode(&record[offset..], *big_endian);
+                    offset += kind.size();
+                }
+            }
+            each(&values)?;
+        }
+        Ok(true)
+    }
+
+    fn read_element(
+        &mut self,
+        element: &PlyElement,
+        mut each: impl FnMut(&[f64]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        if self.read_fixed(element, &mut each)? {
+            return Ok(());
+        }
+        let mut values = vec![0f64; element.properties.len()];
+        for _ in 0..element.count {
+            self.read_record(element, &mut values)?;
+            each(&values)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn read_ply(path: &str, progress: &LoadProgress, chunks: &mut Chunks<'_>) -> Result<PointCloud, String> {
+    let mut reader = progress.open(path)?;
+
+    let header = parse_header(&mut reader)?;
+    let vertex = header.elements.iter()
+        .position(|e| e.name == "vertex")
+        .ok_or("PLY file has no vertex element")?;
+    let layout = VertexLayout::new(&header.elements[vertex])?;
+
+    let mut body = match header.format {
+        PlyFormat::Ascii => Body::Ascii(reader.lines()),
+        PlyFormat::BinaryLittleEndian => Body::Binary { reader, big_endian: false },
+        PlyFormat::BinaryBigEndian => Body::Binary { reader, big_endian: true },
+    };
+
+    // Elements before the vertices still have to be read past
+    for element in &header.elements[..vertex] {
+        body.read_element(element, |_| Ok(()))?;
+    }
+
+    let element = &header.elements[vertex];
+    // Every property takes at least a byte, binary or ASCII
+    let capacity = chunks.capacity(progress.capacity(element.count, element.properties.len()));
+    let mut cloud = PointCloud::with_capacity(capacity);
+    cloud.attributes = layout.extra.iter()
+        .map(|&i| Attribute::new(VertexLayout::attribute_name(&element.properties[i]), capacity))
+        .collect();
+
+    let mut first = true;
+    body.read_element(element, |values| {
+        if first {
+            layout.set_offsets(&mut cloud, values);
+            first = false;
+        }
+        layout.push(&mut cloud, values);
+        if chunks.full(&cloud) {
+            chunks.flush(&mut cloud)?;
+        }
+        Ok(())
+    })?;
+
+    Ok(cloud)
+}
+
+/// Reads the header line by line, leaving `reader` at the first byte of the body.
+pub fn parse_header<R: BufRead>(reader: &mut R) -> Result<PlyHeader, String> {
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    let mut comments = Vec::new();
+    let mut line = String::new();
+    let mut first = true;
+
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).map_err(|e| format!("Failed to read header: {}", e))?;
+        if read == 0 {
+            return Err("Unexpected end of file in PLY header".to_string());
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+
+        if first {
+            if parts.first() != Some(&"ply") {
+                return Err("Not a PLY file".to_string());
+            }
+            first = false;
+            continue;
+        }
+
+        let mut add_property = |property: PlyProperty| -> Result<(), String> {
+            elements.last_mut()
+                .ok_or("PLY property declared before any element")?
+                .properties.push(property);
+            Ok(())
+        };
+
+        match parts.as_slice() {
+            ["format", kind, _version] => {
+                format = Some(match *kind {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::BinaryLittleEndian,
+                    "binary_big_endian" => PlyFormat::BinaryBigEndian,
+                    _ => return Err(format!("Unknown PLY format '{}'", kind)),
+                });
+            }
+            ["element", name, count] => {
+                elements.push(PlyElement {
+                    name: name.to_string(),
+                    count: count.parse().map_err(|_| format!("Invalid count for element '{}'", name))?,
+                    properties: Vec::new(),
+                });
+            }
+            ["property", "list", count, item, name] => add_property(PlyProperty::List {
+                name: name.to_string(),
+                count: ScalarType::parse(count)?,
+                item: ScalarType::parse(item)?,
+            })?,
+            ["property", kind, name] => add_property(PlyProperty::Scalar {
+                name: name.to_string(),
+                kind: ScalarType::parse(kind)?,
+            })?,
+            ["comment", ..] => comments.push(line.trim()["comment".len()..].trim().to_string()),
+            ["end_header"] => break,
+            // obj_info and blank lines
+            _ => {}
+        }
+    }
+
+    Ok(PlyHeader {
+        format: format.ok_or("PLY header has no format line")?,
+        elements,
+        comments,
+    })
+}
+
+/// Property names for the attributes: anything but letters, digits and underscores becomes
+/// an underscore, so the header stays parsable, and clashes get a numbered suffix.
+fn property_names(cloud: &PointCloud) -> Vec<String> {
+    let mut taken: Vec<String> = ["x", "y", "z", "red", "green", "blue", "alpha"].map(String::from).to_vec();
+    let mut names = Vec::with_capacity(cloud.attributes.len());
+    for attribute in &cloud.attributes {
+        let mut base: String = attribute.name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+            .collect();
+        if base.is_empty() {
+            base = "attribute".to_string();
+        }
+        let mut name = base.clone();
+        let mut suffix = 2;
+        while taken.contains(&name) {
+            name = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+        taken.push(name.clone());
+        names.push(name);
+    }
+    names
+}
+
+/// Writes positions as doubles, colours as uchar and every attribute as a float property,
+/// or a double one if the attribute has an offset.
+pub fn write_ply(path: &str, cloud: &PointCloud, format: PlyFormat) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("Failed to create file: {}", e))?;
+    let mut writer = BufWriter::new(file);
+    let io_err = |e: std::io::Error| format!("Failed to write PLY: {}", e);
+
+    // Alpha is only written when it carries information
+    let has_alpha = cloud.colors.iter().any(|c| c.a() != 255);
+
+    let format_name = match format {
+        PlyFormat::Ascii => "ascii",
+        PlyFormat::BinaryLittleEndian => "binary_little_endian",
+        PlyFormat::BinaryBigEndian => "binary_big_endian",
+    };
+    let mut header = format!("ply\nformat {} 1.0\ncomment written by rushroom\nelement vertex {}\n", format_name, cloud.len());
+    header += "property double x\nproperty double y\nproperty double z\n";
+    header += "property uchar red\nproperty uchar green\nproperty uchar blue\n";
+    if has_alpha {
+        header += "property uchar alpha\n";
+    }
+    for (attribute, name) in cloud.attributes.iter().zip(property_names(cloud)) {
+        let kind = if attribute.offset != 0.0 { "double" } else { "float" };
+        header += &format!("property {} {}\n", kind, name);
+    }
+    header += "end_header\n";
+    writer.write_all(header.as_bytes()).map_err(io_err)?;
+
+    for (i, color) in cloud.colors.iter().enumerate() {
+        let position = cloud.position(i);
+        let [r, g, b, a] = color.to_array();
+        let rgba: &[u8] = if has_alpha { &[r, g, b, a] } else { &[r, g, b] };
+
+        if format == PlyFormat::Ascii {
+            let mut line = format!("{} {} {}", position[0], position[1], position[2]);
+            for channel in rgba {
+                line += &format!(" {}", channel);
+            }
+            for attribute in &cloud.attributes {
+                if attribute.offset != 0.0 {
+                    line += &format!(" {}", attribute.get(i));
+                } else {
+                    line += &format!(" {}", attribute.values[i]);
+                }
+            }
+            line.push('\n');
+            writer.write_all(line.as_bytes()).map_err(io_err)?;
+        } else {
+            let big_endian = format == PlyFormat::BinaryBigEndian;
+            let mut record: Vec<u8> = Vec::with_capacity(28 + 4 * cloud.attributes.len());
+            for v in position {
+                record.extend_from_slice(&if big_endian { v.to_be_bytes() } else { v.to_le_bytes() });
+            }
+            record.extend_from_slice(rgba);
+            for attribute in &cloud.attributes {
+                if attribute.offset != 0.0 {
+                    let v = attribute.get(i);
+                    record.extend_from_slice(&if big_endian { v.to_be_bytes() } else { v.to_le_bytes() });
+                } else {
+                    let v = attribute.values[i];
+                    record.extend_from_slice(&if big_endian { v.to_be_bytes() } else { v.to_le_bytes() });
+                }
+            }
+            writer.write_all(&record).map_err(io_err)?;
+        }
+    }
+
+    writer.flush().map_err(io_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("rushroom-ply-{}-{}.ply", std::process::id(), name));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn read(path: &str) -> Result<PointCloud, String> {
+        let read = read_ply(path, &LoadProgress::default(), &mut Chunks::whole());
+        std::fs::remove_file(path).unwrap();
+        read
+    }
+
+    fn values(cloud: &PointCloud, name: &str) -> Vec<f64> {
+        let attribute = cloud.attribute(name).unwrap();
+        (0..cloud.len()).map(|i| attribute.get(i)).collect()
+    }
+
+    /// Encodes `value` as the given PLY type, the inverse of `ScalarType::decode`.
+    fn encode(kind: ScalarType, value: f64, big_endian: bool) -> Vec<u8> {
+        macro_rules! encode {
+            ($t:ty) => {{
+                let v = value as $t;
+                if big_endian { v.to_be_bytes().to_vec() } else { v.to_le_bytes().to_vec() }
+            }};
+        }
+        match kind {
+            ScalarType::Char => encode!(i8),
+            ScalarType::UChar => encode!(u8),
+            ScalarType::Short => encode!(i16),
+            ScalarType::UShort => encode!(u16),
+            ScalarType::Int => encode!(i32),
+            ScalarType::UInt => encode!(u32),
+            ScalarType::Float => encode!(f32),
+            ScalarType::Double => encode!(f64),
+        }
+    }
+
+    /// A binary PLY with one vertex element of `properties` (type name, property name),
+    /// holding `rows` in declared order.
+    fn binary_file(name: &str, big_endian: bool, properties: &[(&str, &str)], rows: &[&[f64]]) -> String {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut bytes = format!("ply\nformat {} 1.0\nelement vertex {}\n", format, rows.len()).into_bytes();
+        for (kind, property) in properties {
+            bytes.extend(format!("property {} {}\n", kind, property).bytes());
+        }
+        bytes.extend(b"end_header\n");
+        for row in rows {
+            for ((kind, _), &value) in properties.iter().zip(row.iter()) {
+                bytes.extend(encode(ScalarType::parse(kind).unwrap(), value, big_endian));
+            }
+        }
+        let path = temp_path(name);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn binary_reads_every_scalar_type_in_declared_order() {
+        // Positions and colours are interleaved with the extras, and every type and alias appears
+        let properties = [
+            ("char", "c"),
+            ("float64", "z"),
+            ("uint16", "us"),
+            ("uchar", "red"),
+            ("short", "s"),
+            ("double", "x"),
+            ("uint8", "green"),
+            ("int32", "i"),
+            ("uchar", "blue"),
+            ("uint", "u"),
+            ("float", "y"),
+            ("int8", "c8"),
+            ("float32", "f"),
+        ];
+        let rows: [&[f64]; 2] = [
+            &[-5.0, 3.25, 60000.0, 255.0, -300.0, 1.5, 128.0, -70000.0, 0.0, 3e9, -2.5, 127.0, 0.125],
+            &[127.0, -1.0, 0.0, 1.0, 32767.0, -8.0, 2.0, -2147483648.0, 3.0, 0.0, 4.0, -128.0, -1e6],
+        ];
+
+        for big_endian in [false, true] {
+            let name = if big_endian { "types-be" } else { "types-le" };
+            let cloud = read(&binary_file(name, big_endian, &properties, &rows)).unwrap();
+
+            assert_eq!(cloud.len(), 2);
+            assert_eq!(cloud.position(0), [1.5, -2.5, 3.25]);
+            assert_eq!(cloud.position(1), [-8.0, 4.0, -1.0]);
+            assert_eq!(cloud.colors, [Color32::from_rgb(255, 128, 0), Color32::from_rgb(1, 2, 3)]);
+
+            let names: Vec<&str> = cloud.attributes.iter().map(|a| a.name.as_str()).collect();
+            assert_eq!(names, ["c", "us", "s", "i", "u", "c8", "f"]);
+            assert_eq!(values(&cloud, "c"), [-5.0, 127.0]);
+            assert_eq!(values(&cloud, "us"), [60000.0, 0.0]);
+            assert_eq!(values(&cloud, "s"), [-300.0, 32767.0]);
+            assert_eq!(values(&cloud, "i"), [-70000.0, -2147483648.0]);
+            assert_eq!(values(&cloud, "u"), [3e9, 0.0]);
+            assert_eq!(values(&cloud, "c8"), [127.0, -128.0]);
+            assert_eq!(values(&cloud, "f"), [0.125, -1e6]);
+        }
+    }
+
+    #[test]
+    fn binary_skips_elements_before_the_vertices() {
+        let mut bytes = b"ply\nformat binary_big_endian 1.0\nelement face 1\nproperty list uchar int vertex_indices\n\
+            element vertex 1\nproperty float x\nproperty float y\nproperty float z\nend_header\n".to_vec();
+        bytes.push(3);
+        for index in [0i32, 1, 2] {
+            bytes.extend(index.to_be_bytes());
+        }
+        for v in [1f32, 2.0, 3.0] {
+            bytes.extend(v.to_be_bytes());
+        }
+        let path = temp_path("face-first");
+        std::fs::write(&path, bytes).unwrap();
+
+        let cloud = read(&path).unwrap();
+        assert_eq!(cloud.len(), 1);
+        assert_eq!(cloud.position(0), [1.0, 2.0, 3.0]);
+        assert_eq!(cloud.colors, [Color32::WHITE]);
+    }
+
+    #[test]
+    fn float_colours_are_scaled_and_alpha_is_kept_straight() {
+        let properties = [
+            ("float", "x"), ("float", "y"), ("float", "z"),
+            ("float", "red"), ("float", "green"), ("float", "blue"), ("float", "alpha"),
+        ];
+        let rows: [&[f64]; 2] = [
+            &[0.0, 0.0, 0.0, 1.0, 0.5, 0.0, 0.2],
+            &[0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 0.0],
+        ];
+        let cloud = read(&binary_file("alpha", false, &properties, &rows)).unwrap();
+
+        // Translucent and fully transparent points keep their RGB
+        assert_eq!(cloud.colors[0].to_array(), [255, 127, 0, 51]);
+        assert_eq!(cloud.colors[1].to_array(), [255, 255, 255, 0]);
+    }
+
+    #[test]
+    fn double_properties_keep_their_precision() {
+        let properties = [("float", "x"), ("float", "y"), ("float", "z"), ("double", "gps_time"), ("float", "f")];
+        let rows: [&[f64]; 3] = [
+            &[0.0, 0.0, 0.0, 450_000_000.125, 1.0],
+            &[0.0, 0.0, 0.0, 450_000_001.5, 2.0],
+            &[0.0, 0.0, 0.0, 449_999_999.875, 3.0],
+        ];
+        let cloud = read(&binary_file("double", false, &properties, &rows)).unwrap();
+
+        // As f32 these would all round to 450000000
+        assert_eq!(values(&cloud, "gps_time"), [450_000_000.125, 450_000_001.5, 449_999_999.875]);
+        assert_eq!(cloud.attribute("f").unwrap().offset, 0.0);
+
+        // And they are written back as doubles
+        let path = temp_path("double-written");
+        write_ply(&path, &cloud, PlyFormat::Ascii).unwrap();
+        let header = parse_header(&mut std::io::BufReader::new(File::open(&path).unwrap())).unwrap();
+        let kinds: Vec<_> = header.elements[0].properties.iter().map(|p| (p.name(), p.scalar_type())).collect();
+        assert!(kinds.contains(&("gps_time", Some(ScalarType::Double))));
+        assert!(kinds.contains(&("f", Some(ScalarType::Float))));
+        assert_eq!(values(&read(&path).unwrap(), "gps_time"), values(&cloud, "gps_time"));
+    }
+
+    #[test]
+    fn double_offsets_survive_chunked_reads() {
+        let properties = [("float", "x"), ("float", "y"), ("float", "z"), ("double", "gps_time")];
+        let rows: Vec<[f64; 4]> = (0..10).map(|i| [0.0, 0.0, 0.0, 450_000_000.0 + i as f64 * 0.25]).collect();
+        let rows: Vec<&[f64]> = rows.iter().map(|r| &r[..]).collect();
+        let path = binary_file("double-chunks", true, &properties, &rows);
+
+        let mut times = Vec::new();
+        let mut each = |chunk: PointCloud| {
+            times.extend(values(&chunk, "gps_time"));
+            Ok(())
+        };
+        let rest = read_ply(&path, &LoadProgress::default(), &mut Chunks::new(3, &mut each)).unwrap();
+        times.extend(values(&rest, "gps_time"));
+        std::fs::remove_file(&path).unwrap();
+
+        let expected: Vec<f64> = (0..10).map(|i| 450_000_000.0 + i as f64 * 0.25).collect();
+        assert_eq!(times, expected);
+    }
+
+    /// A georeferenced cloud with translucent colours, normals and both kinds of attribute.
+    fn sample_cloud() -> PointCloud {
+        let mut cloud = PointCloud::default();
+        let mut attributes = ["nx", "ny", "nz", "intensity", "gps_time"].map(|name| Attribute::new(name, 4));
+        attributes[4].offset = 450_000_000.5;
+        for i in 0..4 {
+            let t = i as f32;
+            cloud.push(
+                [512_000.25 + t as f64, 6_123_000.25 - t as f64 * 0.125, 87.75 + t as f64 * 10.0],
+                Color32::from_rgba_premultiplied(200, 10 * i as u8, 255, 255 - 85 * i as u8),
+            );
+            for (attribute, value) in attributes.iter_mut().zip([t.cos(), t.sin(), 0.0, 1000.0 + t, t * 0.25]) {
+                attribute.values.push(value);
+            }
+        }
+        cloud.attributes = attributes.into();
+        cloud
+    }
+
+    #[test]
+    fn written_files_read_back_unchanged() {
+        let cloud = sample_cloud();
+        for format in PlyFormat::ALL {
+            let path = temp_path(&format!("round-trip-{:?}", format));
+            write_ply(&path, &cloud, format).unwrap();
+            let read = read(&path).unwrap();
+
+            assert_eq!(read.origin, [512_000.0, 6_123_000.0, 88.0], "{:?}", format);
+            assert_eq!(read, cloud, "{:?}", format);
+            assert!(read.normals().is_some(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn opaque_clouds_are_written_without_alpha() {
+        let mut cloud = sample_cloud();
+        for color in &mut cloud.colors {
+            *color = Color32::from_rgb(color.r(), color.g(), color.b());
+        }
+        let path = temp_path("opaque");
+        write_ply(&path, &cloud, PlyFormat::BinaryLittleEndian).unwrap();
+
+        let header = parse_header(&mut std::io::BufReader::new(File::open(&path).unwrap())).unwrap();
+        assert!(header.elements[0].properties.iter().all(|p| p.name() != "alpha"));
+        assert_eq!(read(&path).unwrap(), cloud);
+    }
+
+    #[test]
+    fn attribute_names_are_made_valid_and_unique() {
+        let mut cloud = PointCloud::default();
+        cloud.push([0.0; 3], Color32::WHITE);
+        for name in ["return number", "return_number", ""] {
+            let mut attribute = Attribute::new(name, 1);
+            attribute.values.push(1.0);
+            cloud.attributes.push(attribute);
+        }
+        let path = temp_path("names");
+        write_ply(&path, &cloud, PlyFormat::Ascii).unwrap();
+
+        let read = read(&path).unwrap();
+        let names: Vec<&str> = read.attributes.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["return_number", "return_number_2", "attribute"]);
+    }
+
+    #[test]
+    fn a_short_binary_body_is_an_error() {
+        let properties = [("float", "x"), ("float", "y"), ("float", "z")];
+        let path = binary_file("short", false, &properties, &[&[1.0, 2.0, 3.0]]);
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes.truncate(bytes.len() - 2);
+        std::fs::write(&path, bytes).unwrap();
+
+        assert!(read(&path).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Shared between a loader and whoever started it: the loader reports bytes read,
+/// the other side can watch the fraction and ask it to stop.
+#[derive(Clone, Debug, Default)]
+pub struct LoadProgress {
+    read: Arc<AtomicU64>,
+    total: Arc<AtomicU64>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl LoadProgress {
+    /// How far through the file the reader is, 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        let total = self.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        (self.read.load(Ordering::Relaxed) as f64 / total as f64).min(1.0) as f32
+    }
+
+    pub fn bytes(&self) -> (u64, u64) {
+        (self.read.load(Ordering::Relaxed), self.total.load(Ordering::Relaxed))
+    }
+
+    /// Makes the next read fail, which unwinds the reader with an error.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Sets the progress directly, for work other than reading a file.
+    pub(crate) fn report(&self, done: u64, total: u64) {
+        self.read.store(done, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    /// An error once cancelled, for work that does not read through [`Self::open`].
+    pub(crate) fn check(&self) -> Result<(), String> {
+        if self.is_cancelled() {
+            return Err("cancelled".to_string());
+        }
+        Ok(())
+    }
+
+    /// `count` clamped to how many records of at least `record_size` bytes the rest of the
+    /// file can hold, so a header claiming too many cannot make a reader allocate them up front.
+    pub(crate) fn capacity(&self, count: usize, record_size: usize) -> usize {
+        let remaining = self.total.load(Ordering::Relaxed).saturating_sub(self.read.load(Ordering::Relaxed));
+        count.min((remaining / record_size.max(1) as u64).try_into().unwrap_or(usize::MAX))
+    }
+
+    /// Opens `path` for a reader, counting every byte read against the file size.
+    pub(crate) fn open(&self, path: &str) -> Result<BufReader<ProgressFile>, String> {
+        let file = File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        self.read.store(0, Ordering::Relaxed);
+        self.total.store(len, Ordering::Relaxed);
+        Ok(BufReader::new(ProgressFile { file, progress: self.clone() }))
+    }
+}
+
+pub(crate) struct ProgressFile {
+    file: File,
+    progress: LoadProgress,
+}
+
+impl Read for ProgressFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.progress.is_cancelled() {
+            // Not `Interrupted`, which `read_exact` and friends would retry
+            return Err(io::Error::other("loading cancelled"));
+        }
+        let n = self.file.read(buf)?;
+        self.progress.read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl Seek for ProgressFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.file.seek(pos)?;
+        self.progress.read.store(position, Ordering::Relaxed);
+        Ok(position)
+    }
+}
@@ -0,0 +1,163 @@
+use egui::Color32;
+
+/// Colour maps for scalar fields, each a list of evenly spaced stops.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ColorRamp {
+    #[default]
+    Viridis,
+    Inferno,
+    Jet,
+    Greyscale,
+    CoolWarm,
+}
+
+/// Texels per ramp in the renderer's lookup texture.
+pub const RAMP_SAMPLES: usize = 256;
+
+impl ColorRamp {
+    pub const ALL: [ColorRamp; 5] = [
+        ColorRamp::Viridis,
+        ColorRamp::Inferno,
+        ColorRamp::Jet,
+        ColorRamp::Greyscale,
+        ColorRamp::CoolWarm,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorRamp::Viridis => "Viridis",
+            ColorRamp::Inferno => "Inferno",
+            ColorRamp::Jet => "Jet",
+            ColorRamp::Greyscale => "Greyscale",
+            ColorRamp::CoolWarm => "Cool to warm",
+        }
+    }
+
+    fn stops(&self) -> &'static [[u8; 3]] {
+        match self {
+            ColorRamp::Viridis => &[
+                [68, 1, 84], [71, 44, 122], [59, 81, 139], [44, 113, 142], [33, 144, 141],
+                [39, 173, 129], [92, 200, 99], [170, 220, 50], [253, 231, 37],
+            ],
+            ColorRamp::Inferno => &[
+                [0, 0, 4], [31, 12, 72], [85, 15, 109], [136, 34, 106], [186, 54, 85],
+                [227, 89, 51], [249, 140, 10], [249, 201, 50], [252, 255, 164],
+            ],
+            ColorRamp::Jet => &[
+                [0, 0, 128], [0, 0, 255], [0, 128, 255], [0, 255, 255], [128, 255, 128],
+                [255, 255, 0], [255, 128, 0], [255, 0, 0], [128, 0, 0],
+            ],
+            ColorRamp::Greyscale => &[[0, 0, 0], [255, 255, 255]],
+            ColorRamp::CoolWarm => &[[59, 76, 192], [221, 221, 221], [180, 4, 38]],
+        }
+    }
+
+    /// Colour at `t`, clamped to 0-1.
+    pub fn sample(&self, t: f32) -> Color32 {
+        let stops = self.stops();
+        let x = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (x as usize).min(stops.len() - 2);
+        let f = x - i as f32;
+        let [r, g, b] = std::array::from_fn(|c| {
+            (stops[i][c] as f32 + (stops[i + 1][c] as f32 - stops[i][c] as f32) * f).round() as u8
+        });
+        Color32::from_rgb(r, g, b)
+    }
+
+    /// Row of this ramp in the texture built by [`ramp_texture`].
+    pub fn row(&self) -> usize {
+        ColorRamp::ALL.iter().position(|ramp| ramp == self).unwrap()
+    }
+}
+
+/// RGBA texels for every ramp, one row of `RAMP_SAMPLES` each, in the order of `ColorRamp::ALL`.
+pub fn ramp_texture() -> Vec<u8> {
+    let mut texels = Vec::with_capacity(ColorRamp::ALL.len() * RAMP_SAMPLES * 4);
+    for ramp in ColorRamp::ALL {
+        for i in 0..RAMP_SAMPLES {
+            texels.extend_from_slice(&ramp.sample(i as f32 / (RAMP_SAMPLES - 1) as f32).to_array());
+        }
+    }
+    texels
+}
+
+const HISTOGRAM_BINS: usize = 1024;
+
+/// Range covering the values between the `low` and `high` quantiles, so a handful of
+/// outliers do not squash the ramp. `None` when there are no finite values.
+pub fn histogram_range(values: &[f64], low: f64, high: f64) -> Option<[f64; 2]> {
+    let values: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if values.is_empty() {
+        return None;
+    }
+    let last = values.len() - 1;
+    let rank = |q: f64| ((q * values.len() as f64) as usize).min(last);
+    let (low, high) = (rank(low), rank(high));
+    Some([quantile(values.clone(), low), quantile(values, high)])
+}
+
+/// The `rank`-th smallest value. Each round bins the candidates into a histogram and
+/// keeps only the bin holding the rank, until few enough are left to sort.
+fn quantile(mut values: Vec<f64>, mut rank: usize) -> f64 {
+    loop {
+        let (min, max) = values.iter().fold((f64::MAX, f64::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        if values.len() <= HISTOGRAM_BINS || min == max {
+            values.sort_unstable_by(f64::total_cmp);
+            return values[rank];
+        }
+
+        let width = (max - min) / HISTOGRAM_BINS as f64;
+        let bin = |v: f64| (((v - min) / width) as usize).min(HISTOGRAM_BINS - 1);
+        let mut bins = [0usize; HISTOGRAM_BINS];
+        for &v in &values {
+            bins[bin(v)] += 1;
+        }
+        let mut below = 0;
+        let mut chosen = HISTOGRAM_BINS - 1;
+        for (i, &count) in bins.iter().enumerate() {
+            if below + count > rank {
+                chosen = i;
+                break;
+            }
+            below += count;
+        }
+        rank -= below;
+        values.retain(|&v| bin(v) == chosen);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_finite_values_has_no_range() {
+        assert_eq!(histogram_range(&[], 0.0, 1.0), None);
+        assert_eq!(histogram_range(&[f64::NAN, f64::INFINITY, f64::NEG_INFINITY], 0.0, 1.0), None);
+    }
+
+    #[test]
+    fn constant_values_give_an_empty_range() {
+        assert_eq!(histogram_range(&[4.5; 5000], 0.02, 0.98), Some([4.5, 4.5]));
+        assert_eq!(histogram_range(&[-1.0], 0.0, 1.0), Some([-1.0, -1.0]));
+    }
+
+    #[test]
+    fn non_finite_values_are_ignored() {
+        let values = [f64::NAN, 3.0, f64::INFINITY, 1.0, 2.0, f64::NEG_INFINITY];
+        assert_eq!(histogram_range(&values, 0.0, 1.0), Some([1.0, 3.0]));
+    }
+
+    #[test]
+    fn quantiles_leave_outliers_out() {
+        // More values than histogram bins, spread unevenly, so the binning has to narrow down
+        let mut values: Vec<f64> = (0..10_000).map(|i| (i as f64).powi(2) / 1000.0).collect();
+        values.extend([1e12, -1e12]);
+        let mut sorted = values.clone();
+        sorted.sort_unstable_by(f64::total_cmp);
+        let rank = |q: f64| sorted[((q * sorted.len() as f64) as usize).min(sorted.len() - 1)];
+
+        assert_eq!(histogram_range(&values, 0.02, 0.98), Some([rank(0.02), rank(0.98)]));
+        assert_eq!(histogram_range(&values, 0.0, 1.0), Some([-1e12, 1e12]));
+    }
+}
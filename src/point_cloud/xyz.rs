@@ -0,0 +1,259 @@
+use egui::Color32;
+use std::io::BufRead;
+use crate::point_cloud::{Attribute, Chunks, LoadProgress, PointCloud};
+
+/// Which columns of an XYZ/CSV file hold what. Indices are zero based.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct XyzColumns {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    /// Red, green and blue columns, 0-255 (or 0-1 if every value is at most 1).
+    pub color: Option<[usize; 3]>,
+    /// `None` splits on commas, semicolons, tabs and spaces.
+    pub delimiter: Option<char>,
+    /// Lines to skip before the data, not counting a detected header row.
+    pub skip_lines: usize,
+}
+
+impl Default for XyzColumns {
+    fn default() -> Self {
+        Self {
+            x: 0,
+            y: 1,
+            z: 2,
+            color: None,
+            delimiter: None,
+            skip_lines: 0,
+        }
+    }
+}
+
+impl XyzColumns {
+    fn split<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        match self.delimiter {
+            Some(d) => line.split(d).map(str::trim).collect(),
+            None => line.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Column editor shown in load dialogs and on the Load Point Cloud node.
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new(ui.id().with("xyz_columns")).show(ui, |ui| {
+            ui.label("X / Y / Z");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.x));
+                ui.add(egui::DragValue::new(&mut self.y));
+                ui.add(egui::DragValue::new(&mut self.z));
+            });
+            ui.end_row();
+
+            let mut has_color = self.color.is_some();
+            ui.checkbox(&mut has_color, "R / G / B");
+            let mut color = self.color.unwrap_or([3, 4, 5]);
+            ui.add_enabled_ui(has_color, |ui| {
+                ui.horizontal(|ui| {
+                    for c in &mut color {
+                        ui.add(egui::DragValue::new(c));
+                    }
+                });
+            });
+            self.color = has_color.then_some(color);
+            ui.end_row();
+
+            ui.label("Delimiter");
+            let mut delimiter = self.delimiter.map(String::from).unwrap_or_default();
+            if ui.add(egui::TextEdit::singleline(&mut delimiter).desired_width(20.).hint_text("auto")).changed() {
+                self.delimiter = delimiter.chars().next();
+            }
+            ui.end_row();
+
+            ui.label("Skip lines");
+            ui.add(egui::DragValue::new(&mut self.skip_lines));
+            ui.end_row();
+        });
+    }
+}
+
+/// Every column that is not position or colour becomes an attribute, named from the
+/// header row if the file has one.
+pub(crate) fn read_xyz(
+    path: &str,
+    columns: &XyzColumns,
+    progress: &LoadProgress,
+    chunks: &mut Chunks<'_>,
+) -> Result<PointCloud, String> {
+    let reader = progress.open(path)?;
+
+    let mut cloud = PointCloud::default();
+    let mut rgb: Vec<[f32; 3]> = Vec::new();
+    let mut names: Option<Vec<String>> = None;
+    let mut extra: Vec<usize> = Vec::new();
+    let mut started = false;
+    let mut scale = None;
+
+    let used: Vec<usize> = [columns.x, columns.y, columns.z].into_iter()
+        .chain(columns.color.into_iter().flatten())
+        .collect();
+
+    for (line_number, line) in reader.lines().enumerate().skip(columns.skip_lines) {
+        let line = line.map_err(|e| format!("Failed to read line: {}", e))?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with("//") {
+            continue;
+        }
+        let parts = columns.split(trimmed);
+        let values: Vec<Option<f64>> = parts.iter().map(|p| p.parse::<f64>().ok()).collect();
+
+        // The first row is a header if anything in it is not a number
+        if !started && names.is_none() && values.iter().any(Option::is_none) {
+            names = Some(parts.iter().map(|s| s.trim_matches('"').to_string()).collect());
+            continue;
+        }
+
+        if !started {
+            started = true;
+            extra = (0..values.len()).filter(|i| !used.contains(i)).collect();
+            cloud.attributes = extra.iter()
+                .map(|&i| Attribute::new(
+                    names.as_ref()
+                        .and_then(|n| n.get(i).cloned())
+                        .unwrap_or_else(|| format!("column {}", i)),
+                    0,
+                ))
+                .collect();
+        }
+
+        let get = |i: usize| values.get(i).copied().flatten()
+            .ok_or_else(|| format!("Line {}: missing or invalid column {}", line_number + 1, i));
+
+        let position = [get(columns.x)?, get(columns.y)?, get(columns.z)?];
+        cloud.push(position, Color32::WHITE);
+
+        if let Some([r, g, b]) = columns.color {
+            rgb.push([get(r)? as f32, get(g)? as f32, get(b)? as f32]);
+        }
+        for (attribute, &i) in cloud.attributes.iter_mut().zip(&extra) {
+            attribute.values.push(values.get(i).copied().flatten().unwrap_or(f64::NAN) as f32);
+        }
+        if chunks.full(&cloud) {
+            apply_rgb(&mut cloud, &mut rgb, &mut scale);
+            chunks.flush(&mut cloud)?;
+        }
+    }
+
+    apply_rgb(&mut cloud, &mut rgb, &mut scale);
+    Ok(cloud)
+}
+
+/// Colours the points from their RGB columns, which are 0-1 if every value read so far is.
+/// The scale is settled by the first call, so later chunks are coloured the same way.
+fn apply_rgb(cloud: &mut PointCloud, rgb: &mut Vec<[f32; 3]>, scale: &mut Option<f32>) {
+    if rgb.is_empty() {
+        return;
+    }
+    let scale = *scale.get_or_insert_with(|| if rgb.iter().flatten().all(|&c| c <= 1.0) { 255.0 } else { 1.0 });
+    for (color, [r, g, b]) in cloud.colors.iter_mut().zip(rgb.drain(..)) {
+        let channel = |c: f32| (c * scale).clamp(0.0, 255.0) as u8;
+        *color = Color32::from_rgb(channel(r), channel(g), channel(b));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(name: &str, contents: &str, columns: &XyzColumns) -> Result<PointCloud, String> {
+        let path = std::env::temp_dir().join(format!("rushroom-xyz-{}-{}.txt", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        let read = read_xyz(path.to_str().unwrap(), columns, &LoadProgress::default(), &mut Chunks::whole());
+        std::fs::remove_file(&path).unwrap();
+        read
+    }
+
+    fn names(cloud: &PointCloud) -> Vec<&str> {
+        cloud.attributes.iter().map(|a| a.name.as_str()).collect()
+    }
+
+    #[test]
+    fn any_common_delimiter_is_detected() {
+        let contents = "1,2,3,10\n4;5;6;11\n7\t8\t9\t12\n  10   11 12  13\n0.5, 1.5 ,2.5\t14\n";
+        let cloud = read("delimiters", contents, &XyzColumns::default()).unwrap();
+
+        assert_eq!(cloud.len(), 5);
+        assert_eq!(cloud.position(1), [4.0, 5.0, 6.0]);
+        assert_eq!(cloud.position(3), [10.0, 11.0, 12.0]);
+        assert_eq!(cloud.position(4), [0.5, 1.5, 2.5]);
+        assert_eq!(names(&cloud), ["column 3"]);
+        assert_eq!(cloud.attributes[0].values, [10.0, 11.0, 12.0, 13.0, 14.0]);
+    }
+
+    #[test]
+    fn a_header_row_names_the_attributes() {
+        let contents = "# exported points\n\"X\",\"Y\",\"Z\",\"Intensity\",\"Class\"\n1,2,3,100,2\n// note\n\n4,5,6,200,6\n";
+        let cloud = read("header", contents, &XyzColumns::default()).unwrap();
+
+        assert_eq!(cloud.len(), 2);
+        assert_eq!(names(&cloud), ["Intensity", "Class"]);
+        assert_eq!(cloud.attributes[1].values, [2.0, 6.0]);
+    }
+
+    #[test]
+    fn an_explicit_delimiter_keeps_empty_fields() {
+        let columns = XyzColumns { delimiter: Some('|'), ..Default::default() };
+        let cloud = read("explicit", "1 | 2 | 3 | 4\n5|6|7|\n", &columns).unwrap();
+
+        assert_eq!(cloud.position(0), [1.0, 2.0, 3.0]);
+        assert_eq!(cloud.position(1), [5.0, 6.0, 7.0]);
+        assert_eq!(cloud.attributes[0].values[0], 4.0);
+        assert!(cloud.attributes[0].values[1].is_nan());
+    }
+
+    #[test]
+    fn columns_can_be_rearranged_and_lines_skipped() {
+        let columns = XyzColumns { x: 3, y: 1, z: 0, color: Some([4, 5, 6]), skip_lines: 2, ..Default::default() };
+        let contents = "written by a scanner\n42 points\n3 2 7 1 255 128 0\n6 5 8 4 0 0 255\n";
+        let cloud = read("columns", contents, &columns).unwrap();
+
+        assert_eq!(cloud.position(0), [1.0, 2.0, 3.0]);
+        assert_eq!(cloud.position(1), [4.0, 5.0, 6.0]);
+        assert_eq!(cloud.colors, [Color32::from_rgb(255, 128, 0), Color32::from_rgb(0, 0, 255)]);
+        assert_eq!(names(&cloud), ["column 2"]);
+        assert_eq!(cloud.attributes[0].values, [7.0, 8.0]);
+    }
+
+    #[test]
+    fn unit_colours_are_scaled_to_bytes() {
+        let columns = XyzColumns { color: Some([3, 4, 5]), ..Default::default() };
+        let cloud = read("unit-colours", "0 0 0 1 0.5 0\n0 0 0 0 0 1\n", &columns).unwrap();
+        assert_eq!(cloud.colors, [Color32::from_rgb(255, 127, 0), Color32::from_rgb(0, 0, 255)]);
+    }
+
+    #[test]
+    fn the_colour_scale_is_settled_by_the_first_chunk() {
+        let columns = XyzColumns { color: Some([3, 4, 5]), ..Default::default() };
+        let path = std::env::temp_dir().join(format!("rushroom-xyz-{}-chunked.txt", std::process::id()));
+        std::fs::write(&path, "0 0 0 1 1 1\n0 0 0 0 1 0\n0 0 0 200 0 0\n").unwrap();
+
+        let mut colors = Vec::new();
+        let mut each = |chunk: PointCloud| {
+            colors.extend(chunk.colors);
+            Ok(())
+        };
+        let rest = read_xyz(path.to_str().unwrap(), &columns, &LoadProgress::default(), &mut Chunks::new(2, &mut each)).unwrap();
+        colors.extend(rest.colors);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(colors, [Color32::WHITE, Color32::GREEN, Color32::from_rgb(255, 0, 0)]);
+    }
+
+    #[test]
+    fn a_missing_column_names_its_line() {
+        assert_eq!(
+            read("missing", "1 2 3\n4 5\n", &XyzColumns::default()),
+            Err("Line 2: missing or invalid column 2".to_string()),
+        );
+    }
+}